@@ -1,15 +1,36 @@
-use std::ops::Add;
+use core::ops::Add;
+use alloc::{format, vec, vec::Vec, string::String};
 use thiserror::Error;
-use crate::model::{entity::Member, condition::Score};
+use crate::model::{entity::{Id, Member}, group::Table, condition::Score};
+use crate::cache::TableCache;
 
 pub type Index = usize;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Position {
     pub group_index: Index,
     pub member_index: Index,
 }
 
+impl Position {
+    /// Unchecked constructor for hot loops that already know the position is valid.
+    pub fn new(group_index: Index, member_index: Index) -> Position {
+        Position { group_index, member_index }
+    }
+
+    /// Builds a position after checking that it refers to a member of `cache` in its current shape.
+    pub fn new_checked(cache: &TableCache, group_index: Index, member_index: Index) -> Result<Position, ActionError> {
+        let sizes = cache.sizes();
+        let Some(group_size) = sizes.get(group_index).copied() else {
+            return Err(ActionError::GroupOutOfRange { group_index, group_count: sizes.len() });
+        };
+        if member_index >= group_size {
+            return Err(ActionError::MemberOutOfRange { group_index, member_index, group_size });
+        }
+        Ok(Position { group_index, member_index })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum GroupAction {
     Add(Member),
@@ -23,12 +44,99 @@ pub enum Action {
     Move { source_position: Position, target_group: Index },
     Add{ member: Member, group_index: Index },
     Remove(Position),
+    CreateGroup,
+    RemoveEmptyGroup(Index),
+    /// Moves every member at `.0` to group `.1` in a single step. All positions refer to the
+    /// table before the batch, and each may appear at most once.
+    MoveBatch(Vec<(Position, Index)>),
+    /// Changes nothing and scores 0; a reference point for measuring the cost of the loop.
+    Noop,
+    /// Puts `member`, who is not in the table, at `position` in place of its current member
+    /// (scored like `GroupAction::Replace`). `TableCache::act` returns the member taken out.
+    Replace { position: Position, member: Member },
+}
+
+impl Action {
+    /// Total order used wherever one of several equally scored actions has to be picked:
+    /// actions compare by variant (in declaration order), then by the group and member indices
+    /// they touch, in the order they appear in the variant, then by the id of a member entering
+    /// the table. The smallest key wins.
+    pub fn tie_break_key(&self) -> (u8, Vec<Index>, Option<Id>) {
+        match self {
+            Action::Swap(position1, position2) => (0, vec![
+                position1.group_index, position1.member_index, position2.group_index, position2.member_index,
+            ], None),
+            Action::Move { source_position, target_group } => (1, vec![
+                source_position.group_index, source_position.member_index, *target_group,
+            ], None),
+            Action::Add { member, group_index } => (2, vec![*group_index], Some(member.id)),
+            Action::Remove(position) => (3, vec![position.group_index, position.member_index], None),
+            Action::CreateGroup => (4, Vec::new(), None),
+            Action::RemoveEmptyGroup(group_index) => (5, vec![*group_index], None),
+            Action::MoveBatch(moves) => (6, moves.iter()
+                .flat_map(|(position, target_group)| [position.group_index, position.member_index, *target_group])
+                .collect(), None),
+            Action::Noop => (7, Vec::new(), None),
+            Action::Replace { position, member } => (8, vec![position.group_index, position.member_index], Some(member.id)),
+        }
+    }
+
+    /// Human-readable description of the action on `table`, naming members by id and groups by
+    /// `Table::group_name`, e.g. "Swap member 3 (group 0) with member 12 (group 2)" or
+    /// "Move member 5 from VIP to Window". Positions that do not refer to a member of `table`
+    /// are rendered as "missing member <index>".
+    pub fn describe(&self, table: &Table) -> String {
+        let member_at = |position: &Position| {
+            table.groups.get(position.group_index)
+                .and_then(|group| group.members.get(position.member_index))
+                .map_or_else(|| format!("missing member {}", position.member_index), |member| format!("member {}", member.id))
+        };
+        let group = |group_index: Index| table.group_name(group_index);
+        let moved = |position: &Position, target_group: &Index| {
+            format!("{} from {} to {}", member_at(position), group(position.group_index), group(*target_group))
+        };
+        match self {
+            Action::Swap(position1, position2) => format!(
+                "Swap {} ({}) with {} ({})",
+                member_at(position1), group(position1.group_index), member_at(position2), group(position2.group_index),
+            ),
+            Action::Move { source_position, target_group } => format!("Move {}", moved(source_position, target_group)),
+            Action::Add { member, group_index } => format!("Add member {} to {}", member.id, group(*group_index)),
+            Action::Remove(position) => format!("Remove {} from {}", member_at(position), group(position.group_index)),
+            Action::CreateGroup => format!("Create group {}", table.group_count()),
+            Action::RemoveEmptyGroup(group_index) => format!("Remove empty {}", group(*group_index)),
+            Action::MoveBatch(moves) => format!(
+                "Move {}",
+                moves.iter().map(|(position, target_group)| moved(position, target_group)).collect::<Vec<_>>().join(", "),
+            ),
+            Action::Noop => String::from("Do nothing"),
+            Action::Replace { position, member } => format!(
+                "Replace {} in {} with member {}", member_at(position), group(position.group_index), member.id,
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Error, PartialEq)]
 pub enum ActionError {
     #[error("Invalid position")]
     InvalidPosition,
+    #[error("Group is not empty")]
+    GroupNotEmpty,
+    #[error("Constraint violated")]
+    ConstraintViolated,
+    #[error("Group {group_index} out of range of {group_count} groups")]
+    GroupOutOfRange { group_index: Index, group_count: usize },
+    #[error("Member {member_index} out of range of group {group_index} with {group_size} members")]
+    MemberOutOfRange { group_index: Index, member_index: Index, group_size: usize },
+    #[error("Member {0} is already in the group")]
+    DuplicateId(Id),
+    #[error("Score overflow")]
+    ScoreOverflow,
+    #[error("Member {0} is pinned to its group")]
+    Pinned(Id),
+    #[error("Member {id} may not return to group {group_index} yet")]
+    Tabu { id: Id, group_index: Index },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -38,6 +146,24 @@ pub enum ActionResult {
     Failed(Vec<ActionError>),
 }
 
+impl ActionResult {
+    /// Score change of the action, whether or not it satisfies the constraints.
+    pub fn score_delta(&self) -> Option<Score> {
+        match self {
+            ActionResult::ScoreDiff(score) | ActionResult::UnsatisfiedScoreDiff(score) => Some(*score),
+            ActionResult::Failed(_) => None,
+        }
+    }
+
+    pub fn is_feasible(&self) -> bool {
+        matches!(self, ActionResult::ScoreDiff(_))
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self, ActionResult::Failed(_))
+    }
+}
+
 impl Add for ActionResult {
     type Output = Self;
 
@@ -50,8 +176,113 @@ impl Add for ActionResult {
             (ActionResult::UnsatisfiedScoreDiff(s1), ActionResult::UnsatisfiedScoreDiff(s2))
                 => ActionResult::UnsatisfiedScoreDiff(s1 + s2),
             (ActionResult::Failed(err1), ActionResult::Failed(err2))
-                => ActionResult::Failed(err1.into_iter().chain(err2.into_iter()).cloned().collect()),
+                => ActionResult::Failed(err1.iter().chain(err2.iter()).cloned().collect()),
             _ => rhs + self
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_diff_accessors() {
+        let result = ActionResult::ScoreDiff(-2 as Score);
+        assert_eq!(result.score_delta(), Some(-2 as Score));
+        assert!(result.is_feasible());
+        assert!(!result.is_failed());
+    }
+
+    #[test]
+    fn test_unsatisfied_score_diff_accessors() {
+        let result = ActionResult::UnsatisfiedScoreDiff(3 as Score);
+        assert_eq!(result.score_delta(), Some(3 as Score));
+        assert!(!result.is_feasible());
+        assert!(!result.is_failed());
+    }
+
+    #[test]
+    fn test_failed_accessors() {
+        let result = ActionResult::Failed(vec![ActionError::InvalidPosition]);
+        assert_eq!(result.score_delta(), None);
+        assert!(!result.is_feasible());
+        assert!(result.is_failed());
+    }
+
+    #[test]
+    fn test_tie_break_key() {
+        let swap = |g1, m1, g2, m2| Action::Swap(
+            Position { group_index: g1, member_index: m1 },
+            Position { group_index: g2, member_index: m2 },
+        );
+        assert!(swap(0, 1, 1, 0).tie_break_key() < swap(0, 2, 1, 0).tie_break_key());
+        assert!(swap(0, 2, 1, 0).tie_break_key() < swap(1, 0, 0, 0).tie_break_key());
+        let moved = Action::Move { source_position: Position { group_index: 0, member_index: 0 }, target_group: 1 };
+        assert!(swap(2, 2, 2, 2).tie_break_key() < moved.tie_break_key());
+        let add = |id| Action::Add { member: Member { id, ..Default::default() }, group_index: 0 };
+        assert!(add(3).tie_break_key() < add(4).tie_break_key());
+    }
+
+    #[test]
+    fn test_new_checked_position() {
+        use crate::model::group::{Group, Table};
+        use crate::model::condition::RelationPenalty;
+        let table = Table { groups: vec![
+            Group { members: vec![Member::new(0), Member::new(1)] },
+            Group { members: vec![Member::new(2)] },
+        ], ..Default::default() };
+        let cache = TableCache::create(&table, &RelationPenalty::new(0 as Score));
+        assert_eq!(Position::new_checked(&cache, 1, 0), Ok(Position::new(1, 0)));
+        assert_eq!(
+            Position::new_checked(&cache, 2, 0),
+            Err(ActionError::GroupOutOfRange { group_index: 2, group_count: 2 })
+        );
+        assert_eq!(
+            Position::new_checked(&cache, 1, 1),
+            Err(ActionError::MemberOutOfRange { group_index: 1, member_index: 1, group_size: 1 })
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        use crate::model::group::Group;
+        let table = Table { groups: vec![
+            Group { members: vec![Member::new(3), Member::new(5)] },
+            Group { members: vec![Member::new(12)] },
+        ], ..Default::default() };
+        let describe = |action: Action| action.describe(&table);
+        assert_eq!(describe(Action::Swap(Position::new(0, 0), Position::new(1, 0))), "Swap member 3 (group 0) with member 12 (group 1)");
+        assert_eq!(
+            describe(Action::Move { source_position: Position::new(0, 1), target_group: 1 }),
+            "Move member 5 from group 0 to group 1",
+        );
+        assert_eq!(describe(Action::Add { member: Member::new(7), group_index: 1 }), "Add member 7 to group 1");
+        assert_eq!(describe(Action::Remove(Position::new(1, 0))), "Remove member 12 from group 1");
+        assert_eq!(describe(Action::Remove(Position::new(1, 4))), "Remove missing member 4 from group 1");
+        assert_eq!(describe(Action::CreateGroup), "Create group 2");
+        assert_eq!(describe(Action::RemoveEmptyGroup(1)), "Remove empty group 1");
+        assert_eq!(
+            describe(Action::MoveBatch(vec![(Position::new(0, 0), 1), (Position::new(1, 0), 0)])),
+            "Move member 3 from group 0 to group 1, member 12 from group 1 to group 0",
+        );
+        assert_eq!(describe(Action::Noop), "Do nothing");
+        assert_eq!(
+            describe(Action::Replace { position: Position::new(1, 0), member: Member::new(7) }),
+            "Replace member 12 in group 1 with member 7",
+        );
+    }
+
+    #[test]
+    fn test_describe_uses_labels() {
+        use alloc::string::ToString;
+        use crate::model::group::Group;
+        let table = Table {
+            groups: vec![Group { members: vec![Member::new(5)] }, Group { members: Vec::new() }],
+            labels: Some(vec!["VIP".to_string(), "Window".to_string()]),
+        };
+        let action = Action::Move { source_position: Position::new(0, 0), target_group: 1 };
+        assert_eq!(action.describe(&table), "Move member 5 from VIP to Window");
+    }
+}
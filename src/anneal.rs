@@ -1,38 +1,311 @@
-use std::{collections::{HashMap, HashSet}, hash::Hash, mem};
+use alloc::{vec, vec::Vec, boxed::Box, sync::Arc};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
 
-use rand::prelude::{SliceRandom};
-use rand::rngs::{SmallRng};
-use itertools::Itertools;
+use rand::prelude::{SliceRandom, Rng, RngCore};
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use thiserror::Error;
 
-use crate::model::entity::{Id, Tag, Member};
-use crate::model::group::{Group, Table};
-use crate::model::condition::{RelationPenalty, Constraint, Condition, Score};
-use crate::action::{Action, GroupAction, Position, ActionResult, ActionError, Index};
+use crate::model::entity::{Id, Member, Tag};
+use crate::model::group::{check_unique_ids, Table};
+use crate::model::condition::{Condition, Constraint, Score};
+use crate::action::{Action, Position, ActionResult, ActionError, Index};
 use crate::cache::TableCache;
+use crate::assign::{greedy_assign, random_assignment};
+use crate::validate::{validate, Infeasibility};
+use crate::error::ShuffleError;
+use crate::collections::{BTreeSet, VecDeque};
+use crate::float;
 
+#[derive(Debug, Clone)]
+pub struct Params {
+    pub temperature: f64,
+    pub cooling_rate: f64,
+    pub max_iterations: usize,
+    /// Number of iterations a member is forbidden to return to a group it just left.
+    /// `None` disables the tabu list.
+    pub tabu_tenure: Option<usize>,
+    /// Upper bound on group sizes. When set, the number of groups becomes part of the search:
+    /// groups are created and removed so that as few groups as possible respect the bound.
+    pub max_group_size: Option<usize>,
+    /// Seed of every random number generator used by the run; equal seeds give equal results.
+    pub seed: u64,
+    /// When set, swaps are biased towards groups violating their constraints and are uniformly
+    /// random only with this probability, clamped to `[0, 1]` (NaN counts as 0). Ignored when
+    /// `max_group_size` is set.
+    /// Without either, optional members in the table select actions that drop and re-add them,
+    /// and otherwise a positive `Condition::size_balance` selects moves that rebalance group sizes.
+    pub guided_exploration: Option<f64>,
+    /// When set, `cooling_rate` is ignored and the temperature is steered so that about this
+    /// fraction of proposals is accepted (see `AdaptiveCooling`).
+    pub target_acceptance: Option<f64>,
+    /// Number of actions drawn and simulated per iteration; the best of them is put to the
+    /// acceptance test. 1 is plain annealing, larger values mix in steepest descent.
+    pub candidates_per_step: usize,
+    /// When set, the run stops early once a window of iterations improves the best score too
+    /// little (see `Convergence`).
+    pub convergence: Option<Convergence>,
+    /// Rejects every action that `simulate` reports as unsatisfied, exactly like a failed one,
+    /// so that a feasible start stays feasible in every intermediate and final table. From an
+    /// infeasible start, only actions that leave all affected groups feasible are accepted.
+    pub strict_feasible: bool,
+    /// Sorts the members of every group of the returned table by id (see
+    /// `Table::sort_members_by_id`). Swaps and moves reorder members within groups, so without it
+    /// runs reaching the same membership may still list members differently.
+    pub canonicalize: bool,
+    /// Checked before every iteration: once another thread sets it, the run stops as if
+    /// `max_iterations` were reached, and the entry points return the best table seen so far.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Divides every score change by the magnitude of the starting score before the acceptance
+    /// test, so that `temperature` is relative to the problem: a temperature of 1.0 accepts a
+    /// worsening by the whole starting score with probability 1/e, however penalties are
+    /// scaled. A starting score of 0 leaves changes as they are.
+    pub normalize_acceptance: bool,
+}
 
-struct Params {
-    temperature: f64,
-    cooling_rate: f64,
-    max_iterations: usize,
+impl Params {
+    /// Checks the settings that the annealer cannot run with.
+    pub fn check(&self) -> Result<(), ParamsError> {
+        if let Some(target) = self.target_acceptance.filter(|target| !(0.0..=1.0).contains(target)) {
+            return Err(ParamsError::TargetAcceptance(target));
+        }
+        if self.max_group_size == Some(0) {
+            return Err(ParamsError::ZeroMaxGroupSize);
+        }
+        Ok(())
+    }
+}
+
+/// A setting of `Params`, or a count passed along with them, that a run cannot use.
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ParamsError {
+    #[error("target acceptance {0} is not a probability")]
+    TargetAcceptance(f64),
+    #[error("the maximum group size must be positive")]
+    ZeroMaxGroupSize,
+    #[error("at least one restart is needed")]
+    NoRestarts,
+}
+
+/// Stops an `Annealer` when the best score improved by less than `min_relative_improvement`
+/// of its magnitude over the last `window` iterations. A window in which the best table becomes
+/// feasible always counts as progress.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Convergence {
+    pub window: usize,
+    pub min_relative_improvement: f64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            temperature: 1.0,
+            cooling_rate: 0.999,
+            max_iterations: 10000,
+            tabu_tenure: None,
+            max_group_size: None,
+            seed: 0,
+            guided_exploration: None,
+            target_acceptance: None,
+            candidates_per_step: 1,
+            convergence: None,
+            strict_feasible: false,
+            canonicalize: false,
+            cancel: None,
+            normalize_acceptance: false,
+        }
+    }
+}
+
+/// Decides the temperature of the next iteration from the current one and whether the last
+/// proposal was accepted.
+pub trait CoolingSchedule {
+    fn next_temperature(&mut self, temperature: f64, accepted: bool) -> f64;
+}
+
+/// Multiplies the temperature by a fixed `rate` every iteration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeometricCooling {
+    pub rate: f64,
+}
+
+impl CoolingSchedule for GeometricCooling {
+    fn next_temperature(&mut self, temperature: f64, _accepted: bool) -> f64 {
+        temperature * self.rate
+    }
+}
+
+/// Measures the acceptance rate over windows of `window` iterations and, at the end of each
+/// window, raises the temperature when too few proposals were accepted and lowers it when too
+/// many were. The change per window is at most a factor of `1 + max_adjustment` either way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveCooling {
+    pub target: f64,
+    pub window: usize,
+    pub max_adjustment: f64,
+    steps: usize,
+    accepted: usize,
+}
+
+const ADAPTIVE_WINDOW: usize = 100;
+const ADAPTIVE_MAX_ADJUSTMENT: f64 = 0.5;
+
+impl AdaptiveCooling {
+    pub fn new(target: f64, window: usize, max_adjustment: f64) -> AdaptiveCooling {
+        assert!((0.0..=1.0).contains(&target) && window > 0 && max_adjustment >= 0.0);
+        AdaptiveCooling { target, window, max_adjustment, steps: 0, accepted: 0 }
+    }
 }
 
+impl CoolingSchedule for AdaptiveCooling {
+    fn next_temperature(&mut self, temperature: f64, accepted: bool) -> f64 {
+        self.steps += 1;
+        self.accepted += usize::from(accepted);
+        if self.steps < self.window {
+            return temperature;
+        }
+        let rate = self.accepted as f64 / self.steps as f64;
+        self.steps = 0;
+        self.accepted = 0;
+        // Normalized so that the worst possible miss in either direction saturates the adjustment.
+        let error = ((self.target - rate) / self.target.max(1.0 - self.target)).clamp(-1.0, 1.0);
+        temperature * float::powf(1.0 + self.max_adjustment, error)
+    }
+}
+
+/// In debug builds, the incremental score is checked against a full recomputation this often;
+/// drift is corrected and reported through `AnnealStep::resynced`.
+const RESYNC_INTERVAL: usize = 1000;
+const RESYNC_EPSILON: Score = 1e-6;
+
 struct State {
-    table: Table,
+    cache: TableCache,
+    score: Score,
     n_iterations: usize,
     temperature: f64,
 }
 
-struct SwapGenerator {
+/// Fixed-tenure buffer of `(member id, group index)` transitions that must not be undone yet.
+#[derive(Debug, Clone)]
+struct TabuList {
+    tenure: usize,
+    entries: VecDeque<(Id, Index, usize)>,  // member id, left group, iteration
+}
+
+impl TabuList {
+    fn new(tenure: usize) -> TabuList {
+        TabuList { tenure, entries: VecDeque::new() }
+    }
+
+    /// Evicts the oldest entries whose tenure has run out at `iteration`.
+    fn expire(&mut self, iteration: usize) {
+        while let Some((_, _, recorded)) = self.entries.front() {
+            if recorded + self.tenure > iteration {
+                break;
+            }
+            self.entries.pop_front();
+        }
+    }
+
+    fn contains(&self, id: Id, group_index: Index) -> bool {
+        self.entries.iter().any(|(tabu_id, tabu_group, _)| *tabu_id == id && *tabu_group == group_index)
+    }
+
+    /// Lists the `(member id, source group, target group)` transitions an action would perform.
+    fn transitions(action: &Action, cache: &TableCache) -> Vec<(Id, Index, Index)> {
+        match action {
+            Action::Swap(position1, position2) => {
+                match (cache.get_member(position1), cache.get_member(position2)) {
+                    (Some(member1), Some(member2)) => vec![
+                        (member1.id, position1.group_index, position2.group_index),
+                        (member2.id, position2.group_index, position1.group_index),
+                    ],
+                    _ => Vec::new(),
+                }
+            }
+            Action::Move { source_position, target_group } => {
+                match cache.get_member(source_position) {
+                    Some(member) => vec![(member.id, source_position.group_index, *target_group)],
+                    None => Vec::new(),
+                }
+            }
+            Action::MoveBatch(moves) => {
+                moves.iter()
+                    .filter_map(|(position, target_group)| {
+                        cache.get_member(position).map(|member| (member.id, position.group_index, *target_group))
+                    })
+                    .collect()
+            }
+            Action::Add { .. } | Action::Remove(_) | Action::Replace { .. } | Action::CreateGroup
+                | Action::RemoveEmptyGroup(_) | Action::Noop => Vec::new(),
+        }
+    }
+
+    /// Fails with the first transition of `action` that the list forbids.
+    fn check(&self, action: &Action, cache: &TableCache) -> Result<(), ActionError> {
+        match TabuList::transitions(action, cache).into_iter().find(|(id, _, target)| self.contains(*id, *target)) {
+            Some((id, _, group_index)) => Err(ActionError::Tabu { id, group_index }),
+            None => Ok(()),
+        }
+    }
+
+    /// Forgets a removed group and shifts the indices of the groups after it.
+    fn remove_group(&mut self, group_index: Index) {
+        self.entries.retain(|(_, tabu_group, _)| *tabu_group != group_index);
+        for (_, tabu_group, _) in self.entries.iter_mut() {
+            if *tabu_group > group_index {
+                *tabu_group -= 1;
+            }
+        }
+    }
+
+    /// Records the groups left by the members of `action`. Must be called before the action is applied.
+    fn record(&mut self, action: &Action, cache: &TableCache, iteration: usize) {
+        for (id, source, _) in TabuList::transitions(action, cache) {
+            self.entries.push_back((id, source, iteration));
+        }
+    }
+}
+
+/// Proposes the actions an `Annealer` tries.
+///
+/// Every random decision is drawn from the `rng` passed in, which the annealer owns, so
+/// generators can be swapped mid-run without restarting the random sequence. Generators that
+/// need the condition, e.g. to target violating groups, keep a reference to it.
+pub trait ActionGenerator {
+    /// Next action to try on `cache`, or `None` when there is nothing to propose.
+    fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action>;
+}
+
+/// Draws actions from `generator` until one is not forbidden by `tabu`.
+/// Gives up with `None` after as many rejections as there are members.
+fn next_allowed(
+    generator: &mut dyn ActionGenerator, cache: &TableCache, rng: &mut SmallRng, tabu: Option<&TabuList>,
+) -> Option<Action> {
+    let max_attempts = cache.sizes().iter().sum::<usize>().max(1);
+    for _ in 0..max_attempts {
+        let action = generator.next(cache, rng)?;
+        if tabu.is_none_or(|tabu| tabu.check(&action, cache).is_ok()) {
+            return Some(action);
+        }
+    }
+    None
+}
+
+/// Swaps members of distinct groups, walking through a shuffled list of all positions so that
+/// every member is proposed once per pass. The default neighborhood; it keeps group sizes.
+pub struct SwapGenerator {
     sizes: Vec<Index>,
     candidates: Vec<Position>,
-    rng: SmallRng,
 }
 
 impl SwapGenerator {
-    fn init(&mut self) {
-        assert!(self.sizes.len() > 1);
-        assert!(self.sizes.iter().all(|size| *size > 0));
+    pub fn new() -> SwapGenerator {
+        SwapGenerator { sizes: Vec::new(), candidates: Vec::new() }
+    }
+
+    fn init(&mut self, rng: &mut SmallRng) {
         self.candidates = self.sizes
             .iter().enumerate()
             .flat_map(
@@ -41,23 +314,1537 @@ impl SwapGenerator {
                 }
             )
             .collect();
+        self.candidates.shuffle(rng);
     }
-    fn next(&mut self) -> Action {
+}
+
+impl Default for SwapGenerator {
+    fn default() -> Self {
+        SwapGenerator::new()
+    }
+}
+
+impl ActionGenerator for SwapGenerator {
+    fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action> {
+        let sizes = cache.sizes();
+        if sizes != self.sizes {
+            // Another generator changed the table, so the remaining positions may be stale.
+            self.sizes = sizes;
+            self.candidates.clear();
+        }
+        if self.sizes.iter().filter(|size| **size > 0).count() < 2 {
+            return None;
+        }
         let pos1 = match self.candidates.pop() {
             Some(pos) => pos,
             None => {
-                self.init();
-                return self.next()
+                self.init(rng);
+                self.candidates.pop().unwrap()
             }
         };
         loop {
             let pos2 = self.candidates.pop().unwrap_or_else(|| {
-                self.init();
+                self.init(rng);
                 self.candidates.pop().unwrap()
             });
             if pos1.group_index != pos2.group_index {
-                return Action::Swap(pos1, pos2)
+                return Some(Action::Swap(pos1, pos2))
+            }
+        }
+    }
+}
+
+/// Moves a random member to a random other group. Broader than swaps, since group sizes change;
+/// useful early in a run (see `Annealer::set_generator`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MoveGenerator;
+
+impl ActionGenerator for MoveGenerator {
+    fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action> {
+        let sizes = cache.sizes();
+        let nonempty: Vec<Index> = (0..sizes.len()).filter(|index| sizes[*index] > 0).collect();
+        if sizes.len() < 2 {
+            return None;
+        }
+        let source = *nonempty.choose(rng)?;
+        let target = (source + rng.gen_range(1..sizes.len())) % sizes.len();
+        Some(Action::Move { source_position: random_position(rng, &sizes, source), target_group: target })
+    }
+}
+
+/// Delegates every proposal to one of several generators, picked at random in proportion to
+/// its weight, e.g. mostly swaps with an occasional move.
+pub struct MixedGenerator<'a> {
+    generators: Vec<(Box<dyn ActionGenerator + 'a>, f64)>,
+}
+
+impl<'a> MixedGenerator<'a> {
+    pub fn new(generators: Vec<(Box<dyn ActionGenerator + 'a>, f64)>) -> MixedGenerator<'a> {
+        assert!(generators.iter().all(|(_, weight)| *weight >= 0.0));
+        assert!(generators.iter().any(|(_, weight)| *weight > 0.0));
+        MixedGenerator { generators }
+    }
+}
+
+impl ActionGenerator for MixedGenerator<'_> {
+    fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action> {
+        let total: f64 = self.generators.iter().map(|(_, weight)| weight).sum();
+        let mut draw = rng.gen::<f64>() * total;
+        let chosen = self.generators.iter().position(|(_, weight)| {
+            draw -= weight;
+            draw < 0.0
+        }).unwrap_or(self.generators.len() - 1);
+        self.generators[chosen].0.next(cache, rng)
+    }
+}
+
+/// Splits up the highest-penalty pair of the table (see `TableCache::score_breakdown`): one of
+/// its two members, picked at random, is swapped with a random member of another group or moved
+/// there. With probability `exploration`, or when no pair has a positive penalty, it proposes a
+/// random move instead, so that the search does not cycle around the same pair.
+pub struct WorstPairGenerator<'a> {
+    exploration: f64,
+    condition: &'a Condition,
+}
+
+impl<'a> WorstPairGenerator<'a> {
+    pub fn new(exploration: f64, condition: &'a Condition) -> WorstPairGenerator<'a> {
+        assert!((0.0..=1.0).contains(&exploration));
+        WorstPairGenerator { exploration, condition }
+    }
+
+    /// Group and ids of the highest-penalty pair; ties go to the lowest group index.
+    fn worst_pair(&self, cache: &TableCache) -> Option<(Index, [Id; 2])> {
+        let mut worst: Option<(Index, [Id; 2], Score)> = None;
+        for group_index in 0..cache.sizes().len() {
+            let breakdown = cache.score_breakdown(group_index, &self.condition.penalty)?;
+            if let Some((ids, score)) = breakdown.first() {
+                if *score > 0 as Score && worst.as_ref().is_none_or(|(_, _, worst_score)| score > worst_score) {
+                    worst = Some((group_index, *ids, *score));
+                }
+            }
+        }
+        worst.map(|(group_index, ids, _)| (group_index, ids))
+    }
+}
+
+impl ActionGenerator for WorstPairGenerator<'_> {
+    fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action> {
+        let sizes = cache.sizes();
+        if sizes.len() < 2 || rng.gen_bool(self.exploration) {
+            return MoveGenerator.next(cache, rng);
+        }
+        let Some((group_index, ids)) = self.worst_pair(cache) else {
+            return MoveGenerator.next(cache, rng);
+        };
+        let id = *ids.choose(rng)?;
+        let member_index = (0..sizes[group_index])
+            .position(|member_index| cache.get_member(&Position::new(group_index, member_index)).is_some_and(|member| member.id == id))?;
+        let source_position = Position { group_index, member_index };
+        let target_group = (group_index + rng.gen_range(1..sizes.len())) % sizes.len();
+        if sizes[target_group] > 0 && rng.gen_bool(0.5) {
+            Some(Action::Swap(source_position, random_position(rng, &sizes, target_group)))
+        } else {
+            Some(Action::Move { source_position, target_group })
+        }
+    }
+}
+
+fn random_position(rng: &mut SmallRng, sizes: &[usize], group_index: Index) -> Position {
+    Position { group_index, member_index: rng.gen_range(0..sizes[group_index]) }
+}
+
+/// Swaps two random members of two distinct non-empty groups.
+fn random_swap(rng: &mut SmallRng, sizes: &[usize]) -> Option<Action> {
+    let nonempty: Vec<Index> = (0..sizes.len()).filter(|index| sizes[*index] > 0).collect();
+    if nonempty.len() < 2 {
+        return None;
+    }
+    let groups: Vec<Index> = nonempty.choose_multiple(rng, 2).copied().collect();
+    Some(Action::Swap(random_position(rng, sizes, groups[0]), random_position(rng, sizes, groups[1])))
+}
+
+/// Swap generator biased towards groups that currently violate their constraints: with
+/// probability `1 - exploration` one side of the swap lies in such a group, otherwise
+/// (or when every group is feasible) the swap is uniformly random.
+struct GuidedSwapGenerator<'a> {
+    exploration: f64,
+    condition: &'a Condition,
+}
+
+impl<'a> GuidedSwapGenerator<'a> {
+    /// Clamps `exploration` into `[0, 1]`, mapping NaN to 0, so that it is always a probability.
+    fn new(exploration: f64, condition: &'a Condition) -> GuidedSwapGenerator<'a> {
+        let exploration = if exploration.is_nan() { 0.0 } else { exploration.clamp(0.0, 1.0) };
+        GuidedSwapGenerator { exploration, condition }
+    }
+}
+
+impl ActionGenerator for GuidedSwapGenerator<'_> {
+    fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action> {
+        let condition = self.condition;
+        let sizes = cache.sizes();
+        let violating: Vec<Index> = (0..sizes.len())
+            .filter(|index| sizes[*index] > 0 && !cache.is_group_feasible(*index, condition))
+            .collect();
+        if violating.is_empty() || rng.gen_bool(self.exploration) {
+            return random_swap(rng, &sizes);
+        }
+        let group_index = *violating.choose(rng)?;
+        let others: Vec<Index> = (0..sizes.len())
+            .filter(|index| *index != group_index && sizes[*index] > 0)
+            .collect();
+        let other_index = *others.choose(rng)?;
+        Some(Action::Swap(
+            random_position(rng, &sizes, group_index),
+            random_position(rng, &sizes, other_index),
+        ))
+    }
+}
+
+/// Proposes actions that keep every group within `max_size` members using as few groups as possible:
+/// it relieves oversized groups (opening a new group when every group is full), drains the smallest
+/// group while the others can absorb its members, and removes groups once they are empty.
+/// When there is no size pressure it falls back to random swaps.
+struct ResizeGenerator<'a> {
+    max_size: usize,
+    condition: &'a Condition,
+}
+
+impl<'a> ResizeGenerator<'a> {
+    fn new(max_size: usize, condition: &'a Condition) -> ResizeGenerator<'a> {
+        assert!(max_size > 0);
+        ResizeGenerator { max_size, condition }
+    }
+
+    fn random_move(rng: &mut SmallRng, sizes: &[usize], source: Index, target: Index) -> Action {
+        let member_index = rng.gen_range(0..sizes[source]);
+        Action::Move { source_position: Position { group_index: source, member_index }, target_group: target }
+    }
+}
+
+impl ActionGenerator for ResizeGenerator<'_> {
+    fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action> {
+        let sizes = cache.sizes();
+        let condition = self.condition;
+        // The bound of a group is the tighter of `max_size` and its capacity in `condition`.
+        let limit = |group_index: Index| condition.max_group_size(group_index).map_or(self.max_size, |max| max.min(self.max_size));
+        let oversized = (0..sizes.len())
+            .filter(|index| sizes[*index] > limit(*index))
+            .max_by_key(|index| sizes[*index]);
+        if let Some(oversized) = oversized {
+            let target = (0..sizes.len())
+                .filter(|index| sizes[*index] < limit(*index))
+                .min_by_key(|index| sizes[*index]);
+            return match target {
+                Some(target) => Some(ResizeGenerator::random_move(rng, &sizes, oversized, target)),
+                None => Some(Action::CreateGroup),
+            };
+        }
+        if let Some(empty) = sizes.iter().position(|size| *size == 0) {
+            return Some(Action::RemoveEmptyGroup(empty));
+        }
+        let (smallest, smallest_size) = sizes.iter().copied().enumerate().min_by_key(|(_, size)| *size)?;
+        let room: usize = (0..sizes.len())
+            .filter(|index| *index != smallest)
+            .map(|index| limit(index).saturating_sub(sizes[index]))
+            .sum();
+        if smallest_size <= room && rng.gen_bool(0.5) {
+            let targets: Vec<Index> = (0..sizes.len())
+                .filter(|index| *index != smallest && sizes[*index] < limit(*index))
+                .collect();
+            if let Some(target) = targets.choose(rng) {
+                return Some(ResizeGenerator::random_move(rng, &sizes, smallest, *target));
+            }
+        }
+        random_swap(rng, &sizes)
+    }
+}
+
+/// Used when the size-balance objective is enabled: half of the time, while group sizes differ by
+/// more than one, moves a random member from a largest to a smallest group; otherwise proposes a
+/// random swap, so that balance is traded against the penalty by the acceptance rule.
+struct RebalanceGenerator;
+
+impl ActionGenerator for RebalanceGenerator {
+    fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action> {
+        let sizes = cache.sizes();
+        let (largest, largest_size) = sizes.iter().copied().enumerate().max_by_key(|(_, size)| *size)?;
+        let (smallest, smallest_size) = sizes.iter().copied().enumerate().min_by_key(|(_, size)| *size)?;
+        if largest_size > smallest_size + 1 && rng.gen_bool(0.5) {
+            let member_index = rng.gen_range(0..largest_size);
+            return Some(Action::Move { source_position: Position { group_index: largest, member_index }, target_group: smallest });
+        }
+        random_swap(rng, &sizes)
+    }
+}
+
+/// Used when the table has optional members: with probability `ATTENDANCE_RATE` takes a random
+/// optional member out of the table, or puts a random unassigned one back into a random group;
+/// otherwise proposes a random swap.
+struct AttendanceGenerator;
+
+const ATTENDANCE_RATE: f64 = 0.2;
+
+impl ActionGenerator for AttendanceGenerator {
+    fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action> {
+        let sizes = cache.sizes();
+        if !sizes.is_empty() && rng.gen_bool(ATTENDANCE_RATE) {
+            if !cache.unassigned().is_empty() && rng.gen_bool(0.5) {
+                let member = cache.unassigned().choose(rng)?.clone();
+                return Some(Action::Add { member, group_index: rng.gen_range(0..sizes.len()) });
+            }
+            let optional: Vec<Position> = sizes.iter().enumerate()
+                .flat_map(|(group_index, size)| (0..*size).map(move |member_index| Position { group_index, member_index }))
+                .filter(|position| cache.get_member(position).is_some_and(|member| member.optional))
+                .collect();
+            if let Some(position) = optional.choose(rng) {
+                return Some(Action::Remove(position.clone()));
+            }
+        }
+        random_swap(rng, &sizes)
+    }
+}
+
+/// What an `Annealer` minimizes (see `Annealer::set_objective`).
+#[derive(Default)]
+pub enum Objective<'a> {
+    /// `TableCache::effective_score` under the condition, updated incrementally.
+    #[default]
+    Effective,
+    /// Any score of the whole table, e.g. the number of groups with more than three engineers.
+    /// There is no incremental update: every candidate action is applied to a copy of the
+    /// cache and scored from scratch, so a step costs a full evaluation plus copying the groups
+    /// the action touches. Failures and feasibility are still decided by `simulate`.
+    Custom(Box<dyn Fn(&TableCache) -> Score + 'a>),
+}
+
+/// `result` with its score change replaced by `delta`; failures stay failures.
+fn with_delta(result: &ActionResult, delta: Score) -> ActionResult {
+    match result {
+        ActionResult::ScoreDiff(_) => ActionResult::ScoreDiff(delta),
+        ActionResult::UnsatisfiedScoreDiff(_) => ActionResult::UnsatisfiedScoreDiff(delta),
+        ActionResult::Failed(errors) => ActionResult::Failed(errors.clone()),
+    }
+}
+
+fn accepts(result: &ActionResult, temperature: f64, rng: &mut SmallRng) -> bool {
+    match result.score_delta() {
+        Some(diff) => diff <= 0.0 || rng.gen::<f64>() < float::exp(-diff / temperature),
+        None => false,
+    }
+}
+
+/// Outcome of a single proposal/acceptance cycle of an `Annealer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnealStep {
+    pub iteration: usize,
+    /// Score of the current table after the step, under the annealer's objective.
+    pub score: Score,
+    /// Score of the best table seen so far.
+    pub best_score: Score,
+    pub temperature: f64,
+    pub accepted: bool,
+    /// Share of `max_iterations` done after the step, reaching 1.0 at the last iteration.
+    pub progress: f64,
+    /// Rough time left until `max_iterations`, from a moving average of the wall time per
+    /// iteration. The clock is only read every 64 iterations, so this is `None` for the first
+    /// 63 steps, and always without `std`, which has no clock.
+    pub eta: Option<Duration>,
+    /// Whether the periodic self-check of debug builds found the incremental penalty drifted
+    /// from a full recomputation in this step and resynced it (see `TableCache::resync`).
+    pub resynced: bool,
+}
+
+/// Number of iterations between two readings of the clock by `Pace`, which keeps the clock out
+/// of most iterations.
+#[cfg(feature = "std")]
+const PACE_SAMPLE_INTERVAL: usize = 64;
+
+/// Weight of the latest sample in the moving average of `Pace`.
+#[cfg(feature = "std")]
+const PACE_SMOOTHING: f64 = 0.25;
+
+/// Exponential moving average of the wall time per iteration, sampled every
+/// `PACE_SAMPLE_INTERVAL` iterations.
+#[derive(Debug, Clone, Default)]
+struct Pace {
+    mean: Option<Duration>,
+    /// Time and number of iterations done at the latest reading of the clock.
+    #[cfg(feature = "std")]
+    sample: Option<(std::time::Instant, usize)>,
+}
+
+impl Pace {
+    /// Reads the clock after the first iteration and every `PACE_SAMPLE_INTERVAL` iterations,
+    /// averaging the time per iteration since the previous reading.
+    #[cfg(feature = "std")]
+    fn tick(&mut self, done: usize) {
+        if self.sample.is_some() && !done.is_multiple_of(PACE_SAMPLE_INTERVAL) {
+            return;
+        }
+        let now = std::time::Instant::now();
+        if let Some((then, then_done)) = self.sample {
+            let elapsed = (now - then) / (done - then_done) as u32;
+            self.mean = Some(match self.mean {
+                Some(mean) => mean.mul_f64(1.0 - PACE_SMOOTHING) + elapsed.mul_f64(PACE_SMOOTHING),
+                None => elapsed,
+            });
+        }
+        self.sample = Some((now, done));
+    }
+
+    /// Time `remaining` iterations take at the average pace, or `None` before the first average
+    /// and beyond the largest `Duration`.
+    fn eta(&self, remaining: usize) -> Option<Duration> {
+        Duration::try_from_secs_f64(self.mean?.as_secs_f64() * remaining as f64).ok()
+    }
+}
+
+/// Simulated annealing driven one step at a time. Every call to `next` performs one
+/// proposal/acceptance cycle; iteration ends after `max_iterations` steps, but the caller may stop
+/// whenever they like and read the best table seen so far.
+pub struct Annealer<'a> {
+    condition: &'a Condition,
+    params: &'a Params,
+    state: State,
+    generator: Box<dyn ActionGenerator + 'a>,
+    schedule: Box<dyn CoolingSchedule + 'a>,
+    tabu: Option<TabuList>,
+    /// Source of the generator's random decisions, kept apart from the acceptance draws.
+    generator_rng: SmallRng,
+    rng: SmallRng,
+    best_rank: (bool, Score, usize),
+    best_table: Table,
+    /// Best rank when the current convergence window started.
+    window_start_rank: (bool, Score, usize),
+    pace: Pace,
+    objective: Objective<'a>,
+    /// Score changes are divided by this before the acceptance test (see
+    /// `Params::normalize_acceptance`).
+    acceptance_scale: f64,
+}
+
+impl<'a> Annealer<'a> {
+    pub fn new(table: &Table, condition: &'a Condition, params: &'a Params) -> Annealer<'a> {
+        Annealer::with_rng(table, condition, params, SmallRng::seed_from_u64(params.seed))
+    }
+
+    /// Like `new`, but draws every random decision from `rng` instead of seeding from
+    /// `params.seed`, for targets without a default source of randomness.
+    pub fn with_rng(table: &Table, condition: &'a Condition, params: &'a Params, rng: impl RngCore) -> Annealer<'a> {
+        Annealer::from_cache_with_rng(TableCache::create(table, &condition.penalty), condition, params, rng)
+    }
+
+    /// Like `new`, but starts from a cache that is already built, e.g. by a repair step.
+    pub fn from_cache(cache: TableCache, condition: &'a Condition, params: &'a Params) -> Annealer<'a> {
+        Annealer::from_cache_with_rng(cache, condition, params, SmallRng::seed_from_u64(params.seed))
+    }
+
+    fn from_cache_with_rng(cache: TableCache, condition: &'a Condition, params: &'a Params, mut rng: impl RngCore) -> Annealer<'a> {
+        let table = cache.to_table();
+        let generator_rng = SmallRng::from_rng(&mut rng).expect("random number generator failed");
+        let rng = SmallRng::from_rng(&mut rng).expect("random number generator failed");
+        let generator: Box<dyn ActionGenerator + 'a> = match (params.max_group_size, params.guided_exploration) {
+            (Some(max_size), _) => Box::new(ResizeGenerator::new(max_size, condition)),
+            (None, Some(exploration)) => Box::new(GuidedSwapGenerator::new(exploration, condition)),
+            (None, None) if table.iter_members().any(|member| member.optional)
+                => Box::new(AttendanceGenerator),
+            (None, None) if condition.size_balance > 0 as Score => Box::new(RebalanceGenerator),
+            (None, None) => Box::new(SwapGenerator::new()),
+        };
+        let schedule: Box<dyn CoolingSchedule> = match params.target_acceptance {
+            Some(target) => Box::new(AdaptiveCooling::new(target, ADAPTIVE_WINDOW, ADAPTIVE_MAX_ADJUSTMENT)),
+            None => Box::new(GeometricCooling { rate: params.cooling_rate }),
+        };
+        let state = State {
+            score: cache.effective_score(condition),
+            cache,
+            n_iterations: 0,
+            temperature: params.temperature,
+        };
+        let mut annealer = Annealer {
+            condition,
+            params,
+            generator,
+            schedule,
+            tabu: params.tabu_tenure.map(TabuList::new),
+            generator_rng,
+            rng,
+            best_rank: (true, Score::INFINITY, usize::MAX),
+            best_table: table,
+            window_start_rank: (true, Score::INFINITY, usize::MAX),
+            pace: Pace::default(),
+            objective: Objective::Effective,
+            acceptance_scale: 1.0,
+            state,
+        };
+        annealer.best_rank = annealer.rank();
+        annealer.window_start_rank = annealer.best_rank;
+        annealer.acceptance_scale = annealer.scale_of(annealer.state.score);
+        annealer
+    }
+
+    /// Acceptance scale for a run starting at `score`.
+    fn scale_of(&self, score: Score) -> f64 {
+        match score.abs() {
+            scale if self.params.normalize_acceptance && scale > 0.0 && scale.is_finite() => scale,
+            _ => 1.0,
+        }
+    }
+
+    /// Ranks the current state by feasibility first, then score, then (only relevant when groups
+    /// can be created and removed) the number of groups. Smaller is better.
+    fn rank(&self) -> (bool, Score, usize) {
+        let cache = &self.state.cache;
+        let feasible = cache.is_feasible(self.condition)
+            && self.params.max_group_size.is_none_or(|max_size| cache.sizes().iter().all(|size| *size <= max_size));
+        (!feasible, self.state.score, cache.sizes().len())
+    }
+
+    /// Whether the window ending at the current iteration improved the best score by less than
+    /// `convergence` requires. Starts the next window otherwise.
+    fn converged(&mut self, convergence: &Convergence) -> bool {
+        let window = convergence.window.max(1);
+        let n_iterations = self.state.n_iterations;
+        if n_iterations == 0 || !n_iterations.is_multiple_of(window) {
+            return false;
+        }
+        let (start, best) = (self.window_start_rank, self.best_rank);
+        let became_feasible = start.0 && !best.0;
+        let improvement = (start.1 - best.1) / start.1.abs().max(Score::MIN_POSITIVE);
+        if !became_feasible && improvement < convergence.min_relative_improvement {
+            return true;
+        }
+        self.window_start_rank = best;
+        false
+    }
+
+    /// Tries one action and reports whether it was applied.
+    fn try_action(&mut self) -> bool {
+        let actions: Vec<Action> = (0..self.params.candidates_per_step.max(1))
+            .map_while(|_| next_allowed(self.generator.as_mut(), &self.state.cache, &mut self.generator_rng, self.tabu.as_ref()))
+            .collect();
+        let mut results = self.state.cache.simulate_many(&actions, self.condition);
+        if self.params.strict_feasible {
+            for result in results.iter_mut().filter(|result| matches!(result, ActionResult::UnsatisfiedScoreDiff(_))) {
+                *result = ActionResult::Failed(vec![ActionError::ConstraintViolated]);
+            }
+        }
+        if let Objective::Custom(score) = &self.objective {
+            for (action, result) in actions.iter().zip(results.iter_mut()).filter(|(_, result)| !result.is_failed()) {
+                let mut trial = self.state.cache.clone();
+                *result = match trial.act(action.clone(), self.condition) {
+                    Ok(_) => with_delta(result, score(&trial) - self.state.score),
+                    Err(error) => ActionResult::Failed(vec![error]),
+                };
+            }
+        }
+        // The first of the smallest deltas wins; failed actions only when nothing else is left.
+        let Some((action, result)) = actions.into_iter().zip(results)
+            .min_by(|(_, a), (_, b)| {
+                let delta = |result: &ActionResult| result.score_delta().unwrap_or(Score::INFINITY);
+                delta(a).total_cmp(&delta(b))
+            })
+        else {
+            return false;
+        };
+        // Dividing the change by the scale is the same as multiplying the temperature by it.
+        if !accepts(&result, self.state.temperature * self.acceptance_scale, &mut self.rng) {
+            return false;
+        }
+        if let Some(tabu) = self.tabu.as_mut() {
+            tabu.record(&action, &self.state.cache, self.state.n_iterations);
+            if let Action::RemoveEmptyGroup(group_index) = action {
+                tabu.remove_group(group_index);
+            }
+        }
+        self.state.cache.act(action, self.condition).is_ok()
+    }
+
+    /// Replaces the generator chosen from `params` for the remaining iterations, e.g. to switch
+    /// from broad moves to fine swaps once the temperature has dropped.
+    pub fn set_generator(&mut self, generator: Box<dyn ActionGenerator + 'a>) {
+        self.generator = generator;
+    }
+
+    /// Minimizes `objective` instead of the effective score for the remaining iterations. The
+    /// best table is ranked under the new objective from here on, starting with the current one,
+    /// and a normalized acceptance is scaled by the current score under it.
+    pub fn set_objective(&mut self, objective: Objective<'a>) {
+        self.objective = objective;
+        self.state.score = self.current_score();
+        self.acceptance_scale = self.scale_of(self.state.score);
+        self.best_rank = self.rank();
+        self.best_table = self.state.cache.to_table();
+        self.window_start_rank = self.best_rank;
+    }
+
+    /// Score of the current table under the objective, computed from scratch.
+    fn current_score(&self) -> Score {
+        match &self.objective {
+            Objective::Effective => self.state.cache.effective_score(self.condition),
+            Objective::Custom(score) => score(&self.state.cache),
+        }
+    }
+
+    /// Replaces the cooling schedule chosen from `params` for the remaining iterations.
+    pub fn set_schedule(&mut self, schedule: impl CoolingSchedule + 'a) {
+        self.schedule = Box::new(schedule);
+    }
+
+    pub fn best_table(&self) -> &Table {
+        &self.best_table
+    }
+
+    pub fn best_score(&self) -> Score {
+        self.best_rank.1
+    }
+
+    pub fn into_best_table(self) -> Table {
+        self.best_table
+    }
+
+    /// The best table as the entry points return it, canonicalized if `params` asks for it.
+    fn into_output_table(self) -> Table {
+        let mut table = self.best_table;
+        if self.params.canonicalize {
+            table.sort_members_by_id();
+        }
+        table
+    }
+}
+
+impl Iterator for Annealer<'_> {
+    type Item = AnnealStep;
+
+    fn next(&mut self) -> Option<AnnealStep> {
+        if self.state.n_iterations >= self.params.max_iterations {
+            return None;
+        }
+        if self.params.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            return None;
+        }
+        if let Some(convergence) = &self.params.convergence {
+            if self.converged(convergence) {
+                return None;
+            }
+        }
+        if let Some(tabu) = self.tabu.as_mut() {
+            tabu.expire(self.state.n_iterations);
+        }
+        let accepted = self.try_action();
+        let resynced = cfg!(debug_assertions)
+            && self.state.n_iterations % RESYNC_INTERVAL == RESYNC_INTERVAL - 1
+            && self.state.cache.resync(&self.condition.penalty, RESYNC_EPSILON);
+        if resynced {
+            self.state.score = self.current_score();
+        }
+        if accepted {
+            self.state.score = self.current_score();
+            let rank = self.rank();
+            if rank < self.best_rank {
+                self.best_rank = rank;
+                self.best_table = self.state.cache.to_table();
+            }
+        }
+        let done = self.state.n_iterations + 1;
+        #[cfg(feature = "std")]
+        self.pace.tick(done);
+        let step = AnnealStep {
+            iteration: self.state.n_iterations,
+            score: self.state.score,
+            best_score: self.best_score(),
+            temperature: self.state.temperature,
+            accepted,
+            progress: done as f64 / self.params.max_iterations as f64,
+            eta: self.pace.eta(self.params.max_iterations - done),
+            resynced,
+        };
+        self.state.temperature = self.schedule.next_temperature(self.state.temperature, accepted);
+        self.state.n_iterations += 1;
+        Some(step)
+    }
+}
+
+/// Runs simulated annealing from `table` and returns the best table seen,
+/// preferring tables that satisfy every constraint (and the group size bound, if any).
+/// Fails if `params` are out of range (see `Params::check`) or if an id occurs twice in `table`.
+pub fn anneal(table: &Table, condition: &Condition, params: &Params) -> Result<Table, ShuffleError> {
+    Ok(anneal_cached(TableCache::create(table, &condition.penalty), condition, params)?.0)
+}
+
+/// Like `anneal`, but runs on `cache` instead of building one, and also returns the effective
+/// score of the best table.
+pub fn anneal_cached(cache: TableCache, condition: &Condition, params: &Params) -> Result<(Table, Score), ShuffleError> {
+    params.check()?;
+    cache.to_table().check_unique_ids()?;
+    let mut annealer = Annealer::from_cache(cache, condition, params);
+    annealer.by_ref().for_each(drop);
+    let score = annealer.best_score();
+    Ok((annealer.into_output_table(), score))
+}
+
+/// Anneals in stages of increasing `Condition::constraint_priorities`. Stage `p` starts from the
+/// previous stage's table and keeps the tag constraints of priority up to `p` hard. Tags with a
+/// higher priority move to `Condition::soft_constraint`: they cost `constraint_penalty` but do
+/// not decide feasibility yet.
+/// A stage fails when its best table violates a tag of priority `p`, or more tags of lower
+/// priorities than the previous table; capacities and `max_pair_penalty` are not considered.
+/// A failed stage is dropped, its tags are sacrificed and left out of the later stages, and the
+/// next stage continues from the previous table. Priority 0 is never sacrificed, so its stage
+/// always keeps its best table.
+/// Returns the final table and the sacrificed tags in order.
+pub fn anneal_staged(table: &Table, condition: &Condition, params: &Params) -> Result<(Table, Vec<Tag>), ShuffleError> {
+    let priority = |tag: &Tag| condition.constraint_priorities.get(tag).copied().unwrap_or(0);
+    let levels: BTreeSet<u8> = condition.constraint.0.keys().map(priority).chain([0]).collect();
+    let mut best = table.clone();
+    let mut sacrificed = BTreeSet::new();
+    for level in levels {
+        let subset = |keep: &dyn Fn(u8) -> bool| Constraint(condition.constraint.0.iter()
+            .filter(|(tag, _)| keep(priority(tag)) && !sacrificed.contains(*tag))
+            .map(|(tag, range)| (tag.clone(), range.clone()))
+            .collect());
+        let (earlier, current) = (subset(&|p| p < level), subset(&|p| p == level));
+        let mut soft_constraint = condition.soft_constraint.clone();
+        soft_constraint.0.extend(subset(&|p| p > level).0);
+        let stage = Condition { constraint: subset(&|p| p <= level), soft_constraint, ..condition.clone() };
+        let candidate = anneal(&best, &stage, params)?;
+        let failed = level > 0 && (tag_violations(&candidate, &current) > 0
+            || tag_violations(&candidate, &earlier) > tag_violations(&best, &earlier));
+        if failed {
+            sacrificed.extend(current.0.into_keys());
+        } else {
+            best = candidate;
+        }
+    }
+    Ok((best, sacrificed.into_iter().collect()))
+}
+
+/// Number of `(group, tag)` pairs of `table` whose count lies outside its range in `constraint`.
+fn tag_violations(table: &Table, constraint: &Constraint) -> usize {
+    let condition = Condition { constraint: constraint.clone(), ..Default::default() };
+    TableCache::create(table, &condition.penalty).constraint_report(&condition).into_iter()
+        .map(|(_, status)| status.err().map_or(0, |violations| violations.len()))
+        .sum()
+}
+
+/// Checks `condition` with `validate`, builds a starting table with `greedy_assign` and anneals it.
+/// Fails with `AssignmentError::DuplicateIds` before anything else if two members share an id.
+pub fn solve(members: Vec<Member>, condition: &Condition, group_count: usize, params: &Params) -> Result<Table, ShuffleError> {
+    check_unique_ids(&members)?;
+    validate(&members, condition, group_count)?;
+    let start = greedy_assign(members, condition, group_count)?;
+    anneal(&start, condition, params)
+}
+
+/// Anneals `restarts` times, each run from a fresh `random_assignment` and with `params.seed`
+/// replaced by `seed + r` in restart `r`, and returns the best table found, ranked like the
+/// best table of a single run (feasible first, then by score). Fails like `anneal`, with
+/// `ParamsError::NoRestarts` for 0 restarts and with `Infeasibility::NoGroups` for 0 groups.
+pub fn anneal_with_restarts(
+    members: Vec<Member>, condition: &Condition, group_count: usize, params: &Params, restarts: usize, seed: u64,
+) -> Result<Table, ShuffleError> {
+    params.check()?;
+    check_unique_ids(&members)?;
+    if group_count == 0 {
+        return Err(ShuffleError::Infeasible(vec![Infeasibility::NoGroups { members: members.len() }]));
+    }
+    let mut best: Option<((bool, Score, usize), Table)> = None;
+    for restart in 0..restarts {
+        let restart_seed = seed.wrapping_add(restart as u64);
+        let start = random_assignment(members.clone(), group_count, restart_seed);
+        let restart_params = Params { seed: restart_seed, ..params.clone() };
+        let mut annealer = Annealer::new(&start, condition, &restart_params);
+        annealer.by_ref().for_each(drop);
+        if best.as_ref().is_none_or(|(rank, _)| annealer.best_rank < *rank) {
+            best = Some((annealer.best_rank, annealer.into_output_table()));
+        }
+    }
+    best.map(|(_, table)| table).ok_or(ShuffleError::Params(ParamsError::NoRestarts))
+}
+
+/// Produces one table per round for a multi-session event, after checking `base_condition` with
+/// `validate`. Every round starts from
+/// `greedy_assign` and is annealed under `base_condition` plus a history penalty of `per_repeat`
+/// for each earlier round a pair shared a group, so partners rarely repeat. Annealing uses the
+/// default `Params`, starting at temperature `per_repeat` and seeded with `seed + r` in round `r`,
+/// which makes the result deterministic.
+pub fn shuffle_rounds(
+    members: Vec<Member>, base_condition: &Condition, group_count: usize, rounds: usize, per_repeat: Score, seed: u64,
+) -> Result<Vec<Table>, ShuffleError> {
+    check_unique_ids(&members)?;
+    validate(&members, base_condition, group_count)?;
+    let mut condition = base_condition.clone();
+    let mut tables = Vec::with_capacity(rounds);
+    for round in 0..rounds {
+        let start = greedy_assign(members.clone(), &condition, group_count)?;
+        let params = Params { temperature: per_repeat, seed: seed.wrapping_add(round as u64), ..Default::default() };
+        let table = anneal(&start, &condition, &params)?;
+        condition.penalty.accumulate(&table, per_repeat);
+        tables.push(table);
+    }
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, string::ToString};
+    use crate::collections::{BTreeSet, HashMap, HashSet};
+    use super::*;
+    use crate::model::entity::Member;
+    use crate::model::group::{Group, AssignmentError};
+    use crate::model::condition::{RelationPenalty, Constraint, Range};
+
+    fn table_fixture() -> Table {
+        let groups = (0..3).map(|group_index| {
+            Group {
+                members: (0..3).map(|i| Member { id: group_index * 3 + i, tags: HashSet::new(), ..Default::default() }).collect(),
+            }
+        }).collect();
+        Table { groups, ..Default::default() }
+    }
+
+    fn condition_fixture() -> Condition {
+        Condition {
+            penalty: RelationPenalty {
+                scores: [
+                    ([0, 1].into_iter().collect::<BTreeSet<Id>>(), 5 as Score),
+                    ([3, 4].into_iter().collect::<BTreeSet<Id>>(), 5 as Score),
+                    ([6, 7].into_iter().collect::<BTreeSet<Id>>(), 5 as Score),
+                ].into_iter().collect(),
+                default: 0 as Score,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tabu_expire() {
+        let mut tabu = TabuList::new(2);
+        tabu.entries.push_back((0, 0, 0));
+        tabu.entries.push_back((1, 1, 1));
+        tabu.expire(1);
+        assert!(tabu.contains(0, 0));
+        tabu.expire(2);
+        assert!(!tabu.contains(0, 0));
+        assert!(tabu.contains(1, 1));
+        tabu.expire(3);
+        assert!(tabu.entries.is_empty());
+    }
+
+    #[test]
+    fn test_tabu_forbids_moving_back() {
+        let tenure = 4;
+        let condition = condition_fixture();
+        let mut cache = TableCache::create(&table_fixture(), &condition.penalty);
+        let mut generator = SwapGenerator::new();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut tabu = TabuList::new(tenure);
+        // member id -> (group left, iteration)
+        let mut left: HashMap<Id, (Index, usize)> = HashMap::new();
+
+        for iteration in 0..200 {
+            tabu.expire(iteration);
+            let Some(action) = next_allowed(&mut generator, &cache, &mut rng, Some(&tabu)) else { continue };
+            for (id, source, target) in TabuList::transitions(&action, &cache) {
+                if let Some((group, moved_at)) = left.get(&id) {
+                    assert!(*group != target || iteration >= moved_at + tenure);
+                }
+                left.insert(id, (source, iteration));
+            }
+            tabu.record(&action, &cache, iteration);
+            cache.act(action, &condition).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_anneal_keeps_members() {
+        let condition = condition_fixture();
+        let params = Params { cooling_rate: 0.99, max_iterations: 500, tabu_tenure: Some(2), ..Default::default() };
+        let table = anneal(&table_fixture(), &condition, &params).unwrap();
+        let cache = TableCache::create(&table, &condition.penalty);
+        let ids: BTreeSet<Id> = table.iter_members().map(|member| member.id).collect();
+        assert_eq!(ids, (0..9).collect());
+        assert_eq!(cache.penalty_score, 0 as Score);
+    }
+
+    #[test]
+    fn test_anneal_cached_matches_anneal() {
+        let condition = condition_fixture();
+        let params = Params { cooling_rate: 0.99, max_iterations: 500, seed: 3, ..Default::default() };
+        let cache = TableCache::create(&table_fixture(), &condition.penalty);
+        let (table, score) = anneal_cached(cache, &condition, &params).unwrap();
+        assert_eq!(table, anneal(&table_fixture(), &condition, &params).unwrap());
+        assert_eq!(score, TableCache::create(&table, &condition.penalty).effective_score(&condition));
+    }
+
+    #[test]
+    fn test_resize_generator() {
+        let condition = condition_fixture();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut generator = ResizeGenerator::new(2, &condition);
+        let mut cache = TableCache::create(&table_fixture(), &condition.penalty);
+        assert!(matches!(generator.next(&cache, &mut rng), Some(Action::CreateGroup)));
+        cache.act(Action::CreateGroup, &condition).unwrap();
+        assert!(matches!(
+            generator.next(&cache, &mut rng),
+            Some(Action::Move { source_position: Position { group_index: 2, .. }, target_group: 3 })
+        ));
+
+        let mut generator = ResizeGenerator::new(3, &condition);
+        assert!(matches!(generator.next(&cache, &mut rng), Some(Action::RemoveEmptyGroup(3))));
+    }
+
+    #[test]
+    fn test_anneal_reduces_group_count() {
+        let condition = condition_fixture();
+        let params = Params { cooling_rate: 0.99, max_iterations: 2000, max_group_size: Some(5), ..Default::default() };
+        let table = anneal(&table_fixture(), &condition, &params).unwrap();
+        let cache = TableCache::create(&table, &condition.penalty);
+        assert_eq!(table.groups.len(), 2);
+        assert!(cache.sizes().iter().all(|size| *size <= 5));
+        assert_eq!(cache.sizes().iter().sum::<usize>(), 9);
+        assert_eq!(cache.penalty_score, 0 as Score);
+    }
+
+    #[test]
+    fn test_accumulate_history_over_rounds() {
+        let mut history = RelationPenalty::new(0 as Score);
+        let first_round = table_fixture();
+        history.accumulate(&first_round, 1 as Score);
+        assert_eq!(history.get_pair([0, 1]), 1 as Score);
+        assert_eq!(history.get_pair([0, 3]), 0 as Score);
+        assert_eq!(TableCache::create(&first_round, &history).penalty_score, 9 as Score);
+
+        let condition = Condition { penalty: history, ..Default::default() };
+        let params = Params { cooling_rate: 0.995, max_iterations: 3000, ..Default::default() };
+        let second_round = anneal(&first_round, &condition, &params).unwrap();
+        assert_eq!(TableCache::create(&second_round, &condition.penalty).penalty_score, 0 as Score);
+
+        let mut history = condition.penalty;
+        history.accumulate(&second_round, 1 as Score);
+        assert_eq!(TableCache::create(&first_round, &history).penalty_score, 9 as Score);
+        assert_eq!(TableCache::create(&second_round, &history).penalty_score, 9 as Score);
+    }
+
+    #[test]
+    fn test_seeded_anneal_is_reproducible() {
+        let condition = condition_fixture();
+        for seed in [0, 1, 42] {
+            let params = Params { max_iterations: 300, tabu_tenure: Some(2), seed, ..Default::default() };
+            let first = anneal(&table_fixture(), &condition, &params).unwrap();
+            let second = anneal(&table_fixture(), &condition, &params).unwrap();
+            assert_eq!(first, second);
+        }
+    }
+
+    #[test]
+    fn test_annealer_steps() {
+        let condition = condition_fixture();
+        let params = Params { max_iterations: 100, ..Default::default() };
+        let table = table_fixture();
+        let mut annealer = Annealer::new(&table, &condition, &params);
+        let steps: Vec<AnnealStep> = annealer.by_ref().take(50).collect();
+        assert_eq!(steps.len(), 50);
+        assert!(steps.iter().enumerate().all(|(i, step)| step.iteration == i));
+        assert!(steps.windows(2).all(|pair| pair[1].best_score <= pair[0].best_score));
+        assert!(steps.iter().all(|step| step.best_score <= step.score));
+        assert_eq!(annealer.by_ref().count(), 50);
+        let best = TableCache::create(annealer.best_table(), &condition.penalty);
+        assert_eq!(best.effective_score(&condition), annealer.best_score());
+    }
+
+    #[test]
+    fn test_guided_swaps_prefer_violating_groups() {
+        let tagged = |id: Id| if id < 2 { Member::with_tags(id, ["a"]) } else { Member::new(id) };
+        let table = Table {
+            groups: (0..4).map(|group_index| Group { members: (0..3).map(|i| tagged(group_index * 3 + i)).collect() }).collect(),
+            ..Default::default()
+        };
+        let condition = Condition {
+            constraint: Constraint([("a".to_string(), Range::Count { min: 0, max: 1 })].into()),
+            ..Default::default()
+        };
+        let cache = TableCache::create(&table, &condition.penalty);
+        assert!(!cache.is_group_feasible(0, &condition));
+        assert!((1..4).all(|group_index| cache.is_group_feasible(group_index, &condition)));
+
+        let mut generator = GuidedSwapGenerator::new(0.1, &condition);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let n_proposals = 1000;
+        let touching = (0..n_proposals).filter(|_| {
+            match generator.next(&cache, &mut rng) {
+                Some(Action::Swap(position1, position2)) => position1.group_index == 0 || position2.group_index == 0,
+                _ => false,
+            }
+        }).count();
+        // uniformly random swaps would touch the violating group half of the time
+        assert!(touching > n_proposals * 8 / 10);
+    }
+
+    #[test]
+    fn test_guided_exploration_is_clamped() {
+        let members = (0..6).map(|id| if id < 2 { Member::with_tags(id, ["a"]) } else { Member::new(id) }).collect();
+        let table = Table::from_assignment(members, &[0, 0, 0, 1, 1, 1], 2).unwrap();
+        let condition = Condition {
+            constraint: Constraint([("a".to_string(), Range::AtMost(1))].into()),
+            ..Default::default()
+        };
+        for exploration in [-1.0, 2.0, f64::NAN] {
+            let params = Params { guided_exploration: Some(exploration), max_iterations: 50, ..Default::default() };
+            let best = anneal(&table, &condition, &params).unwrap();
+            assert!(TableCache::create(&best, &condition.penalty).is_feasible(&condition));
+        }
+    }
+
+    #[test]
+    fn test_step_reports_resync() {
+        let condition = condition_fixture();
+        let params = Params { max_iterations: RESYNC_INTERVAL, ..Default::default() };
+        let mut annealer = Annealer::new(&table_fixture(), &condition, &params);
+        annealer.state.cache.penalty_score += 1 as Score;
+        let resynced: Vec<usize> = annealer.by_ref().filter(|step| step.resynced).map(|step| step.iteration).collect();
+        let expected = if cfg!(debug_assertions) { vec![RESYNC_INTERVAL - 1] } else { Vec::new() };
+        assert_eq!(resynced, expected);
+    }
+
+    #[test]
+    fn test_adaptive_cooling_tracks_target() {
+        let members: Vec<Member> = (0..12).map(Member::new).collect();
+        let table = Table::from_assignment(members, &[0, 1, 2, 3].repeat(3), 4).unwrap();
+        let mut penalty = RelationPenalty::new(0 as Score);
+        for a in 0..12 {
+            for b in a + 1..12 {
+                penalty.scores.insert([a, b].into(), ((a * 7 + b * 13) % 10) as Score);
+            }
+        }
+        let condition = Condition { penalty, ..Default::default() };
+        let target = 0.3;
+        let params = Params { temperature: 100.0, max_iterations: 6000, target_acceptance: Some(target), ..Default::default() };
+        let steps: Vec<AnnealStep> = Annealer::new(&table, &condition, &params).collect();
+        let late = &steps[3000..];
+        let rate = late.iter().filter(|step| step.accepted).count() as f64 / late.len() as f64;
+        assert!((rate - target).abs() < 0.05, "acceptance rate {rate}");
+        assert!(late[0].temperature < 100.0);
+    }
+
+    #[test]
+    fn test_cooling_schedules() {
+        let mut schedule = GeometricCooling { rate: 0.5 };
+        assert_eq!(schedule.next_temperature(2.0, true), 1.0);
+        let mut adaptive = AdaptiveCooling::new(0.5, 2, 1.0);
+        assert_eq!(adaptive.next_temperature(1.0, false), 1.0);
+        assert_eq!(adaptive.next_temperature(1.0, false), 2.0);
+        assert_eq!(adaptive.next_temperature(2.0, true), 2.0);
+        assert_eq!(adaptive.next_temperature(2.0, true), 1.0);
+    }
+
+    #[test]
+    fn test_shuffle_rounds_avoids_repeats() {
+        let members: Vec<Member> = (0..12).map(Member::new).collect();
+        let condition = Condition { penalty: RelationPenalty::new(0 as Score), ..Default::default() };
+        let tables = shuffle_rounds(members.clone(), &condition, 4, 3, 10 as Score, 7).unwrap();
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables, shuffle_rounds(members, &condition, 4, 3, 10 as Score, 7).unwrap());
+        let mut seen: HashMap<BTreeSet<Id>, usize> = HashMap::new();
+        for table in &tables {
+            for group in &table.groups {
+                assert_eq!(group.members.len(), 3);
+                for a in &group.members {
+                    for b in group.members.iter().filter(|b| b.id > a.id) {
+                        *seen.entry([a.id, b.id].into()).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        let repeats: usize = seen.values().map(|count| count - 1).sum();
+        // Random tables would repeat about 6 of the 36 pairs; three rounds without repeats exist.
+        assert!(repeats <= 1, "{repeats} repeated pairs");
+    }
+
+    #[test]
+    fn test_rebalance_reduces_size_spread() {
+        let members: Vec<Member> = (0..9).map(Member::new).collect();
+        let table = Table::from_assignment(members, &[0, 0, 0, 0, 0, 0, 1, 1, 2], 3).unwrap();
+        let condition = Condition { size_balance: 1 as Score, ..condition_fixture() };
+        let spread = |table: &Table| {
+            let sizes = table.group_sizes();
+            sizes.iter().max().unwrap() - sizes.iter().min().unwrap()
+        };
+        assert_eq!(spread(&table), 5);
+        let params = Params { max_iterations: 500, ..Default::default() };
+        let balanced = anneal(&table, &condition, &params).unwrap();
+        assert!(spread(&balanced) <= 1);
+        assert_eq!(balanced.group_sizes().iter().sum::<usize>(), 9);
+    }
+
+    #[test]
+    fn test_solve_reports_infeasible_constraints() {
+        let members: Vec<Member> = (0..6).map(|id| Member::with_tags(id, if id < 2 { vec!["lead"] } else { vec![] })).collect();
+        let condition = Condition {
+            constraint: Constraint([("lead".to_string(), Range::AtLeast(1))].into_iter().collect()),
+            ..Default::default()
+        };
+        let error = solve(members.clone(), &condition, 3, &Params::default()).unwrap_err();
+        assert!(matches!(&error, ShuffleError::Infeasible(infeasibilities) if infeasibilities.len() == 1));
+        assert_eq!(
+            error.to_string(),
+            "constraints cannot be satisfied: tag lead is held by 2 members, but the groups need at least 3"
+        );
+        let table = solve(members.clone(), &condition, 2, &Params { max_iterations: 100, ..Default::default() }).unwrap();
+        assert!(TableCache::create(&table, &condition.penalty).is_feasible(&condition));
+
+        let mut duplicated = members;
+        duplicated[5].id = 2;
+        assert_eq!(
+            solve(duplicated.clone(), &condition, 2, &Params::default()).unwrap_err(),
+            ShuffleError::Assignment(AssignmentError::DuplicateIds { ids: vec![2] })
+        );
+        // Duplicate ids are reported before the infeasible constraints of 3 groups.
+        assert_eq!(
+            solve(duplicated, &condition, 3, &Params::default()).unwrap_err(),
+            ShuffleError::Assignment(AssignmentError::DuplicateIds { ids: vec![2] })
+        );
+    }
+
+    #[test]
+    fn test_entry_points_report_errors() {
+        let mut duplicated = table_fixture();
+        duplicated.groups[1].members[0].id = 0;
+        let duplicate_ids = ShuffleError::Assignment(AssignmentError::DuplicateIds { ids: vec![0] });
+        assert_eq!(anneal(&duplicated, &condition_fixture(), &Params::default()).unwrap_err(), duplicate_ids);
+
+        let members: Vec<Member> = (0..6).map(Member::new).collect();
+        let condition = Condition::default();
+        let params = Params { max_iterations: 10, ..Default::default() };
+        assert_eq!(
+            anneal_with_restarts(members.clone(), &condition, 2, &params, 0, 0).unwrap_err(),
+            ShuffleError::Params(ParamsError::NoRestarts)
+        );
+        assert!(matches!(
+            anneal_with_restarts(members.clone(), &condition, 0, &params, 1, 0),
+            Err(ShuffleError::Infeasible(_))
+        ));
+        let hot = Params { target_acceptance: Some(1.5), ..params.clone() };
+        let error = anneal(&table_fixture(), &condition, &hot).unwrap_err();
+        assert_eq!(error, ShuffleError::Params(ParamsError::TargetAcceptance(1.5)));
+        assert_eq!(error.to_string(), "invalid parameters: target acceptance 1.5 is not a probability");
+        let unbounded = Params { max_group_size: Some(0), ..params };
+        assert_eq!(
+            anneal(&table_fixture(), &condition, &unbounded).unwrap_err(),
+            ShuffleError::Params(ParamsError::ZeroMaxGroupSize)
+        );
+    }
+
+    #[test]
+    fn test_candidates_per_step_converges_faster() {
+        let table = table_fixture();
+        let condition = condition_fixture();
+        let first_optimal = |candidates_per_step: usize| {
+            let params = Params { max_iterations: 2000, candidates_per_step, ..Default::default() };
+            let mut annealer = Annealer::new(&table, &condition, &params);
+            annealer.position(|step| step.best_score == 0 as Score).expect("the optimum is reached")
+        };
+        assert!(first_optimal(20) < first_optimal(1));
+    }
+
+    #[test]
+    fn test_drops_optional_member_to_meet_constraint() {
+        let mut members: Vec<Member> = (0..6).map(|id| Member::with_tags(id, if id < 3 { vec!["x"] } else { vec![] })).collect();
+        members[2].optional = true;
+        let table = Table::from_assignment(members, &[0, 0, 0, 1, 1, 1], 2).unwrap();
+        let condition = Condition {
+            constraint: Constraint([("x".to_string(), Range::AtMost(1))].into_iter().collect()),
+            unassigned_penalty: 5 as Score,
+            ..condition_fixture()
+        };
+        // Constraints only decide which table is best, so the search has to be hot enough to pay
+        // for dropping the member before it finds a feasible arrangement.
+        let params = Params { temperature: 10.0, max_iterations: 3000, ..Default::default() };
+        let solved = anneal(&table, &condition, &params).unwrap();
+        let cache = TableCache::create(&solved, &condition.penalty);
+        assert!(cache.is_feasible(&condition));
+        let ids: BTreeSet<Id> = solved.iter_members().map(|member| member.id).collect();
+        assert_eq!(ids, [0, 1, 3, 4, 5].into_iter().collect());
+    }
+
+    #[test]
+    fn test_churn_penalty_limits_moves() {
+        let previous = table_fixture();
+        let moved = |churn_penalty: Score| {
+            let mut condition = Condition { churn_penalty, ..condition_fixture() };
+            condition.set_previous(&previous);
+            let params = Params { max_iterations: 2000, ..Default::default() };
+            previous.diff(&anneal(&previous, &condition, &params).unwrap()).len()
+        };
+        assert_eq!(moved(100 as Score), 0);
+        assert!(moved(0 as Score) > 0);
+    }
+
+    #[test]
+    fn test_restarts_match_single_run() {
+        let members: Vec<Member> = (0..9).map(Member::new).collect();
+        let condition = condition_fixture();
+        let score = |table: &Table| TableCache::create(table, &condition.penalty).effective_score(&condition);
+        let single_params = Params { max_iterations: 400, ..Default::default() };
+        let single = anneal(&random_assignment(members.clone(), 3, 0), &condition, &single_params).unwrap();
+        let params = Params { max_iterations: 100, ..Default::default() };
+        let restarted = anneal_with_restarts(members, &condition, 3, &params, 4, 0).unwrap();
+        assert!(score(&restarted) <= score(&single));
+        assert_eq!(score(&restarted), 0 as Score);
+    }
+
+    #[test]
+    fn test_set_generator_mid_run() {
+        let condition = condition_fixture();
+        let params = Params { max_iterations: 400, ..Default::default() };
+        let mut annealer = Annealer::new(&table_fixture(), &condition, &params);
+        annealer.set_generator(Box::new(MoveGenerator));
+        assert_eq!(annealer.by_ref().take(200).count(), 200);
+        annealer.set_generator(Box::new(SwapGenerator::new()));
+        let steps: Vec<AnnealStep> = annealer.by_ref().collect();
+        assert_eq!(steps.len(), 200);
+        assert!(steps.iter().any(|step| step.accepted));
+
+        let cache = &annealer.state.cache;
+        let table = cache.to_table();
+        let ids: BTreeSet<Id> = table.iter_members().map(|member| member.id).collect();
+        assert_eq!(ids, (0..9).collect());
+        let rebuilt = TableCache::create(&table, &condition.penalty);
+        assert!((cache.penalty_score - rebuilt.penalty_score).abs() < 1e-9);
+        assert_eq!(annealer.state.score, rebuilt.effective_score(&condition));
+    }
+
+    /// Proposes the same swap every time.
+    struct FixedSwap;
+
+    impl ActionGenerator for FixedSwap {
+        fn next(&mut self, _cache: &TableCache, _rng: &mut SmallRng) -> Option<Action> {
+            Some(Action::Swap(Position::new(0, 1), Position::new(1, 0)))
+        }
+    }
+
+    #[test]
+    fn test_mock_generator_drives_step() {
+        let condition = condition_fixture();
+        let params = Params { max_iterations: 1, ..Default::default() };
+        let mut annealer = Annealer::new(&table_fixture(), &condition, &params);
+        annealer.set_generator(Box::new(FixedSwap));
+        let step = annealer.next().unwrap();
+        // Separating 0 from 1 and 3 from 4 removes two penalties of 5.
+        assert!(step.accepted);
+        assert_eq!(step.score, 5 as Score);
+        let table = annealer.state.cache.to_table();
+        assert_eq!(table.groups[0].members[1].id, 3);
+        assert_eq!(annealer.next(), None);
+    }
+
+    /// Proposes `FixedSwap` first and a harmless swap second.
+    struct FixedThenHarmless {
+        proposed: usize,
+    }
+
+    impl ActionGenerator for FixedThenHarmless {
+        fn next(&mut self, cache: &TableCache, rng: &mut SmallRng) -> Option<Action> {
+            self.proposed += 1;
+            if !self.proposed.is_multiple_of(2) {
+                FixedSwap.next(cache, rng)
+            } else {
+                Some(Action::Swap(Position::new(0, 1), Position::new(1, 1)))
             }
         }
     }
+
+    #[test]
+    fn test_custom_objective_skips_actions_that_fail_to_apply() {
+        // Swapping 1 with 2 passes simulate, since its delta is finite, but overflows the total.
+        let huge = Score::MAX / 1.5;
+        let mut penalty = RelationPenalty::new(0 as Score);
+        for pair in [[0, 1], [0, 2], [1, 3]] {
+            penalty.scores.insert(pair.into(), huge);
+        }
+        let table = Table { groups: vec![
+            Group { members: vec![Member::new(0), Member::new(1)] },
+            Group { members: vec![Member::new(2), Member::new(3)] },
+        ], ..Default::default() };
+        let condition = Condition { penalty, checked_scores: true, ..Default::default() };
+        // An infinite temperature accepts any finite worsening, which leaves the choice between
+        // the two candidates as the only way for the step to fail.
+        let params = Params {
+            max_iterations: 1, candidates_per_step: 2, temperature: f64::INFINITY, ..Default::default()
+        };
+        let mut annealer = Annealer::new(&table, &condition, &params);
+        annealer.set_generator(Box::new(FixedThenHarmless { proposed: 0 }));
+        // The harmless swap looks worse under the objective than the overflowing one does under
+        // the effective score, so the latter wins unless it is ruled out.
+        let three_joins_zero = |cache: &TableCache| {
+            let table = cache.to_table();
+            if table.groups[0].members.iter().any(|member| member.id == 3) { Score::MAX } else { 0 as Score }
+        };
+        annealer.set_objective(Objective::Custom(Box::new(three_joins_zero)));
+        let step = annealer.next().unwrap();
+        assert!(step.accepted);
+        let table = annealer.state.cache.to_table();
+        assert_eq!(table.groups[0].members[1].id, 3);
+    }
+
+    #[test]
+    fn test_mixed_generator() {
+        let cache = TableCache::create(&table_fixture(), &condition_fixture().penalty);
+        let mut generator = MixedGenerator::new(vec![
+            (Box::new(FixedSwap), 1.0),
+            (Box::new(MoveGenerator), 1.0),
+            (Box::new(SwapGenerator::new()), 0.0),
+        ]);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let actions: Vec<Action> = (0..100).filter_map(|_| generator.next(&cache, &mut rng)).collect();
+        assert_eq!(actions.len(), 100);
+        let fixed = actions.iter().filter(|action| matches!(action, Action::Swap(..))).count();
+        assert!((30..70).contains(&fixed));
+    }
+
+    #[test]
+    fn test_convergence_stops_early() {
+        let condition = condition_fixture();
+        let convergence = Convergence { window: 200, min_relative_improvement: 0.01 };
+        let params = Params { max_iterations: 100_000, convergence: Some(convergence), ..Default::default() };
+        let mut annealer = Annealer::new(&table_fixture(), &condition, &params);
+        let steps = annealer.by_ref().count();
+        assert!(steps < 5000);
+        assert_eq!(steps % 200, 0);
+        assert_eq!(annealer.best_score(), 0 as Score);
+        assert_eq!(annealer.next(), None);
+    }
+
+    #[test]
+    fn test_worst_pair_generator_splits_dominating_pair() {
+        let mut condition = condition_fixture();
+        condition.penalty.scores.insert([4, 5].into(), 100 as Score);
+        let cache = TableCache::create(&table_fixture(), &condition.penalty);
+        let mut generator = WorstPairGenerator::new(0.1, &condition);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let n_proposals = 1000;
+        let splitting = (0..n_proposals).filter(|_| {
+            let leaves = |position: &Position| cache.get_member(position).is_some_and(|member| [4, 5].contains(&member.id));
+            match generator.next(&cache, &mut rng) {
+                Some(Action::Swap(position1, position2)) => leaves(&position1) || leaves(&position2),
+                Some(Action::Move { source_position, .. }) => leaves(&source_position),
+                _ => false,
+            }
+        }).count();
+        assert!(splitting > n_proposals * 8 / 10);
+    }
+
+    #[test]
+    fn test_strict_feasible_never_leaves_feasibility() {
+        let mut table = table_fixture();
+        for group in &mut table.groups {
+            group.members[0].add_tag("a");
+        }
+        let condition = Condition {
+            constraint: Constraint([("a".to_string(), Range::Count { min: 1, max: 1 })].into()),
+            ..condition_fixture()
+        };
+        assert!(TableCache::create(&table, &condition.penalty).is_feasible(&condition));
+        let params = Params { temperature: 100.0, max_iterations: 500, strict_feasible: true, ..Default::default() };
+        let mut annealer = Annealer::new(&table, &condition, &params);
+        annealer.set_generator(Box::new(MixedGenerator::new(vec![
+            (Box::new(SwapGenerator::new()), 1.0),
+            (Box::new(MoveGenerator), 1.0),
+        ])));
+        let mut n_accepted = 0;
+        while let Some(step) = annealer.next() {
+            if step.accepted {
+                n_accepted += 1;
+                assert!(annealer.state.cache.is_feasible(&condition));
+            }
+        }
+        assert!(n_accepted > 0);
+        assert!(TableCache::create(annealer.best_table(), &condition.penalty).is_feasible(&condition));
+    }
+
+    #[test]
+    fn test_tabu_check_reports_transition() {
+        let mut cache = TableCache::create(&table_fixture(), &condition_fixture().penalty);
+        let mut tabu = TabuList::new(2);
+        let away = Action::Move { source_position: Position::new(0, 0), target_group: 1 };
+        tabu.record(&away, &cache, 0);
+        cache.act(away, &condition_fixture()).unwrap();
+        let back = Action::Move { source_position: Position::new(1, 3), target_group: 0 };
+        assert_eq!(tabu.check(&back, &cache), Err(ActionError::Tabu { id: 0, group_index: 0 }));
+        tabu.expire(2);
+        assert_eq!(tabu.check(&back, &cache), Ok(()));
+    }
+
+    #[test]
+    fn test_canonicalize_sorts_members() {
+        // Pinned anchors fix the group of every triple, so every good run reaches one membership.
+        let triples = [[0, 4, 8], [3, 7, 2], [6, 1, 5]];
+        let mut penalty = RelationPenalty::new(10 as Score);
+        for triple in triples {
+            penalty.add_pair(triple[0], triple[1], 0 as Score);
+            penalty.add_pair(triple[0], triple[2], 0 as Score);
+            penalty.add_pair(triple[1], triple[2], 0 as Score);
+        }
+        let condition = Condition { penalty, pinned: [0, 3, 6].into_iter().collect(), ..Default::default() };
+        let start = Table::from_assignment((0..9).map(Member::new).collect(), &[0, 0, 0, 1, 1, 1, 2, 2, 2], 3).unwrap();
+        let run = |seed: u64, canonicalize: bool| {
+            let params = Params { max_iterations: 2000, seed, canonicalize, ..Default::default() };
+            anneal(&start, &condition, &params).unwrap()
+        };
+        let (first, second) = (run(0, false), run(1, false));
+        assert_eq!(first.to_assignment(), second.to_assignment());
+        assert_ne!(first, second);
+        let (first, second) = (run(0, true), run(1, true));
+        assert_eq!(format!("{first:?}"), format!("{second:?}"));
+        assert_eq!(first.groups[1].members.iter().map(|member| member.id).collect::<Vec<_>>(), vec![2, 3, 7]);
+    }
+
+    #[test]
+    fn test_step_progress() {
+        let condition = condition_fixture();
+        let params = Params { max_iterations: 300, ..Default::default() };
+        let steps: Vec<AnnealStep> = Annealer::new(&table_fixture(), &condition, &params).collect();
+        assert!(steps.windows(2).all(|pair| pair[0].progress <= pair[1].progress));
+        let last = steps.last().unwrap();
+        assert!((last.progress - 1.0).abs() < 1e-12);
+        assert!((steps[149].progress - 0.5).abs() < 1e-12);
+        #[cfg(feature = "std")]
+        {
+            let (first, rest) = steps.split_at(PACE_SAMPLE_INTERVAL - 1);
+            assert!(first.iter().all(|step| step.eta.is_none()));
+            assert!(rest.iter().all(|step| step.eta.is_some()));
+            assert_eq!(last.eta, Some(Duration::ZERO));
+        }
+        #[cfg(not(feature = "std"))]
+        assert!(steps.iter().all(|step| step.eta.is_none()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_eta_saturates_to_none() {
+        let pace = |mean| Pace { mean: Some(mean), ..Default::default() };
+        assert_eq!(pace(Duration::from_millis(2)).eta(500), Some(Duration::from_secs(1)));
+        assert_eq!(pace(Duration::from_secs(10)).eta(usize::MAX), None);
+        assert_eq!(Pace::default().eta(1), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_cancel_stops_run() {
+        let condition = condition_fixture();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let params = Params { max_iterations: usize::MAX, cancel: Some(cancel.clone()), ..Default::default() };
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            cancel.store(true, Ordering::Relaxed);
+        });
+        let started = std::time::Instant::now();
+        let mut annealer = Annealer::new(&table_fixture(), &condition, &params);
+        let steps = annealer.by_ref().count();
+        canceller.join().unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        assert!(steps > 0);
+        assert_eq!(annealer.next(), None);
+        assert_eq!(annealer.best_score(), 0 as Score);
+    }
+
+    #[test]
+    fn test_custom_objective() {
+        let mut table = table_fixture();
+        for member in &mut table.groups[0].members {
+            member.add_tag("engineer");
+        }
+        let crowded_groups = |cache: &TableCache| {
+            let mut engineers = vec![0; cache.sizes().len()];
+            for position in cache.positions_with_tag(&"engineer".to_string()) {
+                engineers[position.group_index] += 1;
+            }
+            engineers.iter().filter(|count| **count > 1).count() as Score
+        };
+        // Without pair penalties the effective score is flat, so only the custom objective moves
+        // the engineers apart.
+        let condition = Condition { penalty: RelationPenalty::new(0 as Score), ..Default::default() };
+        let params = Params { max_iterations: 500, ..Default::default() };
+        let mut annealer = Annealer::new(&table, &condition, &params);
+        annealer.set_objective(Objective::Custom(Box::new(crowded_groups)));
+        assert_eq!(annealer.best_score(), 1 as Score);
+        annealer.by_ref().for_each(drop);
+        assert_eq!(annealer.best_score(), 0 as Score);
+        let best = TableCache::create(annealer.best_table(), &condition.penalty);
+        assert_eq!(crowded_groups(&best), 0 as Score);
+        assert_eq!(best.sizes(), vec![3, 3, 3]);
+    }
+
+    #[test]
+    fn test_normalized_acceptance_ignores_penalty_scale() {
+        let members: Vec<Member> = (0..12).map(Member::new).collect();
+        let table = Table::from_assignment(members, &[0, 1, 2, 3].repeat(3), 4).unwrap();
+        let condition = |factor: Score| {
+            let mut penalty = RelationPenalty::new(0 as Score);
+            for a in 0..12 {
+                for b in a + 1..12 {
+                    penalty.add_pair(a, b, factor * ((a * 7 + b * 13) % 10) as Score);
+                }
+            }
+            Condition { penalty, ..Default::default() }
+        };
+        let acceptance_rate = |factor: Score, temperature: f64, normalize_acceptance: bool| {
+            let condition = condition(factor);
+            let params = Params { temperature, max_iterations: 3000, normalize_acceptance, seed: 5, ..Default::default() };
+            let steps: Vec<AnnealStep> = Annealer::new(&table, &condition, &params).collect();
+            steps.iter().filter(|step| step.accepted).count() as f64 / steps.len() as f64
+        };
+        let (small, large) = (acceptance_rate(1.0, 0.05, true), acceptance_rate(10.0, 0.05, true));
+        assert!((small - large).abs() < 0.02, "acceptance rates {small} and {large}");
+        let (small, large) = (acceptance_rate(1.0, 5.0, false), acceptance_rate(10.0, 5.0, false));
+        assert!(small > large + 0.05, "acceptance rates {small} and {large}");
+    }
+
+    #[test]
+    fn test_staged_sacrifices_lower_priority_constraint() {
+        // With at most one "a" and one "b" per pair, both "ab" members pair with an untagged
+        // member, which leaves the two "c" members together.
+        let tags: [&[&str]; 6] = [&[], &[], &["a", "b"], &["a", "b"], &["a", "c"], &["b", "c"]];
+        let members = tags.iter().enumerate().map(|(id, tags)| Member::with_tags(id as Id, tags.iter().copied())).collect();
+        let table = Table::from_assignment(members, &[2, 2, 0, 0, 1, 1], 3).unwrap();
+        let condition = Condition {
+            constraint: Constraint(["a", "b", "c"].into_iter().map(|tag| (tag.to_string(), Range::AtMost(1))).collect()),
+            constraint_priorities: [("c".to_string(), 1)].into(),
+            constraint_penalty: 1 as Score,
+            ..Default::default()
+        };
+        let params = Params { max_iterations: 2000, seed: 0, ..Default::default() };
+        assert!(!TableCache::create(&anneal(&table, &condition, &params).unwrap(), &condition.penalty).is_feasible(&condition));
+
+        let (best, sacrificed) = anneal_staged(&table, &condition, &params).unwrap();
+        assert_eq!(sacrificed, vec!["c".to_string()]);
+        let priority_0 = Condition { constraint: Constraint(condition.constraint.0.iter()
+            .filter(|(tag, _)| *tag != "c").map(|(tag, range)| (tag.clone(), range.clone())).collect()), ..condition.clone() };
+        assert!(TableCache::create(&best, &priority_0.penalty).is_feasible(&priority_0));
+    }
+
+    #[test]
+    fn test_staged_judges_stages_by_their_tags() {
+        // Three "a" members cannot spread over two groups, and group 0 cannot shrink to its
+        // capacity under swaps, yet neither costs a tag: priority 0 is kept and "c" is judged
+        // on its own violations.
+        let tags: [&[&str]; 4] = [&["a", "c"], &["a", "c"], &["a"], &[]];
+        let members = tags.iter().enumerate().map(|(id, tags)| Member::with_tags(id as Id, tags.iter().copied())).collect();
+        let table = Table::from_assignment(members, &[0, 0, 1, 1], 2).unwrap();
+        let condition = Condition {
+            constraint: Constraint(["a", "c"].into_iter().map(|tag| (tag.to_string(), Range::AtMost(1))).collect()),
+            constraint_priorities: [("c".to_string(), 1)].into(),
+            capacities: vec![Range::Exact(1)],
+            constraint_penalty: 1 as Score,
+            ..Default::default()
+        };
+        let params = Params { max_iterations: 500, seed: 0, ..Default::default() };
+        let (best, sacrificed) = anneal_staged(&table, &condition, &params).unwrap();
+        assert_eq!(sacrificed, Vec::<Tag>::new());
+        let assignment = best.to_assignment();
+        assert_ne!(assignment[&0], assignment[&1]);
+    }
 }
@@ -0,0 +1,369 @@
+use alloc::{vec, vec::Vec};
+use itertools::Itertools;
+
+use rand::seq::SliceRandom;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::model::entity::{Member, Tag};
+use crate::model::group::{check_unique_ids, Group, Table};
+use crate::model::condition::{Condition, Score, Range};
+use crate::action::{Action, ActionError, ActionResult, Index};
+use crate::cache::TableCache;
+use crate::validate::Infeasibility;
+use crate::error::ShuffleError;
+
+
+/// Deterministic baseline: inserts `members` in order, each into the group where `simulate_add`
+/// reports the smallest score delta among the groups whose number of violated tag constraints
+/// grows the least (so feasible insertions come first and the least infeasible one is the fallback).
+/// Groups are capped at their capacity in `condition`, or at `ceil(members / group_count)` members
+/// to keep them balanced; once every group is full, all groups are candidates again. Remaining
+/// ties go to the lowest group index (the order of `Action::tie_break_key` for additions).
+/// Required members are inserted before optional ones, so that optional members cannot use up a
+/// `Condition::global_tag_cap`; an optional member that no group accepts is left out of the table.
+/// Fails with `AssignmentError::DuplicateIds` if two members share an id, with
+/// `Infeasibility::NoGroups` for 0 groups, and with the error `simulate` reports if a required
+/// member cannot be added to any group.
+pub fn greedy_assign(members: Vec<Member>, condition: &Condition, group_count: usize) -> Result<Table, ShuffleError> {
+    check_unique_ids(&members)?;
+    if group_count == 0 {
+        return Err(ShuffleError::Infeasible(vec![Infeasibility::NoGroups { members: members.len() }]));
+    }
+    let balanced = members.len().div_ceil(group_count);
+    let capacities: Vec<usize> = (0..group_count)
+        .map(|group_index| condition.max_group_size(group_index).unwrap_or(balanced))
+        .collect();
+    let empty = Table { groups: (0..group_count).map(|_| Group { members: Vec::new() }).collect(), ..Default::default() };
+    let mut cache = TableCache::create(&empty, &condition.penalty);
+    let (required, optional): (Vec<Member>, Vec<Member>) = members.into_iter().partition(|member| !member.optional);
+    for member in required.into_iter().chain(optional) {
+        let sizes = cache.sizes();
+        let mut best: Option<(isize, Score, Index)> = None;
+        let mut error = None;
+        let mut open: Vec<Index> = (0..group_count).filter(|index| sizes[*index] < capacities[*index]).collect();
+        if open.is_empty() {
+            open = (0..group_count).collect();
+        }
+        for group_index in open {
+            let before = cache.group_violations(group_index, condition).unwrap();
+            let after = cache.group_violations_after_add(group_index, &member, condition).unwrap();
+            let action = Action::Add { member: member.clone(), group_index };
+            let score = match cache.simulate(&action, condition) {
+                ActionResult::ScoreDiff(score) | ActionResult::UnsatisfiedScoreDiff(score) => score,
+                ActionResult::Failed(errors) => {
+                    error = errors.into_iter().next();
+                    continue;
+                }
+            };
+            let candidate = (after as isize - before as isize, score, group_index);
+            if best.as_ref().is_none_or(|best| (candidate.0, candidate.1) < (best.0, best.1)) {
+                best = Some(candidate);
+            }
+        }
+        let Some((_, _, group_index)) = best else {
+            if member.optional {
+                continue;
+            }
+            return Err(error.unwrap_or(ActionError::InvalidPosition).into());
+        };
+        cache.act(Action::Add { member, group_index }, condition)?;
+    }
+    Ok(cache.to_table())
+}
+
+/// Shuffles `members` with a generator seeded by `seed` and deals them round-robin into
+/// `group_count` groups, so sizes differ by at most one. Conditions are ignored; this is a
+/// starting point for annealing, not a solution.
+pub fn random_assignment(mut members: Vec<Member>, group_count: usize, seed: u64) -> Table {
+    assert!(group_count > 0);
+    members.shuffle(&mut SmallRng::seed_from_u64(seed));
+    let mut groups: Vec<Group> = (0..group_count).map(|_| Group { members: Vec::new() }).collect();
+    for (index, member) in members.into_iter().enumerate() {
+        groups[index % group_count].members.push(member);
+    }
+    Table { groups, ..Default::default() }
+}
+
+
+/// Number of placements `feasible_assignment` tries before giving up.
+const FEASIBLE_ASSIGNMENT_STEPS: usize = 100_000;
+
+/// Places `members` into `group_count` groups of balanced sizes (as in `random_assignment`) so
+/// that every group satisfies the tag constraint of `condition`, by depth-first search with
+/// pruning. Returns `None` when no such table exists, when the balanced sizes fall outside the
+/// group capacities, or when the search exceeds a fixed budget of placements. Penalties and the
+/// table-level terms (`global_tag_cap`, `tag_spread`) are ignored; anneal the result to optimize.
+/// `Range::RelativeTo` constraints only prune by group size and are checked on complete tables.
+pub fn feasible_assignment(members: Vec<Member>, condition: &Condition, group_count: usize) -> Option<Table> {
+    assert!(group_count > 0);
+    let targets: Vec<usize> = (0..group_count)
+        .map(|group_index| members.len() / group_count + usize::from(group_index < members.len() % group_count))
+        .collect();
+    if !targets.iter().enumerate().all(|(group_index, size)| condition.allows_size(group_index, *size)) {
+        return None;
+    }
+    let mut tags: Vec<(&Tag, &Range)> = condition.constraint.0.iter().sorted_by(|(tag1, _), (tag2, _)| tag1.cmp(tag2)).collect();
+    // Reference tags of relative ranges are counted too, without bounds of their own.
+    let unbounded = Range::AtLeast(0);
+    let references: Vec<&Tag> = tags.iter()
+        .filter_map(|(_, range)| match range {
+            Range::RelativeTo { tag, .. } => Some(tag),
+            _ => None,
+        })
+        .filter(|tag| !condition.constraint.0.contains_key(*tag))
+        .sorted()
+        .dedup()
+        .collect();
+    tags.extend(references.into_iter().map(|tag| (tag, &unbounded)));
+    let relative: Vec<(usize, usize)> = tags.iter().enumerate()
+        .filter_map(|(t, (_, range))| match range {
+            Range::RelativeTo { tag, .. } => Some((t, tags.iter().position(|(other, _)| *other == tag)?)),
+            _ => None,
+        })
+        .collect();
+    // Members with the most constrained tags are placed first, where they prune the most.
+    let members: Vec<Member> = members.into_iter()
+        .sorted_by_key(|member| core::cmp::Reverse(tags.iter().filter(|(tag, _)| member.has_tag(tag)).count()))
+        .collect();
+    let member_tags: Vec<Vec<usize>> = members.iter()
+        .map(|member| (0..tags.len()).filter(|t| member.has_tag(tags[*t].0)).collect())
+        .collect();
+    let mut packing = Packing {
+        bounds: targets.iter().map(|size| tags.iter().map(|(_, range)| range.count_bounds(*size)).collect()).collect(),
+        supply: tags.iter().map(|(tag, _)| members.iter().filter(|member| member.has_tag(tag)).count()).collect(),
+        counts: vec![vec![0; tags.len()]; group_count],
+        sizes: vec![0; group_count],
+        relative: relative.into_iter().map(|(t, reference)| (t, reference, tags[t].1.clone())).collect(),
+        targets,
+        member_tags,
+        placement: Vec::new(),
+        steps: 0,
+    };
+    if !packing.place() {
+        return None;
+    }
+    let mut groups: Vec<Group> = (0..group_count).map(|_| Group { members: Vec::new() }).collect();
+    for (member, group_index) in members.into_iter().zip(packing.placement) {
+        groups[group_index].members.push(member);
+    }
+    Some(Table { groups, ..Default::default() })
+}
+
+/// Search state of `feasible_assignment`; tags are indexed in the order of its sorted tag list.
+struct Packing {
+    targets: Vec<usize>,
+    /// `(min, max)` count of every tag in every group, at the group's target size.
+    bounds: Vec<Vec<(usize, usize)>>,
+    member_tags: Vec<Vec<usize>>,
+    /// Tag, reference tag and range of every `Range::RelativeTo` constraint.
+    relative: Vec<(usize, usize, Range)>,
+    /// Members carrying each tag that are not placed yet.
+    supply: Vec<usize>,
+    counts: Vec<Vec<usize>>,
+    sizes: Vec<usize>,
+    /// Group of every member placed so far, in placement order.
+    placement: Vec<Index>,
+    steps: usize,
+}
+
+impl Packing {
+    /// Places the next member and everyone after it, undoing its own changes on failure.
+    fn place(&mut self) -> bool {
+        let member_index = self.placement.len();
+        if member_index == self.member_tags.len() {
+            return self.relative_satisfied();
+        }
+        let tags = self.member_tags[member_index].clone();
+        for group_index in 0..self.targets.len() {
+            // Empty groups of equal size are interchangeable, so only the first one is tried.
+            let interchangeable = self.sizes[group_index] == 0 && (0..group_index).any(|other| {
+                self.sizes[other] == 0 && self.targets[other] == self.targets[group_index]
+            });
+            if self.sizes[group_index] == self.targets[group_index] || interchangeable {
+                continue;
+            }
+            if tags.iter().any(|t| self.counts[group_index][*t] == self.bounds[group_index][*t].1) {
+                continue;
+            }
+            self.steps += 1;
+            if self.steps > FEASIBLE_ASSIGNMENT_STEPS {
+                return false;
+            }
+            self.apply(group_index, &tags, true);
+            self.placement.push(group_index);
+            if self.can_complete() && self.place() {
+                return true;
+            }
+            self.placement.pop();
+            self.apply(group_index, &tags, false);
+            if self.steps > FEASIBLE_ASSIGNMENT_STEPS {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn apply(&mut self, group_index: Index, tags: &[usize], entering: bool) {
+        if entering {
+            self.sizes[group_index] += 1;
+        } else {
+            self.sizes[group_index] -= 1;
+        }
+        for t in tags {
+            if entering {
+                self.counts[group_index][*t] += 1;
+                self.supply[*t] -= 1;
+            } else {
+                self.counts[group_index][*t] -= 1;
+                self.supply[*t] += 1;
+            }
+        }
+    }
+
+    /// Whether every complete group holds its relative ranges.
+    fn relative_satisfied(&self) -> bool {
+        self.relative.iter().all(|(t, reference, range)| {
+            (0..self.targets.len()).all(|group_index| {
+                let (min, max) = range.relative_bounds(self.targets[group_index], self.counts[group_index][*reference]);
+                (min..=max).contains(&self.counts[group_index][*t])
+            })
+        })
+    }
+
+    /// Whether the unplaced members carrying each tag can still bring every group into range:
+    /// each group must be able to reach its minimum, and the groups together must have room
+    /// below their maxima for all of them.
+    fn can_complete(&self) -> bool {
+        (0..self.supply.len()).all(|t| {
+            let mut deficit = 0;
+            let mut room = 0;
+            for group_index in 0..self.targets.len() {
+                let (min, max) = self.bounds[group_index][t];
+                let count = self.counts[group_index][t];
+                let free = self.targets[group_index] - self.sizes[group_index];
+                if count + free.min(self.supply[t]) < min {
+                    return false;
+                }
+                deficit += min.saturating_sub(count);
+                room += free.min(max.saturating_sub(count));
+            }
+            deficit <= self.supply[t] && self.supply[t] <= room
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::vec;
+    use super::*;
+    use crate::model::condition::{RelationPenalty, Constraint, Range};
+    use crate::validate::validate;
+    use crate::anneal::{solve, Params};
+
+    fn members_fixture() -> Vec<Member> {
+        ["a", "a", "b", "b", "c", "c"].iter().enumerate()
+            .map(|(id, tag)| Member::with_tags(id as u32, [*tag]))
+            .collect()
+    }
+
+    fn condition_fixture() -> Condition {
+        Condition {
+            penalty: RelationPenalty::new(1 as Score),
+            constraint: Constraint(
+                [
+                    ("a".to_string(), Range::Count { min: 1, max: 1 }),
+                    ("b".to_string(), Range::Count { min: 1, max: 1 }),
+                    ("c".to_string(), Range::Count { min: 1, max: 1 }),
+                ].into()
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_greedy_assign_is_feasible() {
+        let condition = condition_fixture();
+        let table = greedy_assign(members_fixture(), &condition, 2).unwrap();
+        let cache = TableCache::create(&table, &condition.penalty);
+        assert!(cache.is_feasible(&condition));
+        assert_eq!(cache.sizes(), vec![3, 3]);
+        assert_eq!(cache.penalty_score, 6 as Score);
+    }
+
+    #[test]
+    fn test_greedy_assign_prefers_low_penalty() {
+        let mut condition = Condition { penalty: RelationPenalty::new(0 as Score), ..Default::default() };
+        condition.penalty.scores.insert([0, 1].into(), 10 as Score);
+        condition.penalty.scores.insert([2, 3].into(), 10 as Score);
+        let table = greedy_assign(members_fixture(), &condition, 2).unwrap();
+        assert_eq!(TableCache::create(&table, &condition.penalty).penalty_score, 0 as Score);
+    }
+
+    #[test]
+    fn test_greedy_assign_leaves_out_optional_members_over_a_cap() {
+        let mut members = members_fixture();
+        members[0].optional = true;
+        let condition = Condition { global_tag_cap: [("a".to_string(), 1)].into(), ..Default::default() };
+        assert_eq!(validate(&members, &condition, 2), Ok(()));
+        let table = greedy_assign(members.clone(), &condition, 2).unwrap();
+        let ids: Vec<_> = table.iter_members().map(|member| member.id).sorted().collect();
+        assert_eq!(ids, (1..6).collect::<Vec<_>>());
+        let params = Params { max_iterations: 100, ..Default::default() };
+        assert_eq!(solve(members, &condition, 2, &params).unwrap().iter_members().filter(|member| member.has_tag("a")).count(), 1);
+    }
+
+    #[test]
+    fn test_random_assignment() {
+        let table = random_assignment(members_fixture(), 4, 7);
+        assert_eq!(table, random_assignment(members_fixture(), 4, 7));
+        assert_eq!(table.group_sizes(), vec![2, 2, 1, 1]);
+        let mut ids: Vec<_> = table.iter_members().map(|member| member.id).collect();
+        ids.sort();
+        assert_eq!(ids, (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_greedy_assign_respects_capacities() {
+        let condition = Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            capacities: vec![Range::AtMost(2), Range::AtMost(4)],
+            ..Default::default()
+        };
+        let table = greedy_assign(members_fixture(), &condition, 2).unwrap();
+        assert_eq!(TableCache::create(&table, &condition.penalty).sizes(), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_feasible_assignment() {
+        let members: Vec<Member> = ["a", "a", "b", "b", "c", "c"].iter().enumerate()
+            .map(|(id, tag)| Member::with_tags(id as u32, [*tag]))
+            .collect();
+        let condition = condition_fixture();
+        let table = feasible_assignment(members.clone(), &condition, 2).unwrap();
+        let cache = TableCache::create(&table, &condition.penalty);
+        assert!(cache.is_feasible(&condition));
+        assert_eq!(table.member_count(), 6);
+
+        // Three groups need three "a" members but there are only two.
+        assert_eq!(feasible_assignment(members, &condition, 3), None);
+    }
+
+    #[test]
+    fn test_feasible_assignment_relative() {
+        // Two seniors and four juniors: two groups, each with one senior and two juniors.
+        let members: Vec<Member> = ["senior", "senior", "junior", "junior", "junior", "junior"].iter().enumerate()
+            .map(|(id, tag)| Member::with_tags(id as u32, [*tag]))
+            .collect();
+        let relative = Range::RelativeTo { tag: "senior".to_string(), min_ratio: 0.0, max_ratio: 2.0 };
+        let condition = Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            constraint: Constraint([("junior".to_string(), relative)].into()),
+            ..Default::default()
+        };
+        let table = feasible_assignment(members, &condition, 2).unwrap();
+        assert!(TableCache::create(&table, &condition.penalty).is_feasible(&condition));
+    }
+}
@@ -1,26 +1,47 @@
-use std::collections::{HashMap, HashSet};
-use std::ops::{Add, Sub};
+use core::ops::{Add, Sub};
+use alloc::{vec, vec::Vec, string::String, sync::Arc};
+
+use crate::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 use itertools::Itertools;
 
 use crate::model::entity::{Id, Tag, Member};
-use crate::model::group::{Group, Table};
-use crate::model::condition::{RelationPenalty, Constraint, Condition, Score, Range};
+use crate::model::group::{group_name, AssignmentError, Group, Table};
+use crate::model::condition::{RelationPenalty, Constraint, Condition, Score, Range, ViolationScaling, GroupScoreMode};
 use crate::action::{Index, Action, ActionResult, ActionError, Position};
 
 
+/// Penalty of a pair of members, scaled by the product of their weights.
+pub(crate) fn pair_score(penalty: &RelationPenalty, a: &Member, b: &Member) -> Score {
+    penalty.get_pair_for(a, b) * a.weight * b.weight
+}
+
 impl Group {
-    fn calc_score(&self, penalty: &RelationPenalty) -> Score {
+    /// Sum of the penalties of every pair of members, which takes O(n²) lookups for n members.
+    pub(crate) fn calc_score(&self, penalty: &RelationPenalty) -> Score {
         self.members.iter().combinations(2).map(|pair| {
-            let ids = [pair[0].id, pair[1].id];
-            penalty.get_pair(ids)
+            pair_score(penalty, pair[0], pair[1])
         }).sum()
     }
+
+    /// Pair penalty of the group combined as set by `Condition::group_score_mode`.
+    pub fn aggregate_score(&self, condition: &Condition) -> Score {
+        condition.aggregate_pairs(self.calc_score(&condition.penalty), self.members.iter(), |a, b| pair_score(&condition.penalty, a, b))
+    }
+}
+
+/// Largest pair penalty among `members` as scored by `score`, or 0 without pairs.
+fn max_pair_score<M: Clone>(members: impl Iterator<Item = M>, score: impl Fn(&M, &M) -> Score) -> Score {
+    members.combinations(2)
+        .map(|pair| score(&pair[0], &pair[1]))
+        .reduce(Score::max)
+        .unwrap_or(0 as Score)
 }
 
 
-#[derive(Debug, Clone)]
-struct TagCounter (HashMap<Tag, usize>);
+/// Number of members carrying each tag in a group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagCounter (HashMap<Tag, usize>);
 
 impl From<Vec<Tag>> for TagCounter {
     fn from(tags: Vec<Tag>) -> Self {
@@ -56,62 +77,430 @@ impl Sub for TagCounter {
     }
 }
 
+impl TagCounter {
+    pub fn increment(&mut self, tag: &Tag) {
+        match self.0.get_mut(tag) {
+            Some(count) => *count += 1,
+            None => {
+                self.0.insert(tag.clone(), 1);
+            }
+        }
+    }
+
+    /// Counts one member fewer carrying `tag`. Like `-`, this keeps the tag with a count of 0.
+    pub fn decrement(&mut self, tag: &Tag) {
+        if let Some(count) = self.0.get_mut(tag) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    fn add_member(&mut self, member: &Member) {
+        member.tags.iter().for_each(|tag| self.increment(tag));
+    }
+
+    fn remove_member(&mut self, member: &Member) {
+        member.tags.iter().for_each(|tag| self.decrement(tag));
+    }
+
+    fn distinct(&self) -> usize {
+        self.0.values().filter(|count| **count > 0).count()
+    }
+}
+
+impl Condition {
+    /// Whether anything in the condition depends on the tags of group members. When it does not,
+    /// group caches skip maintaining their tag counts, which are then only valid as created.
+    fn uses_tags(&self) -> bool {
+        !self.constraint.is_empty() || !self.soft_constraint.is_empty() || self.diversity_bonus != 0 as Score || !self.tag_cooccurrence.is_empty()
+            || !self.tag_spread.is_empty()
+    }
+
+    /// Pair term of a group with `members`, whose pair penalties sum to `sum` before `seat_weight`.
+    /// Pairs are scored by `score`, which agrees with `pair_score`.
+    fn aggregate_pairs<M: Clone>(&self, sum: Score, members: impl Iterator<Item = M> + Clone, score: impl Fn(&M, &M) -> Score) -> Score {
+        let n_members = members.clone().count();
+        let seated: Option<Vec<Score>> = self.seat_weight.map(|seat_weight| {
+            members.clone().enumerate().combinations(2)
+                .map(|pair| score(&pair[0].1, &pair[1].1) * seat_weight(pair[0].0, pair[1].0))
+                .collect()
+        });
+        let sum = seated.as_ref().map_or(sum, |scores| scores.iter().sum());
+        match self.group_score_mode {
+            GroupScoreMode::SumPairs => sum,
+            GroupScoreMode::MaxPair => match &seated {
+                Some(scores) => scores.iter().copied().reduce(Score::max).unwrap_or(0 as Score),
+                None => max_pair_score(members, score),
+            },
+            GroupScoreMode::MeanPair if n_members < 2 => 0 as Score,
+            GroupScoreMode::MeanPair => sum / (n_members * (n_members - 1) / 2) as Score,
+        }
+    }
+
+    /// Whether two of `members` score a pair penalty above `max_pair_penalty` by `score`, which
+    /// agrees with `pair_score`; always false without a maximum, so that the quadratic scan only
+    /// runs when asked for.
+    fn has_forbidden_pair<M: Clone>(&self, members: impl Iterator<Item = M>, score: impl Fn(&M, &M) -> Score) -> bool {
+        let Some(max) = self.max_pair_penalty else {
+            return false;
+        };
+        members.combinations(2).any(|pair| score(&pair[0], &pair[1]) > max)
+    }
+
+    /// Part of a group's effective score computed from its size alone.
+    fn size_score(&self, n_members: usize) -> Score {
+        self.size_balance * (n_members * n_members) as Score
+    }
+
+    /// Cost of the tag constraints, hard and soft, a group with `tagcounts` and `n_members` violates.
+    fn constraint_score(&self, tagcounts: &TagCounter, n_members: usize) -> Score {
+        if self.constraint_penalty == 0 as Score {
+            return 0 as Score;
+        }
+        let violations = [&self.constraint, &self.soft_constraint].into_iter()
+            .filter_map(|constraint| constraint.check_detailed(tagcounts, n_members).err())
+            .flatten();
+        self.violation_cost(violations.map(|violation| violation.distance))
+    }
+
+    /// Cost of violations lying `distances` away from their ranges.
+    fn violation_cost(&self, distances: impl Iterator<Item = usize>) -> Score {
+        let weight: usize = match self.violation_scaling {
+            ViolationScaling::Flat => distances.count(),
+            ViolationScaling::Distance => distances.sum(),
+        };
+        self.constraint_penalty * weight as Score
+    }
+
+    /// Part of a group's effective score computed from its tag counts alone.
+    fn tag_score(&self, tagcounts: &TagCounter) -> Score {
+        let cooccurrence: Score = self.tag_cooccurrence.iter()
+            .map(|(tag, score)| {
+                let count = tagcounts.0.get(tag).copied().unwrap_or(0);
+                score * (count * count.saturating_sub(1) / 2) as Score
+            })
+            .sum();
+        if self.diversity_bonus == 0 as Score {
+            return cooccurrence;
+        }
+        cooccurrence - self.diversity_bonus * tagcounts.distinct() as Score
+    }
+}
+
+/// A tag whose count in a group lies outside its required range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagViolation {
+    pub tag: Tag,
+    pub actual: usize,
+    pub range: Range,
+    /// Distance of `actual` from the nearest bound of `range`.
+    pub distance: usize,
+}
+
+/// A member leaving group `from` for group `to`; `None` stands for outside the table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub id: Id,
+    pub from: Option<Index>,
+    pub to: Option<Index>,
+    /// `TableCache::group_name` of `from` and `to`.
+    pub from_name: Option<String>,
+    pub to_name: Option<String>,
+}
+
+/// Everything an action would change, as reported by `TableCache::explain`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionExplanation {
+    /// Indices (before the action) of the groups the action touches, in ascending order.
+    pub affected_groups: Vec<Index>,
+    pub transfers: Vec<Transfer>,
+    pub score_delta: Score,
+    /// Constraints violated after the action in the groups it changes and in the tag spreads,
+    /// in ascending order.
+    pub violations: Vec<Violation>,
+}
+
+/// A constraint left violated by an action. Group indices are those after the action.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Violation {
+    /// A tag outside its range in `constraint`.
+    Tag { group_index: Index, tag: Tag },
+    /// A group size outside its capacity.
+    Capacity { group_index: Index, size: usize },
+    /// A group holding a pair above `Condition::max_pair_penalty`.
+    ForbiddenPair { group_index: Index },
+    /// A tag held by a number of groups outside its `Condition::tag_spread`.
+    Spread { tag: Tag },
+}
+
 impl Constraint {
     fn check(&self, tagcounts: &TagCounter, n_members: usize) -> Result<(), HashSet<String>> {
-        let error_tags: HashSet<String> = self.0.iter().map(|(tag, range)| {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.check_detailed(tagcounts, n_members)
+            .map_err(|violations| violations.into_iter().map(|violation| violation.tag).collect())
+    }
+
+    /// Like `check`, but reports the observed count and the required range of every violated tag,
+    /// ordered by tag.
+    pub fn check_detailed(&self, tagcounts: &TagCounter, n_members: usize) -> Result<(), Vec<TagViolation>> {
+        let mut violations: Vec<TagViolation> = self.0.iter().filter_map(|(tag, range)| {
             let count = tagcounts.0.get(tag).copied().unwrap_or(0);
-            match range {
-                Range::Ratio {min, max} => {
-                    if (count as f64) < *min * n_members as f64 || (count as f64) > *max * n_members as f64 {
-                        Option::Some(tag.clone())
-                    } else {
-                        Option::None
-                    }
-                },
-                Range::Count {min, max} => {
-                    if count < *min || count > *max {
-                        Option::Some(tag.clone())
-                    } else {
-                        Option::None
-                    }
-                },
+            let reference = match range {
+                Range::RelativeTo { tag, .. } => tagcounts.0.get(tag).copied().unwrap_or(0),
+                _ => 0,
+            };
+            let (min, max) = range.relative_bounds(n_members, reference);
+            let distance = min.saturating_sub(count).max(count.saturating_sub(max));
+            if distance > 0 {
+                Option::Some(TagViolation { tag: tag.clone(), actual: count, range: range.clone(), distance })
+            } else {
+                Option::None
             }
-        }).filter_map(|x| x).collect();
-        if error_tags.is_empty() {
+        }).collect();
+        if violations.is_empty() {
             Ok(())
         } else {
-            Err(error_tags)
+            violations.sort_by(|a, b| a.tag.cmp(&b.tag));
+            Err(violations)
+        }
+    }
+}
+
+/// Adds `diff` to the score of a simulated result.
+fn shift_score(result: ActionResult, diff: Score) -> ActionResult {
+    match result {
+        ActionResult::ScoreDiff(score) => ActionResult::ScoreDiff(score + diff),
+        ActionResult::UnsatisfiedScoreDiff(score) => ActionResult::UnsatisfiedScoreDiff(score + diff),
+        failed => failed,
+    }
+}
+
+fn count_group_violations(tagcounts: &TagCounter, group_index: Index, n_members: usize, condition: &Condition) -> usize {
+    condition.constraint.check(tagcounts, n_members).err().map_or(0, |tags| tags.len())
+        + usize::from(!condition.allows_size(group_index, n_members))
+}
+
+/// Pair scores (`pair_score`, so including weights) of a fixed list of members, stored densely
+/// and looked up by the positions of the members in that list, so a lookup is two index
+/// operations instead of building and hashing an id set. Memory grows with the square of the
+/// number of members.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairMatrix {
+    scores: Vec<Vec<Score>>,
+}
+
+impl PairMatrix {
+    pub fn new<'m>(members: impl IntoIterator<Item = &'m Member>, penalty: &RelationPenalty) -> PairMatrix {
+        let members: Vec<&Member> = members.into_iter().collect();
+        let scores = members.iter().enumerate()
+            .map(|(i, a)| members.iter().enumerate()
+                .map(|(j, b)| if i == j { 0 as Score } else { pair_score(penalty, a, b) })
+                .collect())
+            .collect();
+        PairMatrix { scores }
+    }
+
+    /// Score of the pair of the members at positions `a` and `b` of the list the matrix was
+    /// built from. Panics if either position is out of range.
+    pub fn get(&self, a: usize, b: usize) -> Score {
+        self.scores[a][b]
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+}
+
+/// `pair_score` of two entries, read from `pairs` when both are interned. The matrix of a cache
+/// lists the members in the order of its arena, so arena indices are matrix positions.
+fn entry_score(pairs: Option<&PairMatrix>, penalty: &RelationPenalty, a: Entry, b: Entry) -> Score {
+    match (pairs, a.index, b.index) {
+        (Some(pairs), Some(a), Some(b)) => pairs.get(a, b),
+        _ => pair_score(penalty, a.member, b.member),
+    }
+}
+
+/// Where a member of a cached group is stored: in the `MemberArena` of its cache, by index, or
+/// in the group itself when it entered from outside the table the cache was created from.
+#[derive(Debug, Clone)]
+enum Seat {
+    Interned(usize),
+    Owned(Member),
+}
+
+/// A member as a cache scores it: with its index in the arena of the cache, if interned there.
+#[derive(Debug, Clone, Copy)]
+struct Entry<'m> {
+    member: &'m Member,
+    index: Option<usize>,
+}
+
+/// The members of the table a `TableCache` was created from, stored once and shared by the
+/// cache, its groups and its snapshots, which refer to them by index. Actions then move indices
+/// between groups instead of cloning members and their tag sets.
+#[derive(Debug, Clone, Default)]
+struct MemberArena {
+    members: Vec<Member>,
+    indices: HashMap<Id, usize>,
+}
+
+impl MemberArena {
+    fn new(members: Vec<Member>) -> MemberArena {
+        let indices = members.iter().enumerate().map(|(index, member)| (member.id_key(), index)).collect();
+        MemberArena { members, indices }
+    }
+
+    /// Seats of the groups of `table`, whose members the arena holds in table order.
+    fn seats(table: &Table) -> Vec<Vec<Seat>> {
+        let mut start = 0;
+        table.groups.iter().map(|group| {
+            let seats = (start..start + group.members.len()).map(Seat::Interned).collect();
+            start += group.members.len();
+            seats
+        }).collect()
+    }
+
+    /// Entry of a member from outside the cache, with the index of an identical member in the arena.
+    fn lookup<'a>(&self, member: &'a Member) -> Entry<'a> {
+        let index = self.indices.get(&member.id_key()).copied().filter(|index| self.members[*index] == *member);
+        Entry { member, index }
+    }
+
+    /// Seat of `member`: its index if the arena holds an identical member, the member itself otherwise.
+    fn intern(&self, member: Member) -> Seat {
+        match self.lookup(&member).index {
+            Some(index) => Seat::Interned(index),
+            None => Seat::Owned(member),
+        }
+    }
+
+    fn get<'a>(&'a self, seat: &'a Seat) -> &'a Member {
+        self.entry(seat).member
+    }
+
+    fn entry<'a>(&'a self, seat: &'a Seat) -> Entry<'a> {
+        match seat {
+            Seat::Interned(index) => Entry { member: &self.members[*index], index: Some(*index) },
+            Seat::Owned(member) => Entry { member, index: None },
+        }
+    }
+
+    /// The member of a seat leaving the cache, cloned out of the arena if interned.
+    fn take(&self, seat: Seat) -> Member {
+        match seat {
+            Seat::Interned(index) => self.members[index].clone(),
+            Seat::Owned(member) => member,
         }
     }
 }
 
+/// One group of a `TableCache` with its cached tag counts and penalty; see `TableCache::group`.
 #[derive(Debug, Clone)]
-struct GroupCache {
-    pub members: Vec<Member>,
+pub struct GroupCache {
+    seats: Vec<Seat>,
+    /// Shared with every group of the table.
+    arena: Arc<MemberArena>,
     pub tagcounts: TagCounter,
     pub penalty_score: Score,
+    /// Shared with every group of the table and indexed like `arena`; see
+    /// `TableCache::create_with_pair_matrix`.
+    pub pairs: Option<Arc<PairMatrix>>,
+}
+
+/// Groups are equal when they hold equal members in the same order, however these are stored.
+impl PartialEq for GroupCache {
+    fn eq(&self, other: &Self) -> bool {
+        self.members().eq(other.members()) && self.tagcounts == other.tagcounts
+            && self.penalty_score == other.penalty_score && self.pairs == other.pairs
+    }
 }
 
 impl GroupCache {
-    fn create(group: &Group, penalty: &RelationPenalty) -> GroupCache {
-        let tagcounts = group.members
-            .iter()
-            .flat_map(|member| member.tags.iter().cloned()).collect::<Vec<Tag>>().into();
-        let penalty_score = group.calc_score(penalty);
-        let members = group.members.clone();
-        GroupCache { members, tagcounts, penalty_score }
+    fn create(seats: Vec<Seat>, arena: Arc<MemberArena>, pairs: Option<Arc<PairMatrix>>, penalty: &RelationPenalty) -> GroupCache {
+        let entries = || seats.iter().map(|seat| arena.entry(seat));
+        let tagcounts = entries()
+            .flat_map(|entry| entry.member.tags.iter().cloned()).collect::<Vec<Tag>>().into();
+        let penalty_score = entries().combinations(2)
+            .map(|pair| entry_score(pairs.as_deref(), penalty, pair[0], pair[1]))
+            .sum();
+        GroupCache { seats, arena, tagcounts, penalty_score, pairs }
     }
 
-    fn get_ids(&self) -> HashSet<Id> {
-        self.members.iter().map(|member| member.id).collect()
+    /// The members of the group in seat order.
+    pub fn members(&self) -> impl ExactSizeIterator<Item = &Member> + Clone + '_ {
+        self.seats.iter().map(|seat| self.arena.get(seat))
     }
 
-    fn simulate_add(&self, member: &Member, condition: &Condition) -> ActionResult {
-        let score = self.get_ids().iter()
-            .map(|id| condition.penalty.get_pair([member.id, *id]))
-            .sum();
-        let tagcounts = self.tagcounts.clone() + member.tags.iter().cloned().collect::<Vec<Tag>>().into();
-        if condition.constraint.check(&tagcounts, self.members.len() + 1).is_ok() {
+    fn member(&self, index: Index) -> Option<&Member> {
+        self.seats.get(index).map(|seat| self.arena.get(seat))
+    }
+
+    fn entries(&self) -> impl Iterator<Item = Entry<'_>> + Clone {
+        self.seats.iter().map(|seat| self.arena.entry(seat))
+    }
+
+    fn entry(&self, index: Index) -> Option<Entry<'_>> {
+        self.seats.get(index).map(|seat| self.arena.entry(seat))
+    }
+
+    /// `pair_score` of two entries, read from the pair matrix when it holds both.
+    fn pair_score(&self, penalty: &RelationPenalty, a: Entry, b: Entry) -> Score {
+        entry_score(self.pairs.as_deref(), penalty, a, b)
+    }
+
+    /// Score of the group including the terms that depend on tag counts, e.g. the diversity bonus.
+    fn effective_score(&self, condition: &Condition) -> Score {
+        self.pair_term(condition) + condition.tag_score(&self.tagcounts) + condition.size_score(self.seats.len())
+            + condition.constraint_score(&self.tagcounts, self.seats.len())
+    }
+
+    fn pair_term(&self, condition: &Condition) -> Score {
+        condition.aggregate_pairs(self.penalty_score, self.entries(), |a, b| self.pair_score(&condition.penalty, *a, *b))
+    }
+
+    /// Change of the pair term if the pair penalties changed by `sum_diff` and the group had
+    /// `entries()`, which is only listed outside `GroupScoreMode::SumPairs` or with a seat weight.
+    fn pair_term_diff<'m>(&self, sum_diff: Score, entries: impl FnOnce() -> Vec<Entry<'m>>, condition: &Condition) -> Score {
+        if condition.group_score_mode == GroupScoreMode::SumPairs && condition.seat_weight.is_none() {
+            return sum_diff;
+        }
+        condition.aggregate_pairs(self.penalty_score + sum_diff, entries().into_iter(), |a, b| self.pair_score(&condition.penalty, *a, *b))
+            - self.pair_term(condition)
+    }
+
+    /// Change of the tag- and size-dependent terms if the group had `tagcounts` and `n_members`.
+    fn group_score_diff(&self, tagcounts: &TagCounter, n_members: usize, condition: &Condition) -> Score {
+        condition.tag_score(tagcounts) - condition.tag_score(&self.tagcounts)
+            + condition.size_score(n_members) - condition.size_score(self.seats.len())
+            + condition.constraint_score(tagcounts, n_members) - condition.constraint_score(&self.tagcounts, self.seats.len())
+    }
+
+    /// Tag counts after adding `added` and removing `removed`, or `None` when `condition` does
+    /// not use tags and the current counts are good enough.
+    fn tagcounts_after(&self, added: Option<&Member>, removed: Option<&Member>, condition: &Condition) -> Option<TagCounter> {
+        condition.uses_tags().then(|| {
+            let mut tagcounts = self.tagcounts.clone();
+            added.into_iter().for_each(|member| tagcounts.add_member(member));
+            removed.into_iter().for_each(|member| tagcounts.remove_member(member));
+            tagcounts
+        })
+    }
+
+    fn simulate_add(&self, entry: Entry, condition: &Condition) -> ActionResult {
+        let tagcounts = self.tagcounts_after(Some(entry.member), None, condition);
+        let tagcounts = tagcounts.as_ref().unwrap_or(&self.tagcounts);
+        let sum_diff = self.entries()
+            .map(|other| self.pair_score(&condition.penalty, entry, other))
+            .sum::<Score>();
+        let added = || self.entries().chain([entry]).collect();
+        let score = self.pair_term_diff(sum_diff, added, condition)
+            + self.group_score_diff(tagcounts, self.seats.len() + 1, condition);
+        if condition.constraint.check(tagcounts, self.seats.len() + 1).is_ok() {
             ActionResult::ScoreDiff(score)
         } else {
             ActionResult::UnsatisfiedScoreDiff(score)
@@ -119,32 +508,43 @@ impl GroupCache {
     }
 
     fn simulate_remove(&self, index: Index, condition: &Condition) -> ActionResult {
-        if let Option::Some(member) = &self.members.get(index) {
-            let tagcounts = self.tagcounts.clone() - member.tags.iter().cloned().collect::<Vec<Tag>>().into();
-            let score = self.get_ids().iter()
-                .filter(|id| **id != member.id)
-                .map(|id| condition.penalty.get_pair([member.id, *id]))
+        if let Option::Some(entry) = self.entry(index) {
+            let tagcounts = self.tagcounts_after(None, Some(entry.member), condition);
+            let tagcounts = tagcounts.as_ref().unwrap_or(&self.tagcounts);
+            let sum_diff = - self.entries()
+                .filter(|other| other.member.id_key() != entry.member.id_key())
+                .map(|other| self.pair_score(&condition.penalty, entry, other))
                 .sum::<Score>();
-            if condition.constraint.check(&tagcounts, self.members.len() - 1).is_ok() {
-                ActionResult::ScoreDiff(-score)
+            let removed = || self.entries().enumerate()
+                .filter(|(member_index, _)| *member_index != index)
+                .map(|(_, other)| other)
+                .collect();
+            let score = self.pair_term_diff(sum_diff, removed, condition)
+                + self.group_score_diff(tagcounts, self.seats.len() - 1, condition);
+            if condition.constraint.check(tagcounts, self.seats.len() - 1).is_ok() {
+                ActionResult::ScoreDiff(score)
             } else {
-                ActionResult::UnsatisfiedScoreDiff(-score)
+                ActionResult::UnsatisfiedScoreDiff(score)
             }
         } else {
             ActionResult::Failed(vec![ActionError::InvalidPosition])
         }
     }
 
-    fn simulate_swap(&self, index: Index, member: &Member, condition: &Condition) -> ActionResult {
-        if let Option::Some(removed_member) = &self.members.get(index) {
-            let score = self.get_ids().iter()
-                .filter(|id| **id != removed_member.id)
-                .map(|id| condition.penalty.get_pair([member.id, *id]) - condition.penalty.get_pair([removed_member.id, *id]))
+    fn simulate_swap(&self, index: Index, entry: Entry, condition: &Condition) -> ActionResult {
+        if let Option::Some(removed) = self.entry(index) {
+            let sum_diff = self.entries()
+                .filter(|other| other.member.id_key() != removed.member.id_key())
+                .map(|other| self.pair_score(&condition.penalty, entry, other) - self.pair_score(&condition.penalty, removed, other))
                 .sum::<Score>();
-            let tagcounts = self.tagcounts.clone()
-                + member.tags.iter().cloned().collect::<Vec<Tag>>().into()
-                - removed_member.tags.iter().cloned().collect::<Vec<Tag>>().into();
-            if condition.constraint.check(&tagcounts, self.members.len()).is_ok() {
+            let swapped = || self.entries().enumerate()
+                .map(|(member_index, other)| if member_index == index { entry } else { other })
+                .collect();
+            let score = self.pair_term_diff(sum_diff, swapped, condition);
+            let tagcounts = self.tagcounts_after(Some(entry.member), Some(removed.member), condition);
+            let tagcounts = tagcounts.as_ref().unwrap_or(&self.tagcounts);
+            let score = score + self.group_score_diff(tagcounts, self.seats.len(), condition);
+            if condition.constraint.check(tagcounts, self.seats.len()).is_ok() {
                 ActionResult::ScoreDiff(score)
             } else {
                 ActionResult::UnsatisfiedScoreDiff(score)
@@ -154,168 +554,1041 @@ impl GroupCache {
         }
     }
 
-    fn add(&mut self, member: Member, condition: &Condition) -> Result<(), ActionError> {
-        self.tagcounts = self.tagcounts.clone() + member.tags.iter().cloned().collect::<Vec<Tag>>().into();
-        self.penalty_score += self.get_ids().iter()
-            .map(|id| condition.penalty.get_pair([member.id, *id]))
+    /// Like `simulate_swap` for two members of this group trading seats, which keeps the members,
+    /// so only a seat weight can change the score.
+    fn simulate_reorder(&self, index1: Index, index2: Index, condition: &Condition) -> ActionResult {
+        if self.seats.len() <= index1.max(index2) {
+            return ActionResult::Failed(vec![ActionError::InvalidPosition]);
+        }
+        let score = match condition.seat_weight {
+            Some(_) => {
+                let mut entries: Vec<Entry> = self.entries().collect();
+                entries.swap(index1, index2);
+                condition.aggregate_pairs(self.penalty_score, entries.into_iter(), |a, b| self.pair_score(&condition.penalty, *a, *b))
+                    - self.pair_term(condition)
+            }
+            None => 0 as Score,
+        };
+        if condition.constraint.check(&self.tagcounts, self.seats.len()).is_ok() {
+            ActionResult::ScoreDiff(score)
+        } else {
+            ActionResult::UnsatisfiedScoreDiff(score)
+        }
+    }
+
+    fn add(&mut self, seat: Seat, condition: &Condition) -> Result<(), ActionError> {
+        let entry = self.arena.entry(&seat);
+        if condition.uses_tags() {
+            self.tagcounts.add_member(entry.member);
+        }
+        self.penalty_score += self.entries()
+            .map(|other| self.pair_score(&condition.penalty, entry, other))
             .sum::<Score>();
-        self.members.push(member);
+        self.seats.push(seat);
         Ok(())
     }
 
-    fn remove(&mut self, index: Index, condition: &Condition) -> Result<Member, ActionError> {
-        if self.members.len() <= index {
+    fn remove(&mut self, index: Index, condition: &Condition) -> Result<Seat, ActionError> {
+        if self.seats.len() <= index {
             return Err(ActionError::InvalidPosition);
         }
-        let member = self.members.remove(index);
-        self.tagcounts = self.tagcounts.clone() - member.tags.iter().cloned().collect::<Vec<Tag>>().into();
-        self.penalty_score -= self.get_ids().iter()
-            .map(|id| condition.penalty.get_pair([member.id, *id]))
+        let seat = self.seats.remove(index);
+        let entry = self.arena.entry(&seat);
+        if condition.uses_tags() {
+            self.tagcounts.remove_member(entry.member);
+        }
+        self.penalty_score -= self.entries()
+            .map(|other| self.pair_score(&condition.penalty, entry, other))
             .sum::<Score>();
-        Ok(member)
+        Ok(seat)
     }
 
-    fn swap(&mut self, index: Index, member: Member, condition: &Condition) -> Result<Member, ActionError> {
-        if self.members.len() <= index {
+    fn swap(&mut self, index: Index, seat: Seat, condition: &Condition) -> Result<Seat, ActionError> {
+        if self.seats.len() <= index {
             return Err(ActionError::InvalidPosition);
         }
-        let removed_member = self.members.remove(index);
-        self.tagcounts = self.tagcounts.clone()
-            + member.tags.iter().cloned().collect::<Vec<Tag>>().into()
-            - removed_member.tags.iter().cloned().collect::<Vec<Tag>>().into();
-        self.penalty_score += self.get_ids().iter()
-            .map(|id| condition.penalty.get_pair([member.id, *id]) - condition.penalty.get_pair([removed_member.id, *id]))
+        let arena = Arc::clone(&self.arena);
+        self.account_replacement(index, arena.entry(&seat), condition);
+        Ok(core::mem::replace(&mut self.seats[index], seat))
+    }
+
+    /// Exchanges the member at `index1` of `group1` with the member at `index2` of `group2`, which
+    /// share an arena, and returns the change of the summed penalty of both groups.
+    fn exchange(group1: &mut GroupCache, index1: Index, group2: &mut GroupCache, index2: Index, condition: &Condition) -> Score {
+        let score_diff = group1.account_replacement(index1, group2.arena.entry(&group2.seats[index2]), condition)
+            + group2.account_replacement(index2, group1.arena.entry(&group1.seats[index1]), condition);
+        core::mem::swap(&mut group1.seats[index1], &mut group2.seats[index2]);
+        score_diff
+    }
+
+    /// Updates the penalty and tag counts as if `entering` took the seat at `index`, leaving the
+    /// seats themselves untouched, and returns the change of the penalty.
+    fn account_replacement(&mut self, index: Index, entering: Entry, condition: &Condition) -> Score {
+        let leaving = self.arena.entry(&self.seats[index]);
+        let diff = self.entries().enumerate()
+            .filter(|(member_index, _)| *member_index != index)
+            .map(|(_, other)| self.pair_score(&condition.penalty, entering, other) - self.pair_score(&condition.penalty, leaving, other))
             .sum::<Score>();
-        self.members.insert(index, member);
-        Ok(removed_member)
+        if condition.uses_tags() {
+            self.tagcounts.add_member(entering.member);
+            self.tagcounts.remove_member(leaving.member);
+        }
+        self.penalty_score += diff;
+        diff
+    }
+
+    /// Contribution of every pair of members to the group's penalty, largest first
+    /// (ties ordered by ids).
+    pub fn score_breakdown(&self, penalty: &RelationPenalty) -> Vec<([Id; 2], Score)> {
+        self.entries().combinations(2)
+            .map(|pair| ([pair[0].member.id, pair[1].member.id], self.pair_score(penalty, pair[0], pair[1])))
+            .sorted_by(|(ids1, score1), (ids2, score2)| score2.total_cmp(score1).then(ids1.cmp(ids2)))
+            .collect()
+    }
+
+    /// Tag constraints the group violates as it stands, from the cached tag counts.
+    pub fn constraint_status(&self, condition: &Condition) -> Result<(), Vec<TagViolation>> {
+        condition.constraint.check_detailed(&self.tagcounts, self.seats.len())
+    }
+
+    /// Pair penalty of the group computed from scratch, free of incremental rounding drift.
+    fn recompute_score(&self, penalty: &RelationPenalty) -> Score {
+        self.entries().combinations(2).map(|pair| self.pair_score(penalty, pair[0], pair[1])).sum()
     }
 
     fn to_group(&self) -> Group {
-        Group { members: self.members.clone() }
+        Group { members: self.members().cloned().collect() }
     }
 
 }
 
+/// Bounds on the tables `TableCache::create_checked` accepts, e.g. from a service scoring
+/// untrusted input. Every bound is unlimited by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// Most members of a single group. Scoring a group visits every pair of its members, so one
+    /// huge group costs quadratic time and could stall the caller.
+    pub max_group_size: Option<usize>,
+}
+
+/// Groups are shared between clones and copied on their first change (see `snapshot`).
 #[derive(Debug, Clone)]
 pub struct TableCache {
-    groups: Vec<GroupCache>,
+    groups: Vec<Arc<GroupCache>>,
     pub penalty_score: Score,
+    /// Optional members removed from the table, in the order they were removed.
+    unassigned: Vec<Member>,
+    /// `Table::labels`, kept aligned with `groups`.
+    labels: Option<Vec<String>>,
+    /// Dense pair scores shared by the groups, if the cache was created with one.
+    pairs: Option<Arc<PairMatrix>>,
+    /// Members of the table the cache was created from, shared by the groups.
+    arena: Arc<MemberArena>,
+}
+
+impl PartialEq for TableCache {
+    fn eq(&self, other: &Self) -> bool {
+        self.groups == other.groups && self.penalty_score == other.penalty_score
+            && self.unassigned == other.unassigned && self.labels == other.labels && self.pairs == other.pairs
+    }
 }
 
+/// A saved state of a `TableCache`; see `TableCache::snapshot`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSnapshot(TableCache);
+
 impl TableCache {
-    fn create(table: &Table, penalty: &RelationPenalty) -> TableCache {
-        let groups = table.groups.iter().map(|group| {
-            GroupCache::create(group, penalty)
+    /// Interns the members of `table` once; groups then hold indices into the shared members,
+    /// so that moving members between groups never clones them.
+    pub fn create(table: &Table, penalty: &RelationPenalty) -> TableCache {
+        let arena = Arc::new(MemberArena::new(table.iter_members().cloned().collect()));
+        let groups: Vec<Arc<GroupCache>> = MemberArena::seats(table).into_iter().map(|seats| {
+            Arc::new(GroupCache::create(seats, Arc::clone(&arena), None, penalty))
         }).collect();
-        let penalty_score = table.groups.iter().map(|group| {
-            group.calc_score(penalty)
-        }).sum();
-        TableCache { groups, penalty_score }
+        let penalty_score = groups.iter().map(|group| group.penalty_score).sum();
+        TableCache { groups, penalty_score, unassigned: Vec::new(), labels: table.labels.clone(), pairs: None, arena }
+    }
+
+    /// Like `create`, but also precomputes the score of every pair of members of `table` in a
+    /// `PairMatrix`, which every score the cache computes then reads instead of `penalty`. Worth
+    /// it for many members with explicit pair scores, where building id sets dominates. The
+    /// matrix lists the members in the order of the arena, so a lookup indexes it by the arena
+    /// indices of both members; members entering later that differ from every member of `table`
+    /// fall back to `penalty`. Like the cached scores, the matrix assumes that the penalty stays
+    /// the same, and `rebuild` refreshes it.
+    pub fn create_with_pair_matrix(table: &Table, penalty: &RelationPenalty) -> TableCache {
+        let mut cache = TableCache::create(table, penalty);
+        cache.set_pairs(Some(Arc::new(PairMatrix::new(table.iter_members(), penalty))));
+        cache
     }
 
-    fn get_member(&self, position: &Position) -> Option<&Member> {
-        self.groups.get(position.group_index)?.members.get(position.member_index)
+    /// Shares `pairs` with every group.
+    fn set_pairs(&mut self, pairs: Option<Arc<PairMatrix>>) {
+        for group in self.groups.iter_mut() {
+            Arc::make_mut(group).pairs.clone_from(&pairs);
+        }
+        self.pairs = pairs;
+    }
+
+    /// Like `create`, but first checks `table` against `limits`, before any pair is scored.
+    /// Fails with `AssignmentError::GroupTooLarge` for the first group above `max_group_size`.
+    pub fn create_checked(table: &Table, penalty: &RelationPenalty, limits: &Limits) -> Result<TableCache, AssignmentError> {
+        if let Some(max_size) = limits.max_group_size {
+            if let Some((group_index, group)) = table.groups.iter().enumerate().find(|(_, group)| group.members.len() > max_size) {
+                return Err(AssignmentError::GroupTooLarge { group_index, size: group.members.len(), max_size });
+            }
+        }
+        Ok(TableCache::create(table, penalty))
+    }
+
+    /// Like `create`, but scores the groups concurrently. The result is identical to `create`,
+    /// including the order of the groups.
+    #[cfg(feature = "parallel")]
+    pub fn create_parallel(table: &Table, penalty: &RelationPenalty) -> TableCache {
+        use rayon::prelude::*;
+        let arena = Arc::new(MemberArena::new(table.iter_members().cloned().collect()));
+        let groups: Vec<Arc<GroupCache>> = MemberArena::seats(table).into_par_iter()
+            .map(|seats| Arc::new(GroupCache::create(seats, Arc::clone(&arena), None, penalty)))
+            .collect();
+        let penalty_score = groups.iter().map(|group| group.penalty_score).sum();
+        TableCache { groups, penalty_score, unassigned: Vec::new(), labels: table.labels.clone(), pairs: None, arena }
+    }
+
+    /// Recomputes every group cache from `table` in place. Must be called whenever the `Table`
+    /// the cache was created from is edited directly (e.g. a member is pinned or retagged),
+    /// or the penalty changes, since the cached tag counts and scores would otherwise be stale.
+    /// The same holds when switching to a condition that uses tags after acting under one that
+    /// does not, as tag counts are then not maintained.
+    /// The pool of unassigned members is kept; the labels are taken from `table`, and a pair
+    /// matrix is recomputed for its members.
+    pub fn rebuild(&mut self, table: &Table, penalty: &RelationPenalty) {
+        self.labels.clone_from(&table.labels);
+        self.arena = Arc::new(MemberArena::new(table.iter_members().cloned().collect()));
+        self.groups.clear();
+        self.groups.extend(MemberArena::seats(table).into_iter()
+            .map(|seats| Arc::new(GroupCache::create(seats, Arc::clone(&self.arena), None, penalty))));
+        self.penalty_score = self.groups.iter().map(|group| group.penalty_score).sum();
+        if self.pairs.is_some() {
+            self.set_pairs(Some(Arc::new(PairMatrix::new(table.iter_members(), penalty))));
+        }
+    }
+
+    /// Captures the current state so that `restore` can return to it after trial actions.
+    /// Groups are shared rather than copied, and each group is only copied when an action
+    /// changes it afterwards, so snapshots of large tables are cheap.
+    pub fn snapshot(&self) -> TableSnapshot {
+        TableSnapshot(self.clone())
+    }
+
+    /// Returns to the state captured by `snapshot`, discarding every action since.
+    pub fn restore(&mut self, snapshot: TableSnapshot) {
+        *self = snapshot.0;
+    }
+
+    pub fn get_member(&self, position: &Position) -> Option<&Member> {
+        self.groups.get(position.group_index)?.member(position.member_index)
+    }
+
+    /// Positions of every member holding `tag`, in group and member order.
+    pub fn positions_with_tag(&self, tag: &Tag) -> Vec<Position> {
+        self.groups.iter().enumerate()
+            .flat_map(|(group_index, group)| group.members().enumerate()
+                .filter(|(_, member)| member.has_tag(tag))
+                .map(move |(member_index, _)| Position { group_index, member_index }))
+            .collect()
+    }
+
+    fn get_entry(&self, position: &Position) -> Option<Entry<'_>> {
+        self.groups.get(position.group_index)?.entry(position.member_index)
+    }
+
+    /// `Condition::has_forbidden_pair` with pairs read from the pair matrix when it holds both.
+    fn has_forbidden_pair<'e>(&self, entries: impl Iterator<Item = Entry<'e>>, condition: &Condition) -> bool {
+        condition.has_forbidden_pair(entries, |a, b| entry_score(self.pairs.as_deref(), &condition.penalty, *a, *b))
     }
 
     fn get_group(&self, position: &Position) -> Option<&GroupCache> {
-        self.groups.get(position.group_index)
+        self.groups.get(position.group_index).map(|group| &**group)
+    }
+
+    /// Score change of `action` without applying it. Actions leaving a group outside its
+    /// capacity in `condition`, or with a pair above `Condition::max_pair_penalty`, are reported
+    /// as unsatisfied.
+    pub fn simulate(&self, action: &Action, condition: &Condition) -> ActionResult {
+        if let Some(id) = self.pinned_member(action, condition) {
+            return ActionResult::Failed(vec![ActionError::Pinned(id)]);
+        }
+        let result = self.simulate_groups(action, condition);
+        let result = if condition.churn_penalty == 0 as Score || result.is_failed() {
+            result
+        } else {
+            shift_score(result, self.churn_diff(action, condition))
+        };
+        let result = if condition.tag_spread.is_empty() || result.is_failed() {
+            result
+        } else {
+            self.simulate_spread(result, action, condition)
+        };
+        match result {
+            result if condition.checked_scores && result.score_delta().is_some_and(|score| !score.is_finite())
+                => ActionResult::Failed(vec![ActionError::ScoreOverflow]),
+            ActionResult::ScoreDiff(score) if !self.sizes_after(action).into_iter()
+                .all(|(group_index, size)| condition.allows_size(group_index, size))
+                => ActionResult::UnsatisfiedScoreDiff(score),
+            ActionResult::ScoreDiff(score) if condition.max_pair_penalty.is_some() && self.members_after(action).into_iter()
+                .any(|(_, entries)| self.has_forbidden_pair(entries.into_iter(), condition))
+                => ActionResult::UnsatisfiedScoreDiff(score),
+            result => result,
+        }
     }
 
-    fn get_mut_group(&mut self, position: &Position) -> Option<&mut GroupCache> {
-        self.groups.get_mut(position.group_index)
+    /// First pinned member that `action` would take out of its group, found without scoring.
+    fn pinned_member(&self, action: &Action, condition: &Condition) -> Option<Id> {
+        if condition.pinned.is_empty() {
+            return None;
+        }
+        let leaving = |position: &Position, target_group: Option<Index>| {
+            self.get_member(position)
+                .filter(|member| target_group != Some(position.group_index) && condition.pinned.contains(&member.id))
+                .map(|member| member.id)
+        };
+        match action {
+            Action::Swap(position1, position2) => leaving(position1, Some(position2.group_index))
+                .or_else(|| leaving(position2, Some(position1.group_index))),
+            Action::Move { source_position, target_group } => leaving(source_position, Some(*target_group)),
+            Action::MoveBatch(moves) => moves.iter().find_map(|(position, target_group)| leaving(position, Some(*target_group))),
+            Action::Remove(position) | Action::Replace { position, .. } => leaving(position, None),
+            Action::Add { .. } | Action::CreateGroup | Action::RemoveEmptyGroup(_) | Action::Noop => None,
+        }
+    }
+
+    /// Adds the change of the tag spread cost to `result`, downgrading it to unsatisfied when
+    /// a spread is violated afterwards.
+    fn simulate_spread(&self, result: ActionResult, action: &Action, condition: &Condition) -> ActionResult {
+        let mut after: BTreeMap<Index, TagCounter> = BTreeMap::new();
+        let mut shift = |group_index: Index, member: &Member, entering: bool| {
+            let Some(group) = self.groups.get(group_index) else { return };
+            let tagcounts = after.entry(group_index).or_insert_with(|| group.tagcounts.clone());
+            if entering {
+                tagcounts.add_member(member);
+            } else {
+                tagcounts.remove_member(member);
+            }
+        };
+        let mut n_groups = self.groups.len();
+        match action {
+            Action::Swap(position1, position2) => {
+                if let (Some(member1), Some(member2)) = (self.get_member(position1), self.get_member(position2)) {
+                    shift(position1.group_index, member1, false);
+                    shift(position1.group_index, member2, true);
+                    shift(position2.group_index, member2, false);
+                    shift(position2.group_index, member1, true);
+                }
+            }
+            Action::Move { source_position, target_group } => {
+                if let Some(member) = self.get_member(source_position) {
+                    shift(source_position.group_index, member, false);
+                    shift(*target_group, member, true);
+                }
+            }
+            Action::MoveBatch(moves) => {
+                for (position, target_group) in moves {
+                    if let Some(member) = self.get_member(position) {
+                        shift(position.group_index, member, false);
+                        shift(*target_group, member, true);
+                    }
+                }
+            }
+            Action::Add { member, group_index } => shift(*group_index, member, true),
+            Action::Remove(position) => {
+                if let Some(member) = self.get_member(position) {
+                    shift(position.group_index, member, false);
+                }
+            }
+            Action::Replace { position, member } => {
+                if let Some(replaced) = self.get_member(position) {
+                    shift(position.group_index, replaced, false);
+                    shift(position.group_index, member, true);
+                }
+            }
+            Action::CreateGroup => n_groups += 1,
+            Action::RemoveEmptyGroup(_) => n_groups -= 1,
+            Action::Noop => {}
+        }
+        let before = self.spread_distances(&BTreeMap::new(), self.groups.len(), condition);
+        let distances = self.spread_distances(&after, n_groups, condition);
+        let diff = condition.violation_cost(distances.iter().map(|(_, distance)| *distance))
+            - condition.violation_cost(before.into_iter().map(|(_, distance)| distance));
+        match shift_score(result, diff) {
+            ActionResult::ScoreDiff(score) if !distances.is_empty() => ActionResult::UnsatisfiedScoreDiff(score),
+            result => result,
+        }
+    }
+
+    /// Every violated `Condition::tag_spread` with its distance from the range, with the tag
+    /// counts of some groups replaced by `replaced` and `n_groups` groups in the table.
+    fn spread_distances<'c>(
+        &self, replaced: &BTreeMap<Index, TagCounter>, n_groups: usize, condition: &'c Condition,
+    ) -> Vec<(&'c Tag, usize)> {
+        condition.tag_spread.iter()
+            .map(|(tag, range)| {
+                let count = self.groups.iter().enumerate()
+                    .filter(|(group_index, group)| {
+                        replaced.get(group_index).unwrap_or(&group.tagcounts).0.get(tag).is_some_and(|count| *count > 0)
+                    })
+                    .count();
+                (tag, range.distance(count, n_groups))
+            })
+            .filter(|(_, distance)| *distance > 0)
+            .collect()
+    }
+
+    /// Number of tags whose `Condition::tag_spread` is violated.
+    pub fn spread_violations(&self, condition: &Condition) -> usize {
+        self.spread_distances(&BTreeMap::new(), self.groups.len(), condition).len()
+    }
+
+    /// Whether adding `member` would put more members holding one of its tags into the table
+    /// than `Condition::global_tag_cap` allows.
+    fn exceeds_global_cap(&self, member: &Member, replaced: Option<&Member>, condition: &Condition) -> bool {
+        member.tags.iter().any(|tag| condition.global_tag_cap.get(tag).is_some_and(|cap| {
+            let count = self.groups.iter()
+                .flat_map(|group| group.members())
+                .filter(|other| other.has_tag(tag))
+                .count()
+                - usize::from(replaced.is_some_and(|replaced| replaced.has_tag(tag)));
+            count >= *cap
+        }))
     }
 
-    fn simulate(&self, action: &Action, condition: &Condition) -> ActionResult {
+    fn churn_score(&self, condition: &Condition) -> Score {
+        if condition.churn_penalty == 0 as Score {
+            return 0 as Score;
+        }
+        self.groups.iter().enumerate()
+            .flat_map(|(group_index, group)| group.members().map(move |member| condition.churn_cost(member.id, group_index)))
+            .sum()
+    }
+
+    /// Change of the churn cost under a valid `action`.
+    fn churn_diff(&self, action: &Action, condition: &Condition) -> Score {
+        let moved = |position: &Position, target: Index| self.get_member(position).map_or(0 as Score, |member| {
+            condition.churn_cost(member.id, target) - condition.churn_cost(member.id, position.group_index)
+        });
+        match action {
+            Action::Swap(position1, position2) => moved(position1, position2.group_index) + moved(position2, position1.group_index),
+            Action::Move { source_position, target_group } => moved(source_position, *target_group),
+            Action::MoveBatch(moves) => moves.iter().map(|(position, target_group)| moved(position, *target_group)).sum(),
+            Action::Add { member, group_index } => condition.churn_cost(member.id, *group_index),
+            Action::Remove(position) => self.get_member(position)
+                .map_or(0 as Score, |member| -condition.churn_cost(member.id, position.group_index)),
+            Action::Replace { position, member } => self.get_member(position).map_or(0 as Score, |replaced| {
+                condition.churn_cost(member.id, position.group_index) - condition.churn_cost(replaced.id, position.group_index)
+            }),
+            Action::CreateGroup | Action::Noop => 0 as Score,
+            // Every later group moves down by one index.
+            Action::RemoveEmptyGroup(removed) => self.groups.iter().enumerate().skip(removed + 1)
+                .flat_map(|(group_index, group)| group.members().map(move |member| {
+                    condition.churn_cost(member.id, group_index - 1) - condition.churn_cost(member.id, group_index)
+                }))
+                .sum(),
+        }
+    }
+
+    /// Sizes of the groups whose size may change under `action`, afterwards.
+    fn sizes_after(&self, action: &Action) -> Vec<(Index, usize)> {
+        let size = |group_index: Index| self.groups.get(group_index).map_or(0, |group| group.seats.len());
+        match action {
+            Action::Add { group_index, .. } => vec![(*group_index, size(*group_index) + 1)],
+            Action::Remove(position) => vec![(position.group_index, size(position.group_index).saturating_sub(1))],
+            Action::Swap(..) | Action::Replace { .. } | Action::RemoveEmptyGroup(_) => Vec::new(),
+            Action::Move { source_position, target_group } if source_position.group_index == *target_group => Vec::new(),
+            Action::Move { source_position, target_group } => vec![
+                (source_position.group_index, size(source_position.group_index).saturating_sub(1)),
+                (*target_group, size(*target_group) + 1),
+            ],
+            Action::MoveBatch(moves) => {
+                let mut sizes: HashMap<Index, usize> = HashMap::new();
+                for (position, target_group) in moves {
+                    *sizes.entry(position.group_index).or_insert_with(|| size(position.group_index)) -= 1;
+                    *sizes.entry(*target_group).or_insert_with(|| size(*target_group)) += 1;
+                }
+                sizes.into_iter().sorted().collect()
+            }
+            Action::CreateGroup => vec![(self.groups.len(), 0)],
+            Action::Noop => Vec::new(),
+        }
+    }
+
+    /// Index and members of every group whose membership changes under a valid `action`, afterwards.
+    fn members_after<'s>(&'s self, action: &'s Action) -> Vec<(Index, Vec<Entry<'s>>)> {
+        let group = |group_index: Index| self.groups.get(group_index).into_iter().flat_map(|group| group.entries());
+        let replaced = |position: &Position, entry: Entry<'s>| -> Vec<Entry<'s>> {
+            group(position.group_index).enumerate()
+                .map(|(member_index, other)| if member_index == position.member_index { entry } else { other })
+                .collect()
+        };
+        let without = |position: &Position| -> Vec<Entry<'s>> {
+            group(position.group_index).enumerate()
+                .filter(|(member_index, _)| *member_index != position.member_index)
+                .map(|(_, member)| member)
+                .collect()
+        };
+        match action {
+            Action::Add { member, group_index } => vec![(*group_index, group(*group_index).chain([self.arena.lookup(member)]).collect())],
+            Action::Remove(position) => vec![(position.group_index, without(position))],
+            Action::Replace { position, member } => vec![(position.group_index, replaced(position, self.arena.lookup(member)))],
+            Action::Swap(position1, position2) if position1.group_index != position2.group_index => {
+                match (self.get_entry(position1), self.get_entry(position2)) {
+                    (Some(entry1), Some(entry2)) => vec![
+                        (position1.group_index, replaced(position1, entry2)),
+                        (position2.group_index, replaced(position2, entry1)),
+                    ],
+                    _ => Vec::new(),
+                }
+            }
+            Action::Move { source_position, target_group } if source_position.group_index != *target_group => {
+                match self.get_entry(source_position) {
+                    Some(entry) => vec![
+                        (source_position.group_index, without(source_position)),
+                        (*target_group, group(*target_group).chain([entry]).collect()),
+                    ],
+                    None => Vec::new(),
+                }
+            }
+            Action::MoveBatch(moves) => {
+                let affected: BTreeSet<Index> = moves.iter()
+                    .flat_map(|(position, target_group)| [position.group_index, *target_group])
+                    .collect();
+                affected.into_iter().map(|group_index| {
+                    let staying = group(group_index).enumerate()
+                        .filter(|(member_index, _)| !moves.iter().any(|(position, _)| {
+                            position.group_index == group_index && position.member_index == *member_index
+                        }))
+                        .map(|(_, member)| member);
+                    let entering = moves.iter()
+                        .filter(|(_, target_group)| *target_group == group_index)
+                        .filter_map(|(position, _)| self.get_entry(position));
+                    (group_index, staying.chain(entering).collect())
+                }).collect()
+            }
+            Action::Swap(..) | Action::Move { .. } | Action::CreateGroup | Action::RemoveEmptyGroup(_) | Action::Noop => Vec::new(),
+        }
+    }
+
+    fn simulate_groups(&self, action: &Action, condition: &Condition) -> ActionResult {
         match action {
             Action::Add { group_index, member } => {
                 if let Option::Some(group) = self.groups.get(*group_index) {
-                    group.simulate_add(member, condition)
+                    if self.exceeds_global_cap(member, None, condition) {
+                        return ActionResult::Failed(vec![ActionError::ConstraintViolated]);
+                    }
+                    let returning = self.unassigned.iter().any(|other| other.id_key() == member.id_key());
+                    let pool_diff = if returning { -condition.unassigned_penalty } else { 0 as Score };
+                    shift_score(group.simulate_add(self.arena.lookup(member), condition), pool_diff)
                 } else {
                     ActionResult::Failed(vec![ActionError::InvalidPosition])
                 }
             }
             Action::Remove(position) => {
-                if let Option::Some(group) = self.groups.get(position.group_index) {
-                    group.simulate_remove(position.member_index, condition)
+                if let (Option::Some(group), Some(member)) = (self.groups.get(position.group_index), self.get_member(position)) {
+                    let pool_diff = if member.optional { condition.unassigned_penalty } else { 0 as Score };
+                    shift_score(group.simulate_remove(position.member_index, condition), pool_diff)
                 } else {
                     ActionResult::Failed(vec![ActionError::InvalidPosition])
                 }
             }
-            Action::Swap(position1, position2) => {
-                if let (Some(member1), Some(member2)) = (self.get_member(position1), self.get_member(position2)) {
-                    self.get_group(position1).unwrap().simulate_swap(position1.member_index, &member2, condition)
-                        + self.get_group(position2).unwrap().simulate_swap(position2.member_index, &member1, condition)
+            Action::Replace { position, member } => {
+                if let (Some(group), Some(replaced)) = (self.get_group(position), self.get_member(position)) {
+                    if self.exceeds_global_cap(member, Some(replaced), condition) {
+                        return ActionResult::Failed(vec![ActionError::ConstraintViolated]);
+                    }
+                    let returning = self.unassigned.iter().any(|other| other.id_key() == member.id_key());
+                    let pool_diff = if replaced.optional { condition.unassigned_penalty } else { 0 as Score }
+                        - if returning { condition.unassigned_penalty } else { 0 as Score };
+                    shift_score(group.simulate_swap(position.member_index, self.arena.lookup(member), condition), pool_diff)
                 } else {
                     ActionResult::Failed(vec![ActionError::InvalidPosition])
                 }
             }
-            Action::Move { source_position: from, target_group: to } => {
-                if let (Some(member), Some(group)) = (self.get_member(from), self.get_group(from)) {
-                    group.simulate_remove(from.member_index, condition)
-                        + self.groups.get(*to).unwrap().simulate_add(&member, condition)
+            Action::Swap(position1, position2) if position1.group_index == position2.group_index => {
+                match self.get_group(position1) {
+                    Some(group) => group.simulate_reorder(position1.member_index, position2.member_index, condition),
+                    None => ActionResult::Failed(vec![ActionError::InvalidPosition]),
+                }
+            }
+            Action::Swap(position1, position2) => {
+                if let (Some(entry1), Some(entry2)) = (self.get_entry(position1), self.get_entry(position2)) {
+                    self.get_group(position1).unwrap().simulate_swap(position1.member_index, entry2, condition)
+                        + self.get_group(position2).unwrap().simulate_swap(position2.member_index, entry1, condition)
                 } else {
                     ActionResult::Failed(vec![ActionError::InvalidPosition])
                 }
             }
+            Action::Move { source_position: from, target_group: to } => {
+                // The capacity of the target is checked with the other sizes in `simulate`.
+                if let (Some(entry), Some(group), Some(target)) = (self.get_entry(from), self.get_group(from), self.groups.get(*to)) {
+                    group.simulate_remove(from.member_index, condition) + target.simulate_add(entry, condition)
+                } else {
+                    ActionResult::Failed(vec![ActionError::InvalidPosition])
+                }
+            }
+            Action::CreateGroup => {
+                if condition.constraint.check(&TagCounter(HashMap::new()), 0).is_ok() {
+                    ActionResult::ScoreDiff(0 as Score)
+                } else {
+                    ActionResult::UnsatisfiedScoreDiff(0 as Score)
+                }
+            }
+            Action::MoveBatch(moves) => self.simulate_batch(moves, condition),
+            Action::Noop => ActionResult::ScoreDiff(0 as Score),
+            Action::RemoveEmptyGroup(group_index) => {
+                match self.groups.get(*group_index) {
+                    Some(group) if group.seats.is_empty() => ActionResult::ScoreDiff(0 as Score),
+                    Some(_) => ActionResult::Failed(vec![ActionError::GroupNotEmpty]),
+                    None => ActionResult::Failed(vec![ActionError::InvalidPosition]),
+                }
+            }
         }
     }
 
-    fn act(&mut self, action: Action, condition: &Condition) -> Result<Option<Member>, ActionError> {
+    /// Checks that every source of a `MoveBatch` holds a member, appears only once, and that every
+    /// target group exists.
+    fn check_batch(&self, moves: &[(Position, Index)]) -> Result<(), ActionError> {
+        let mut seen = HashSet::new();
+        for (position, target_group) in moves {
+            if self.get_member(position).is_none() || *target_group >= self.groups.len() || !seen.insert(position) {
+                return Err(ActionError::InvalidPosition);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds every group touched by the batch and sums the changes of their effective scores,
+    /// so that pairs formed or broken between moved members are accounted for exactly.
+    fn simulate_batch(&self, moves: &[(Position, Index)], condition: &Condition) -> ActionResult {
+        if let Err(error) = self.check_batch(moves) {
+            return ActionResult::Failed(vec![error]);
+        }
+        let affected: BTreeSet<Index> = moves.iter()
+            .flat_map(|(position, target_group)| [position.group_index, *target_group])
+            .collect();
+        let mut score = 0 as Score;
+        let mut feasible = true;
+        for group_index in affected {
+            let group = &self.groups[group_index];
+            let staying = group.seats.iter().enumerate()
+                .filter(|(member_index, _)| !moves.iter().any(|(position, _)| {
+                    position.group_index == group_index && position.member_index == *member_index
+                }))
+                .map(|(_, seat)| seat.clone());
+            let entering = moves.iter()
+                .filter(|(_, target_group)| *target_group == group_index)
+                .map(|(position, _)| self.groups[position.group_index].seats[position.member_index].clone());
+            let after = GroupCache::create(staying.chain(entering).collect(), Arc::clone(&self.arena), self.pairs.clone(), &condition.penalty);
+            score += after.effective_score(condition) - group.effective_score(condition);
+            feasible &= condition.constraint.check(&after.tagcounts, after.seats.len()).is_ok();
+        }
+        if feasible {
+            ActionResult::ScoreDiff(score)
+        } else {
+            ActionResult::UnsatisfiedScoreDiff(score)
+        }
+    }
+
+    /// Describes the effect of `action` without applying it. Fails with the first error `simulate`
+    /// reports when the action cannot be applied.
+    pub fn explain(&self, action: &Action, condition: &Condition) -> Result<ActionExplanation, ActionError> {
+        let score_delta = match self.simulate(action, condition) {
+            ActionResult::ScoreDiff(score) | ActionResult::UnsatisfiedScoreDiff(score) => score,
+            ActionResult::Failed(errors) => return Err(errors.into_iter().next().unwrap_or(ActionError::InvalidPosition)),
+        };
+        let member_id = |position: &Position| self.get_member(position).map(|member| member.id);
+        let transfer = |id: Id, from: Option<Index>, to: Option<Index>| Transfer {
+            id, from, to,
+            from_name: from.map(|group_index| self.group_name(group_index)),
+            to_name: to.map(|group_index| self.group_name(group_index)),
+        };
+        let transfers: Vec<Transfer> = match action {
+            Action::Swap(position1, position2) => match (member_id(position1), member_id(position2)) {
+                (Some(id1), Some(id2)) => vec![
+                    transfer(id1, Some(position1.group_index), Some(position2.group_index)),
+                    transfer(id2, Some(position2.group_index), Some(position1.group_index)),
+                ],
+                _ => Vec::new(),
+            },
+            Action::Move { source_position, target_group } => member_id(source_position)
+                .map(|id| transfer(id, Some(source_position.group_index), Some(*target_group)))
+                .into_iter().collect(),
+            Action::MoveBatch(moves) => moves.iter()
+                .filter_map(|(position, target_group)| {
+                    member_id(position).map(|id| transfer(id, Some(position.group_index), Some(*target_group)))
+                })
+                .collect(),
+            Action::Add { member, group_index } => vec![transfer(member.id, None, Some(*group_index))],
+            Action::Remove(position) => member_id(position)
+                .map(|id| transfer(id, Some(position.group_index), None))
+                .into_iter().collect(),
+            Action::Replace { position, member } => member_id(position)
+                .map(|id| vec![
+                    transfer(id, Some(position.group_index), None),
+                    transfer(member.id, None, Some(position.group_index)),
+                ])
+                .unwrap_or_default(),
+            Action::CreateGroup | Action::RemoveEmptyGroup(_) | Action::Noop => Vec::new(),
+        };
+        let affected_groups: Vec<Index> = match action {
+            Action::CreateGroup => vec![self.groups.len()],
+            Action::RemoveEmptyGroup(group_index) => vec![*group_index],
+            _ => transfers.iter().flat_map(|transfer| [transfer.from, transfer.to]).flatten().sorted().dedup().collect(),
+        };
+        let violations = self.violations_after(action, condition);
+        Ok(ActionExplanation { affected_groups, transfers, score_delta, violations })
+    }
+
+    /// Simulates a batch of candidate actions against the current state without mutating it.
+    pub fn simulate_many(&self, actions: &[Action], condition: &Condition) -> Vec<ActionResult> {
+        actions.iter().map(|action| self.simulate(action, condition)).collect()
+    }
+
+    /// Applies `action` and returns the member it took out of the table, if any. With
+    /// `Condition::checked_scores`, an action whose resulting score would not be finite fails
+    /// with `ActionError::ScoreOverflow` and leaves the cache unchanged.
+    pub fn act(&mut self, action: Action, condition: &Condition) -> Result<Option<Member>, ActionError> {
+        if condition.checked_scores {
+            let overflows = match self.simulate(&action, condition) {
+                ActionResult::Failed(errors) => errors.contains(&ActionError::ScoreOverflow),
+                result => result.score_delta().is_some_and(|score| !(self.effective_score(condition) + score).is_finite()),
+            };
+            if overflows {
+                return Err(ActionError::ScoreOverflow);
+            }
+        }
+        // `make_mut` copies a group that a snapshot still shares before changing it.
         match action {
             Action::Add { group_index, member } => {
-                let group = self.groups.get_mut(group_index).ok_or(ActionError::InvalidPosition)?;
+                let group = self.groups.get_mut(group_index).map(Arc::make_mut).ok_or(ActionError::InvalidPosition)?;
                 let prev_score = group.penalty_score;
-                group.add(member, condition)?;
+                self.unassigned.retain(|other| other.id_key() != member.id_key());
+                group.add(self.arena.intern(member), condition)?;
                 self.penalty_score += group.penalty_score - prev_score;
                 Ok(None)
             }
             Action::Remove(position) => {
-                let group = self.groups.get_mut(position.group_index).ok_or(ActionError::InvalidPosition)?;
+                let group = self.groups.get_mut(position.group_index).map(Arc::make_mut).ok_or(ActionError::InvalidPosition)?;
                 let prev_score = group.penalty_score;
-                let member = group.remove(position.member_index, condition)?;
+                let member = self.arena.take(group.remove(position.member_index, condition)?);
                 self.penalty_score += group.penalty_score - prev_score;
+                if member.optional {
+                    self.unassigned.push(member.clone());
+                }
                 Ok(Some(member))
             }
+            Action::Replace { position, member } => {
+                let group = self.groups.get_mut(position.group_index).map(Arc::make_mut).ok_or(ActionError::InvalidPosition)?;
+                let prev_score = group.penalty_score;
+                let entering_id = member.id_key();
+                let replaced = self.arena.take(group.swap(position.member_index, self.arena.intern(member), condition)?);
+                self.penalty_score += group.penalty_score - prev_score;
+                self.unassigned.retain(|other| other.id_key() != entering_id);
+                if replaced.optional {
+                    self.unassigned.push(replaced.clone());
+                }
+                Ok(Some(replaced))
+            }
             Action::Swap(position1, position2) => {
-                // check position1 is valid
                 self.get_member(&position1).ok_or(ActionError::InvalidPosition)?;
-                let member2_clone = self.get_member(&position2).ok_or(ActionError::InvalidPosition)?.clone();
-                let group1 = self.groups.get_mut(position1.group_index).ok_or(ActionError::InvalidPosition)?;
-                let mut score_diff = - group1.penalty_score;
-                let member1 = group1.swap(position1.member_index, member2_clone.clone(), condition)?;
-                score_diff += group1.penalty_score;
-                let group2 = self.groups.get_mut(position2.group_index).ok_or(ActionError::InvalidPosition)?;
-                score_diff -= group2.penalty_score;
-                group2.swap(position2.member_index, member1, condition)?;
-                score_diff += group2.penalty_score;
-                self.penalty_score += score_diff;
+                self.get_member(&position2).ok_or(ActionError::InvalidPosition)?;
+                let (first, second) = if position1.group_index <= position2.group_index {
+                    (position1, position2)
+                } else {
+                    (position2, position1)
+                };
+                if first.group_index == second.group_index {
+                    // The group keeps its members, so only the seats change.
+                    Arc::make_mut(&mut self.groups[first.group_index]).seats.swap(first.member_index, second.member_index);
+                    return Ok(None);
+                }
+                let (head, tail) = self.groups.split_at_mut(second.group_index);
+                self.penalty_score += GroupCache::exchange(
+                    Arc::make_mut(&mut head[first.group_index]), first.member_index,
+                    Arc::make_mut(&mut tail[0]), second.member_index, condition,
+                );
                 Ok(None)
             }
             Action::Move { source_position: from, target_group: to } => {
                 // check the target group exists
                 self.groups.get(to).ok_or(ActionError::InvalidPosition)?;
-                let group_from = self.groups.get_mut(from.group_index).ok_or(ActionError::InvalidPosition)?;
+                let group_from = self.groups.get_mut(from.group_index).map(Arc::make_mut).ok_or(ActionError::InvalidPosition)?;
                 let mut score_diff = - group_from.penalty_score;
                 let member = group_from.remove(from.member_index, condition)?;
                 score_diff += group_from.penalty_score;
-                let group_to = self.groups.get_mut(to).ok_or(ActionError::InvalidPosition)?;
+                let group_to = self.groups.get_mut(to).map(Arc::make_mut).ok_or(ActionError::InvalidPosition)?;
                 score_diff -= group_to.penalty_score;
-                group_to.add(member, &condition)?;
+                group_to.add(member, condition)?;
                 score_diff += group_to.penalty_score;
                 self.penalty_score += score_diff;
                 Ok(None)
             }
+            Action::CreateGroup => {
+                // Unlabeled groups take their `group_name` so the new label lands on the new group.
+                if let Some(labels) = self.labels.as_mut() {
+                    while labels.len() <= self.groups.len() {
+                        labels.push(group_name(None, labels.len()));
+                    }
+                }
+                let group = GroupCache::create(Vec::new(), Arc::clone(&self.arena), self.pairs.clone(), &condition.penalty);
+                self.groups.push(Arc::new(group));
+                Ok(None)
+            }
+            Action::Noop => Ok(None),
+            Action::RemoveEmptyGroup(group_index) => {
+                let group = self.groups.get(group_index).ok_or(ActionError::InvalidPosition)?;
+                if !group.seats.is_empty() {
+                    return Err(ActionError::GroupNotEmpty);
+                }
+                self.groups.remove(group_index);
+                if let Some(labels) = self.labels.as_mut().filter(|labels| group_index < labels.len()) {
+                    labels.remove(group_index);
+                }
+                Ok(None)
+            }
+            Action::MoveBatch(moves) => {
+                self.check_batch(&moves)?;
+                // Removing by descending member index keeps the remaining source indices valid;
+                // members then enter their targets in the order of the batch.
+                let mut removal_order: Vec<usize> = (0..moves.len()).collect();
+                removal_order.sort_by_key(|i| core::cmp::Reverse(moves[*i].0.member_index));
+                let mut removed: Vec<Option<Seat>> = vec![None; moves.len()];
+                for i in removal_order {
+                    let group = Arc::make_mut(&mut self.groups[moves[i].0.group_index]);
+                    let prev_score = group.penalty_score;
+                    removed[i] = Some(group.remove(moves[i].0.member_index, condition)?);
+                    self.penalty_score += group.penalty_score - prev_score;
+                }
+                for ((_, target_group), seat) in moves.into_iter().zip(removed) {
+                    let group = Arc::make_mut(&mut self.groups[target_group]);
+                    let prev_score = group.penalty_score;
+                    group.add(seat.expect("every source was removed"), condition)?;
+                    self.penalty_score += group.penalty_score - prev_score;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Applies `action` only if `simulate` reports that it keeps the affected groups feasible.
+    /// Otherwise the cache is left untouched.
+    pub fn apply_validated(&mut self, action: Action, condition: &Condition) -> Result<Option<Member>, ActionError> {
+        match self.simulate(&action, condition) {
+            ActionResult::ScoreDiff(_) => self.act(action, condition),
+            ActionResult::UnsatisfiedScoreDiff(_) => Err(ActionError::ConstraintViolated),
+            ActionResult::Failed(errors) => Err(errors.into_iter().next().unwrap_or(ActionError::InvalidPosition)),
+        }
+    }
+
+    /// Penalty of the table recomputed from scratch, the value `penalty_score` tracks incrementally.
+    /// Meant for checks and benchmarks rather than hot loops.
+    pub fn recompute_total(&self, penalty: &RelationPenalty) -> Score {
+        self.groups.iter().map(|group| group.recompute_score(penalty)).sum()
+    }
+
+    /// Compares the incrementally maintained penalty scores with a from-scratch recomputation and
+    /// overwrites them with the exact values when they differ by more than `epsilon`.
+    /// Returns whether a resync was necessary.
+    pub fn resync(&mut self, penalty: &RelationPenalty, epsilon: Score) -> bool {
+        let mut drifted = false;
+        for group in self.groups.iter_mut() {
+            let exact = group.recompute_score(penalty);
+            if (group.penalty_score - exact).abs() > epsilon {
+                Arc::make_mut(group).penalty_score = exact;
+                drifted = true;
+            }
+        }
+        let exact: Score = self.groups.iter().map(|group| group.penalty_score).sum();
+        if drifted || (self.penalty_score - exact).abs() > epsilon {
+            self.penalty_score = exact;
+            drifted = true;
+        }
+        drifted
+    }
+
+    /// `Table::group_name` of a group, from the labels of the cache.
+    pub fn group_name(&self, group_index: Index) -> String {
+        group_name(self.labels.as_deref(), group_index)
+    }
+
+    pub fn group(&self, group_index: Index) -> Option<&GroupCache> {
+        self.groups.get(group_index).map(|group| &**group)
+    }
+
+    /// Pair-level contributions to the penalty of one group, largest first.
+    pub fn score_breakdown(&self, group_index: Index, penalty: &RelationPenalty) -> Option<Vec<([Id; 2], Score)>> {
+        Some(self.groups.get(group_index)?.score_breakdown(penalty))
+    }
+
+    pub fn sizes(&self) -> Vec<usize> {
+        self.groups.iter().map(|group| group.seats.len()).collect()
+    }
+
+    /// Total penalty plus the tag- and size-dependent terms of every group, the cost of the
+    /// unassigned members, the churn cost and the cost of violated tag spreads. This is the quantity
+    /// whose changes `simulate` reports.
+    pub fn effective_score(&self, condition: &Condition) -> Score {
+        self.groups.iter().map(|group| group.effective_score(condition)).sum::<Score>()
+            + condition.unassigned_penalty * self.unassigned.len() as Score
+            + self.churn_score(condition)
+            + condition.violation_cost(self.spread_distances(&BTreeMap::new(), self.groups.len(), condition).into_iter()
+                .map(|(_, distance)| distance))
+    }
+
+    /// Optional members that `Action::Remove` took out of the table and no `Action::Add` has
+    /// put back yet.
+    pub fn unassigned(&self) -> &[Member] {
+        &self.unassigned
+    }
+
+    /// Tag constraints violated by one group as it stands, without simulating anything.
+    pub fn constraint_status(&self, group_index: Index, condition: &Condition) -> Option<Result<(), Vec<TagViolation>>> {
+        Some(self.groups.get(group_index)?.constraint_status(condition))
+    }
+
+    /// `constraint_status` of every group, in group order.
+    pub fn constraint_report(&self, condition: &Condition) -> Vec<(Index, Result<(), Vec<TagViolation>>)> {
+        self.groups.iter().enumerate()
+            .map(|(group_index, group)| (group_index, group.constraint_status(condition)))
+            .collect()
+    }
+
+    pub fn is_group_feasible(&self, group_index: Index, condition: &Condition) -> bool {
+        self.group_violations(group_index, condition) == Some(0)
+    }
+
+    pub fn is_feasible(&self, condition: &Condition) -> bool {
+        self.count_violations(condition) == 0
+    }
+
+    /// Number of tags violating their constraint in one group, plus one if the group's size is
+    /// outside its capacity and one if it holds a pair above `Condition::max_pair_penalty`.
+    pub fn group_violations(&self, group_index: Index, condition: &Condition) -> Option<usize> {
+        let group = self.groups.get(group_index)?;
+        Some(count_group_violations(&group.tagcounts, group_index, group.seats.len(), condition)
+            + usize::from(self.has_forbidden_pair(group.entries(), condition)))
+    }
+
+    /// Like `group_violations`, but after adding `member` to the group.
+    pub fn group_violations_after_add(&self, group_index: Index, member: &Member, condition: &Condition) -> Option<usize> {
+        let group = self.groups.get(group_index)?;
+        let mut tagcounts = group.tagcounts.clone();
+        tagcounts.add_member(member);
+        Some(count_group_violations(&tagcounts, group_index, group.seats.len() + 1, condition)
+            + usize::from(self.has_forbidden_pair(group.entries().chain([self.arena.lookup(member)]), condition)))
+    }
+
+    /// Number of `(group, tag)` pairs whose tag constraint is violated, plus the number of groups
+    /// outside their capacity and the number of violated tag spreads.
+    pub fn count_violations(&self, condition: &Condition) -> usize {
+        (0..self.groups.len()).filter_map(|group_index| self.group_violations(group_index, condition)).sum::<usize>()
+            + self.spread_violations(condition)
+    }
+
+    /// `count_violations` after `action`, computed from the groups it changes without applying
+    /// it. Meant for actions that `simulate` does not fail.
+    pub fn count_violations_after(&self, action: &Action, condition: &Condition) -> usize {
+        let mut changed: Vec<Index> = self.members_after(action).into_iter().map(|(group_index, _)| group_index).collect();
+        if let Action::RemoveEmptyGroup(group_index) = action {
+            changed.push(*group_index);
+        }
+        let unchanged: usize = (0..self.groups.len())
+            .filter(|group_index| !changed.contains(group_index))
+            .filter_map(|group_index| self.group_violations(group_index, condition))
+            .sum();
+        unchanged + self.violations_after(action, condition).len()
+    }
+
+    /// Violations after `action` in the groups it changes or creates and in the tag spreads,
+    /// in ascending order, computed without applying it.
+    fn violations_after(&self, action: &Action, condition: &Condition) -> Vec<Violation> {
+        let mut groups = self.members_after(action);
+        let n_groups = match action {
+            Action::CreateGroup => {
+                groups.push((self.groups.len(), Vec::new()));
+                self.groups.len() + 1
+            }
+            Action::RemoveEmptyGroup(_) => self.groups.len().saturating_sub(1),
+            _ => self.groups.len(),
+        };
+        let mut violations = Vec::new();
+        let mut after = BTreeMap::new();
+        for (group_index, entries) in groups {
+            let mut tagcounts = TagCounter(HashMap::new());
+            entries.iter().for_each(|entry| tagcounts.add_member(entry.member));
+            violations.extend(condition.constraint.check(&tagcounts, entries.len()).err().into_iter().flatten()
+                .map(|tag| Violation::Tag { group_index, tag }));
+            if !condition.allows_size(group_index, entries.len()) {
+                violations.push(Violation::Capacity { group_index, size: entries.len() });
+            }
+            if self.has_forbidden_pair(entries.into_iter(), condition) {
+                violations.push(Violation::ForbiddenPair { group_index });
+            }
+            after.insert(group_index, tagcounts);
         }
+        violations.extend(self.spread_distances(&after, n_groups, condition).into_iter()
+            .map(|(tag, _)| Violation::Spread { tag: tag.clone() }));
+        violations.sort();
+        violations
     }
+
     pub fn to_table(&self) -> Table {
-        Table { groups: self.groups.iter().map(|group| group.to_group()).collect() }
+        Table { groups: self.groups.iter().map(|group| group.to_group()).collect(), labels: self.labels.clone() }
+    }
+}
+
+
+/// Opt-in memoization of group scores keyed by membership, for evaluating many candidate tables
+/// (e.g. a parameter grid search) in which the same groups recur.
+/// A `ScoreCache` is tied to one penalty and assumes that each id always carries the same weight and tags.
+#[derive(Debug, Clone, Default)]
+pub struct ScoreCache {
+    scores: HashMap<BTreeSet<Id>, Score>,
+    hits: usize,
+    misses: usize,
+}
+
+impl ScoreCache {
+    pub fn new() -> ScoreCache {
+        ScoreCache::default()
+    }
+
+    pub fn group_score(&mut self, group: &Group, penalty: &RelationPenalty) -> Score {
+        let key: BTreeSet<Id> = group.members.iter().map(Member::id_key).collect();
+        if let Some(score) = self.scores.get(&key) {
+            self.hits += 1;
+            return *score;
+        }
+        self.misses += 1;
+        let score = group.calc_score(penalty);
+        self.scores.insert(key, score);
+        score
+    }
+
+    pub fn table_score(&mut self, table: &Table, penalty: &RelationPenalty) -> Score {
+        table.groups.iter().map(|group| self.group_score(group, penalty)).sum()
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeSet;
+    use alloc::string::ToString;
     use super::*;
     use crate::model::condition::Range;
 
@@ -323,20 +1596,20 @@ mod tests {
         let groups = vec![
             Group {
                 members: vec![
-                    Member { id: 0, tags: ["a".to_string()].into() },
-                    Member { id: 1, tags: ["b".to_string()].into() },
-                    Member { id: 2, tags: ["c".to_string()].into() },
+                    Member { id: 0, tags: ["a".to_string()].into(), ..Default::default() },
+                    Member { id: 1, tags: ["b".to_string()].into(), ..Default::default() },
+                    Member { id: 2, tags: ["c".to_string()].into(), ..Default::default() },
                 ],
             },
             Group {
                 members: vec![
-                    Member { id: 3, tags: ["a".to_string(), "b".to_string()].into() },
-                    Member { id: 4, tags: ["a".to_string(), "c".to_string()].into() },
-                    Member { id: 5, tags: ["b".to_string(), "c".to_string()].into() },
+                    Member { id: 3, tags: ["a".to_string(), "b".to_string()].into(), ..Default::default() },
+                    Member { id: 4, tags: ["a".to_string(), "c".to_string()].into(), ..Default::default() },
+                    Member { id: 5, tags: ["b".to_string(), "c".to_string()].into(), ..Default::default() },
                 ],
             }
         ];
-        Table { groups }
+        Table { groups, ..Default::default() }
     }
 
     fn condition_fixture() -> Condition {
@@ -351,6 +1624,7 @@ mod tests {
                     ([5, 6].into_iter().collect::<BTreeSet<Id>>(), 6 as Score),
                 ].into_iter().collect(),
                 default: 0 as Score,
+                ..Default::default()
             },
             constraint: Constraint (
                 [
@@ -358,7 +1632,8 @@ mod tests {
                     ("b".to_string(), Range::Count { min: 1, max: 2}),
                     ("c".to_string(), Range::Count { min: 1, max: 2}),
                 ].into()
-            )
+            ),
+            ..Default::default()
         }
     }
 
@@ -371,8 +1646,8 @@ mod tests {
         let table = TableCache::create(&table_fixture(), &condition_fixture().penalty);
         assert_eq!(table.groups.len(), 2);
         assert_eq!(table.penalty_score, 12 as Score);
-        assert_eq!(table.groups[0].members.len(), 3);
-        assert_eq!(table.groups[1].members.len(), 3);
+        assert_eq!(table.groups[0].members().len(), 3);
+        assert_eq!(table.groups[1].members().len(), 3);
         assert_eq!(table.groups[0].penalty_score, 3 as Score);
         assert_eq!(table.groups[1].penalty_score, 9 as Score);
     }
@@ -390,9 +1665,9 @@ mod tests {
         ];
 
         for (group_index, tags, result) in args {
-            let member = Member { id: 6, tags: tags.into_iter().collect() };
-            let action = Action::Add { group_index: group_index, member };
-            assert_eq!(table.simulate(&action, &condition), result);
+            let member = Member { id: 6, tags: tags.into_iter().collect(), ..Default::default() };
+            let action = Action::Add { group_index, member };
+            assert_eq!(table.simulate(&action, condition), result);
         };
     }
 
@@ -414,7 +1689,7 @@ mod tests {
         for (group_index, member_index, result) in args {
             let position = Position { group_index, member_index };
             let action = Action::Remove(position);
-            assert_eq!(table.simulate(&action, &condition), result);
+            assert_eq!(table.simulate(&action, condition), result);
         };
     }
 
@@ -455,7 +1730,7 @@ mod tests {
             let position = Position { group_index, member_index };
             let other_position = Position { group_index: other_group_index, member_index: other_member_index };
             let action = Action::Swap(position, other_position);
-            assert_eq!(table.simulate(&action, &condition), result);
+            assert_eq!(table.simulate(&action, condition), result);
         };
     }
 
@@ -475,8 +1750,8 @@ mod tests {
 
         for (group_index, member_index, target_group, result) in args {
             let source_position = Position { group_index, member_index };
-            let action = Action::Move{ source_position, target_group: target_group };
-            assert_eq!(table.simulate(&action, &condition), result);
+            let action = Action::Move{ source_position, target_group };
+            assert_eq!(table.simulate(&action, condition), result);
         };
     }
 
@@ -486,11 +1761,11 @@ mod tests {
         let condition = &condition_fixture();
         let action = Action::Add {
             group_index: 1,
-            member: Member { id: 6, tags: HashSet::new() },
+            member: Member { id: 6, tags: HashSet::new(), ..Default::default() },
         };
-        assert_eq!(table.act(action, &condition), Ok(None));
-        assert_eq!(table.groups[0].members.len(), 3);
-        assert_eq!(table.groups[1].members.len(), 4);
+        assert_eq!(table.act(action, condition), Ok(None));
+        assert_eq!(table.groups[0].members().len(), 3);
+        assert_eq!(table.groups[1].members().len(), 4);
         assert_eq!(table.penalty_score, 18 as Score);
     }
 
@@ -500,11 +1775,11 @@ mod tests {
         let condition = &condition_fixture();
         let action = Action::Add {
             group_index: 2,
-            member: Member { id: 6, tags: HashSet::new() },
+            member: Member { id: 6, tags: HashSet::new(), ..Default::default() },
         };
-        assert_eq!(table.act(action, &condition), Err(ActionError::InvalidPosition));
-        assert_eq!(table.groups[0].members.len(), 3);
-        assert_eq!(table.groups[1].members.len(), 3);
+        assert_eq!(table.act(action, condition), Err(ActionError::InvalidPosition));
+        assert_eq!(table.groups[0].members().len(), 3);
+        assert_eq!(table.groups[1].members().len(), 3);
         assert_eq!(table.penalty_score, 12 as Score);
     }
 
@@ -513,9 +1788,9 @@ mod tests {
         let mut table = tablecache_fixture();
         let condition = &condition_fixture();
         let action = Action::Remove(Position { group_index: 0, member_index: 0 });
-        assert_eq!(table.act(action, &condition), Ok(Some(Member { id: 0, tags: ["a".to_string()].into() })));
-        assert_eq!(table.groups[0].members.len(), 2);
-        assert_eq!(table.groups[1].members.len(), 3);
+        assert_eq!(table.act(action, condition), Ok(Some(Member { id: 0, tags: ["a".to_string()].into(), ..Default::default() })));
+        assert_eq!(table.groups[0].members().len(), 2);
+        assert_eq!(table.groups[1].members().len(), 3);
         assert_eq!(table.penalty_score, 11 as Score);
     }
 
@@ -524,9 +1799,9 @@ mod tests {
         let mut table = tablecache_fixture();
         let condition = &condition_fixture();
         let action = Action::Remove(Position { group_index: 0, member_index: 3 });
-        assert_eq!(table.act(action, &condition), Err(ActionError::InvalidPosition));
-        assert_eq!(table.groups[0].members.len(), 3);
-        assert_eq!(table.groups[1].members.len(), 3);
+        assert_eq!(table.act(action, condition), Err(ActionError::InvalidPosition));
+        assert_eq!(table.groups[0].members().len(), 3);
+        assert_eq!(table.groups[1].members().len(), 3);
         assert_eq!(table.penalty_score, 12 as Score);
     }
 
@@ -538,9 +1813,9 @@ mod tests {
             Position { group_index: 0, member_index: 0 },
             Position { group_index: 1, member_index: 0 },
         );
-        assert_eq!(table.act(action, &condition), Ok(None));
-        assert_eq!(table.groups[0].members[0], Member { id: 3, tags: ["a".to_string(), "b".to_string()].into() });
-        assert_eq!(table.groups[1].members[0], Member { id: 0, tags: ["a".to_string()].into() });
+        assert_eq!(table.act(action, condition), Ok(None));
+        assert_eq!(table.groups[0].member(0), Some(&Member { id: 3, tags: ["a".to_string(), "b".to_string()].into(), ..Default::default() }));
+        assert_eq!(table.groups[1].member(0), Some(&Member { id: 0, tags: ["a".to_string()].into(), ..Default::default() }));
         assert_eq!(table.penalty_score, 10 as Score);
     }
 
@@ -552,9 +1827,9 @@ mod tests {
             Position { group_index: 0, member_index: 0 },
             Position { group_index: 0, member_index: 3 },
         );
-        assert_eq!(table.act(action, &condition), Err(ActionError::InvalidPosition));
-        assert_eq!(table.groups[0].members[0], Member { id: 0, tags: ["a".to_string()].into() });
-        assert_eq!(table.groups[1].members[0], Member { id: 3, tags: ["a".to_string(), "b".to_string()].into() });
+        assert_eq!(table.act(action, condition), Err(ActionError::InvalidPosition));
+        assert_eq!(table.groups[0].member(0), Some(&Member { id: 0, tags: ["a".to_string()].into(), ..Default::default() }));
+        assert_eq!(table.groups[1].member(0), Some(&Member { id: 3, tags: ["a".to_string(), "b".to_string()].into(), ..Default::default() }));
         assert_eq!(table.penalty_score, 12 as Score);
     }
 
@@ -566,10 +1841,10 @@ mod tests {
             source_position: Position { group_index: 0, member_index: 0 },
             target_group: 1,
         };
-        assert_eq!(table.act(action, &condition), Ok(None));
-        assert_eq!(table.groups[0].members.len(), 2);
-        assert_eq!(table.groups[1].members.len(), 4);
-        assert_eq!(table.groups[1].members[3], Member { id: 0, tags: ["a".to_string()].into() });
+        assert_eq!(table.act(action, condition), Ok(None));
+        assert_eq!(table.groups[0].members().len(), 2);
+        assert_eq!(table.groups[1].members().len(), 4);
+        assert_eq!(table.groups[1].member(3), Some(&Member { id: 0, tags: ["a".to_string()].into(), ..Default::default() }));
         assert_eq!(table.penalty_score, 11 as Score);
     }
 
@@ -581,9 +1856,1157 @@ mod tests {
             source_position: Position { group_index: 0, member_index: 0 },
             target_group: 2,
         };
-        assert_eq!(table.act(action, &condition), Err(ActionError::InvalidPosition));
-        assert_eq!(table.groups[0].members.len(), 3);
-        assert_eq!(table.groups[1].members.len(), 3);
+        assert_eq!(table.act(action, condition), Err(ActionError::InvalidPosition));
+        assert_eq!(table.groups[0].members().len(), 3);
+        assert_eq!(table.groups[1].members().len(), 3);
+        assert_eq!(table.penalty_score, 12 as Score);
+    }
+
+    #[test]
+    fn test_simulate_group_count() {
+        let mut table = tablecache_fixture();
+        let condition = &condition_fixture();
+        assert_eq!(table.simulate(&Action::CreateGroup, condition), ActionResult::UnsatisfiedScoreDiff(0 as Score));
+        assert_eq!(table.simulate(&Action::RemoveEmptyGroup(0), condition), ActionResult::Failed(vec![ActionError::GroupNotEmpty]));
+        assert_eq!(table.simulate(&Action::RemoveEmptyGroup(2), condition), ActionResult::Failed(vec![ActionError::InvalidPosition]));
+        table.act(Action::CreateGroup, condition).unwrap();
+        assert_eq!(table.simulate(&Action::RemoveEmptyGroup(2), condition), ActionResult::ScoreDiff(0 as Score));
+    }
+
+    #[test]
+    fn test_act_create_and_fill_group() {
+        let mut table = tablecache_fixture();
+        let condition = &condition_fixture();
+        assert_eq!(table.act(Action::CreateGroup, condition), Ok(None));
+        assert_eq!(table.sizes(), vec![3, 3, 0]);
         assert_eq!(table.penalty_score, 12 as Score);
+        for group_index in [1, 0, 1] {
+            let action = Action::Move {
+                source_position: Position { group_index, member_index: 0 },
+                target_group: 2,
+            };
+            table.act(action, condition).unwrap();
+            let rebuilt = TableCache::create(&table.to_table(), &condition.penalty);
+            assert_eq!(table.penalty_score, rebuilt.penalty_score);
+        }
+        assert_eq!(table.sizes(), vec![2, 1, 3]);
+        assert_eq!(table.penalty_score, 6 as Score);
+        assert_eq!(table.act(Action::RemoveEmptyGroup(1), condition), Err(ActionError::GroupNotEmpty));
+        assert_eq!(table.sizes(), vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn test_weighted_member() {
+        let condition = &condition_fixture();
+        let mut table = table_fixture();
+        let unweighted = TableCache::create(&table, &condition.penalty);
+        table.groups[0].members[1].weight = 2.0;
+        let weighted = TableCache::create(&table, &condition.penalty);
+        assert_eq!(unweighted.groups[0].penalty_score, 3 as Score);
+        assert_eq!(weighted.groups[0].penalty_score, 6 as Score);
+        assert_eq!(weighted.groups[1].penalty_score, 9 as Score);
+
+        let remove = Action::Remove(Position { group_index: 0, member_index: 1 });
+        assert_eq!(unweighted.simulate(&remove, condition), ActionResult::UnsatisfiedScoreDiff(-3 as Score));
+        assert_eq!(weighted.simulate(&remove, condition), ActionResult::UnsatisfiedScoreDiff(-6 as Score));
+        let swap = Action::Swap(
+            Position { group_index: 0, member_index: 1 },
+            Position { group_index: 1, member_index: 0 },
+        );
+        assert_eq!(unweighted.simulate(&swap, condition), ActionResult::ScoreDiff(-4 as Score));
+        assert_eq!(weighted.simulate(&swap, condition), ActionResult::ScoreDiff(-7 as Score));
+    }
+
+    #[test]
+    fn test_apply_validated() {
+        let mut table = tablecache_fixture();
+        let condition = &condition_fixture();
+        let before = table.clone();
+        let infeasible = Action::Add {
+            group_index: 1,
+            member: Member { id: 6, tags: ["a".to_string()].into(), ..Default::default() },
+        };
+        assert_eq!(table.apply_validated(infeasible, condition), Err(ActionError::ConstraintViolated));
+        assert_eq!(table, before);
+        let invalid = Action::Remove(Position { group_index: 0, member_index: 3 });
+        assert_eq!(table.apply_validated(invalid, condition), Err(ActionError::InvalidPosition));
+        assert_eq!(table, before);
+
+        let feasible = Action::Add {
+            group_index: 0,
+            member: Member { id: 6, tags: ["a".to_string()].into(), ..Default::default() },
+        };
+        assert_eq!(table.apply_validated(feasible, condition), Ok(None));
+        assert_eq!(table.groups[0].members().len(), 4);
+    }
+
+    #[test]
+    fn test_diversity_bonus() {
+        let tagged = |id: Id, tag: &str| Member { id, tags: [tag.to_string()].into(), ..Default::default() };
+        let table = Table {
+            groups: vec![
+                Group { members: vec![tagged(0, "a"), tagged(1, "a"), tagged(2, "a")] },
+                Group { members: vec![tagged(3, "a"), tagged(4, "b"), tagged(5, "c")] },
+            ],
+            ..Default::default()
+        };
+        let condition = Condition { diversity_bonus: 1 as Score, ..Default::default() };
+        let cache = TableCache::create(&table, &condition.penalty);
+        assert_eq!(cache.groups[0].penalty_score, cache.groups[1].penalty_score);
+        assert_eq!(cache.groups[0].effective_score(&condition), -1 as Score);
+        assert_eq!(cache.groups[1].effective_score(&condition), -3 as Score);
+        assert_eq!(cache.effective_score(&condition), -4 as Score);
+
+        // a new tag only adds diversity to the monotone group
+        let add_to_monotone = Action::Add { group_index: 0, member: tagged(6, "b") };
+        let add_to_diverse = Action::Add { group_index: 1, member: tagged(6, "b") };
+        assert_eq!(cache.simulate(&add_to_monotone, &condition), ActionResult::ScoreDiff(-1 as Score));
+        assert_eq!(cache.simulate(&add_to_diverse, &condition), ActionResult::ScoreDiff(0 as Score));
+        assert_eq!(cache.simulate(&add_to_monotone, &Condition::default()), ActionResult::ScoreDiff(0 as Score));
+    }
+
+    #[test]
+    fn test_score_cache() {
+        let penalty = &condition_fixture().penalty;
+        let table = table_fixture();
+        let mut scores = ScoreCache::new();
+        assert_eq!(scores.table_score(&table, penalty), 12 as Score);
+        assert_eq!((scores.hits(), scores.misses()), (0, 2));
+
+        // the same memberships in another order are found in the cache
+        let mut reordered = table.clone();
+        reordered.groups.reverse();
+        reordered.groups[0].members.reverse();
+        assert_eq!(scores.table_score(&reordered, penalty), 12 as Score);
+        assert_eq!((scores.hits(), scores.misses()), (2, 2));
+        assert_eq!(scores.table_score(&table, penalty), TableCache::create(&table, penalty).penalty_score);
+
+        // groups are keyed by `id_key`, so retagging a member does not miss
+        let mut retagged = table.clone();
+        retagged.groups[0].members[0].add_tag("z");
+        assert_eq!(scores.group_score(&retagged.groups[0], penalty), scores.group_score(&table.groups[0], penalty));
+        assert_eq!((scores.hits(), scores.misses()), (6, 2));
+    }
+
+    #[test]
+    fn test_check_detailed() {
+        let constraint = Constraint(
+            [
+                ("a".to_string(), Range::Count { min: 2, max: 3 }),
+                ("b".to_string(), Range::Ratio { min: 0.0, max: 0.5 }),
+                ("c".to_string(), Range::Count { min: 0, max: 1 }),
+            ].into()
+        );
+        let tagcounts: TagCounter = ["a", "b", "b", "b", "c"].map(String::from).to_vec().into();
+        assert_eq!(
+            constraint.check_detailed(&tagcounts, 5),
+            Err(vec![
+                TagViolation { tag: "a".to_string(), actual: 1, range: Range::Count { min: 2, max: 3 }, distance: 1 },
+                TagViolation { tag: "b".to_string(), actual: 3, range: Range::Ratio { min: 0.0, max: 0.5 }, distance: 1 },
+            ])
+        );
+        assert_eq!(constraint.check(&tagcounts, 5), Err(["a".to_string(), "b".to_string()].into()));
+        let satisfied: TagCounter = ["a", "a", "b"].map(String::from).to_vec().into();
+        assert_eq!(constraint.check_detailed(&satisfied, 4), Ok(()));
+    }
+
+    #[test]
+    fn test_resync_corrects_accumulated_error() {
+        let condition = Condition {
+            penalty: RelationPenalty {
+                scores: [
+                    ([0, 1].into_iter().collect::<BTreeSet<Id>>(), 1e16 as Score),
+                    ([0, 4].into_iter().collect::<BTreeSet<Id>>(), 0.1 as Score),
+                    ([3, 4].into_iter().collect::<BTreeSet<Id>>(), 0.3 as Score),
+                ].into_iter().collect(),
+                default: 0.7 as Score,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut table = TableCache::create(&table_fixture(), &condition.penalty);
+        for i in 0..1000 {
+            let swap = Action::Swap(
+                Position { group_index: 0, member_index: i % 3 },
+                Position { group_index: 1, member_index: i % 2 },
+            );
+            table.act(swap, &condition).unwrap();
+        }
+        let exact = TableCache::create(&table.to_table(), &condition.penalty);
+        assert!((table.penalty_score - exact.penalty_score).abs() > 1.0);
+
+        assert!(table.resync(&condition.penalty, 1e-9));
+        assert_eq!(table, exact);
+        assert!(!table.resync(&condition.penalty, 1e-9));
+    }
+
+    #[test]
+    fn test_simulate_many() {
+        let table = tablecache_fixture();
+        let condition = &condition_fixture();
+        let actions = vec![
+            Action::Swap(Position { group_index: 0, member_index: 0 }, Position { group_index: 1, member_index: 1 }),
+            Action::Move { source_position: Position { group_index: 1, member_index: 2 }, target_group: 0 },
+            Action::Remove(Position { group_index: 0, member_index: 5 }),
+            Action::CreateGroup,
+        ];
+        let expected: Vec<ActionResult> = actions.iter().map(|action| table.simulate(action, condition)).collect();
+        assert_eq!(table.simulate_many(&actions, condition), expected);
+        assert_eq!(table.simulate_many(&[], condition), Vec::new());
+    }
+
+    #[test]
+    fn test_check_one_sided_ranges() {
+        let counts = |n: usize| -> TagCounter { vec!["a".to_string(); n].into() };
+        let check = |range: Range, n: usize| Constraint([("a".to_string(), range)].into()).check(&counts(n), 10).is_ok();
+        assert!(!check(Range::Exact(2), 1));
+        assert!(check(Range::Exact(2), 2));
+        assert!(!check(Range::Exact(2), 3));
+        assert!(check(Range::Exact(0), 0));
+        assert!(!check(Range::AtLeast(2), 1));
+        assert!(check(Range::AtLeast(2), 2));
+        assert!(check(Range::AtLeast(2), 10));
+        assert!(check(Range::AtMost(2), 0));
+        assert!(check(Range::AtMost(2), 2));
+        assert!(!check(Range::AtMost(2), 3));
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let condition = &condition_fixture();
+        let mut table = table_fixture();
+        let mut cache = TableCache::create(&table, &condition.penalty);
+        table.groups[0].members[0].tags.insert("b".to_string());
+        let moved = table.groups[1].members.remove(2);
+        table.groups[0].members.push(moved);
+        assert_ne!(cache, TableCache::create(&table, &condition.penalty));
+        cache.rebuild(&table, &condition.penalty);
+        assert_eq!(cache, TableCache::create(&table, &condition.penalty));
+        assert_eq!(cache.to_table(), table);
+    }
+
+    #[test]
+    fn test_ratio_rounding() {
+        let constraint = Constraint([("a".to_string(), Range::Ratio { min: 0.3, max: 0.5 })].into());
+        let counts = |n: usize| -> TagCounter { vec!["a".to_string(); n].into() };
+        // 0.9 rounds up to 1, 1.5 rounds down to 1.
+        assert!(constraint.check_detailed(&counts(0), 3).is_err());
+        assert!(constraint.check_detailed(&counts(1), 3).is_ok());
+        assert!(constraint.check_detailed(&counts(2), 3).is_err());
+        // Exact multiples are not lost to floating point error.
+        let tenth = Constraint([("a".to_string(), Range::Ratio { min: 0.1, max: 0.1 })].into());
+        assert!(tenth.check_detailed(&counts(3), 30).is_ok());
+        assert_eq!(Range::Ratio { min: 0.1, max: 0.7 }.count_bounds(30), (3, 21));
+    }
+
+    #[test]
+    fn test_ratio_empty_group() {
+        let empty = TagCounter::from(Vec::<Tag>::new());
+        let required = Constraint([("a".to_string(), Range::Ratio { min: 0.3, max: 0.5 })].into());
+        let optional = Constraint([("a".to_string(), Range::Ratio { min: 0.0, max: 0.5 })].into());
+        assert!(required.check_detailed(&empty, 0).is_err());
+        assert!(optional.check_detailed(&empty, 0).is_ok());
+    }
+
+    #[test]
+    fn test_relative_to() {
+        // At least one and at most two juniors per senior.
+        let relative = Range::RelativeTo { tag: "senior".to_string(), min_ratio: 1.0, max_ratio: 2.0 };
+        let constraint = Constraint([("junior".to_string(), relative)].into());
+        let counts = |seniors: usize, juniors: usize| -> TagCounter {
+            let mut tags = vec!["senior".to_string(); seniors];
+            tags.extend(vec!["junior".to_string(); juniors]);
+            tags.into()
+        };
+        assert!(constraint.check_detailed(&counts(1, 1), 2).is_ok());
+        assert!(constraint.check_detailed(&counts(1, 2), 3).is_ok());
+        assert!(constraint.check_detailed(&counts(2, 1), 3).is_err());
+        let violations = constraint.check_detailed(&counts(1, 3), 4).unwrap_err();
+        assert_eq!(violations[0].distance, 1);
+        // Without seniors no junior is allowed, and an empty group is fine.
+        assert_eq!(constraint.check_detailed(&counts(0, 1), 1).unwrap_err()[0].distance, 1);
+        assert!(constraint.check_detailed(&counts(0, 0), 0).is_ok());
+        assert!(constraint.check_detailed(&counts(3, 0), 3).is_err());
+    }
+
+    #[test]
+    fn test_move_batch() {
+        let condition = &condition_fixture();
+        let mut tablecache = tablecache_fixture();
+        tablecache.act(Action::CreateGroup, condition).unwrap();
+        let position = |group_index, member_index| Position { group_index, member_index };
+        let batch = Action::MoveBatch(vec![(position(0, 0), 1), (position(0, 2), 2)]);
+        let before = tablecache.effective_score(condition);
+        let delta = tablecache.simulate(&batch, condition).score_delta().unwrap();
+        tablecache.act(batch, condition).unwrap();
+        assert_eq!(tablecache.effective_score(condition) - before, delta);
+        assert_eq!(tablecache.penalty_score, TableCache::create(&tablecache.to_table(), &condition.penalty).penalty_score);
+        let ids = |group: &Group| group.members.iter().map(|member| member.id).collect::<Vec<Id>>();
+        let table = tablecache.to_table();
+        assert_eq!(table.groups.iter().map(ids).collect::<Vec<_>>(), vec![vec![1], vec![3, 4, 5, 0], vec![2]]);
+
+        let invalid = Action::MoveBatch(vec![(position(1, 0), 0), (position(1, 9), 2)]);
+        assert_eq!(tablecache.simulate(&invalid, condition), ActionResult::Failed(vec![ActionError::InvalidPosition]));
+        assert_eq!(tablecache.act(invalid, condition), Err(ActionError::InvalidPosition));
+        let duplicate = Action::MoveBatch(vec![(position(1, 0), 0), (position(1, 0), 2)]);
+        assert_eq!(tablecache.act(duplicate, condition), Err(ActionError::InvalidPosition));
+        assert_eq!(tablecache.to_table(), table);
+    }
+
+    #[test]
+    fn test_pair_cap() {
+        let mut penalty = RelationPenalty::new(1 as Score);
+        penalty.scores.insert([0, 1].into(), 1000 as Score);
+        penalty.pair_cap = Some(10 as Score);
+        assert_eq!(penalty.get_pair([0, 1]), 10 as Score);
+        assert_eq!(penalty.get_pair([1, 2]), 1 as Score);
+        let group = Group { members: (0..3).map(Member::new).collect() };
+        assert_eq!(group.calc_score(&penalty), 12 as Score);
+        penalty.pair_cap = None;
+        assert_eq!(group.calc_score(&penalty), 1002 as Score);
+    }
+
+    #[test]
+    fn test_explain_move() {
+        let condition = &condition_fixture();
+        let tablecache = tablecache_fixture();
+        let action = Action::Move { source_position: Position { group_index: 0, member_index: 2 }, target_group: 1 };
+        let explanation = tablecache.explain(&action, condition).unwrap();
+        assert_eq!(
+            explanation,
+            ActionExplanation {
+                affected_groups: vec![0, 1],
+                transfers: vec![Transfer {
+                    id: 2, from: Some(0), to: Some(1), from_name: Some("group 0".to_string()), to_name: Some("group 1".to_string()),
+                }],
+                score_delta: tablecache.simulate(&action, condition).score_delta().unwrap(),
+                violations: vec![
+                    Violation::Tag { group_index: 0, tag: "c".to_string() },
+                    Violation::Tag { group_index: 1, tag: "c".to_string() },
+                ],
+            }
+        );
+        assert_eq!(explanation.score_delta, 1 as Score);
+        assert_eq!(tablecache, tablecache_fixture());
+    }
+
+    #[test]
+    fn test_explain_reports_every_violation_kind() {
+        let condition = Condition {
+            constraint: Constraint::default(),
+            capacities: vec![Range::AtLeast(3)],
+            max_pair_penalty: Some(4 as Score),
+            tag_spread: [("a".to_string(), Range::Exact(2))].into(),
+            ..condition_fixture()
+        };
+        let tablecache = tablecache_fixture();
+        let action = Action::Move { source_position: Position { group_index: 0, member_index: 0 }, target_group: 1 };
+        assert_eq!(
+            tablecache.explain(&action, &condition).unwrap().violations,
+            vec![
+                Violation::Capacity { group_index: 0, size: 2 },
+                Violation::ForbiddenPair { group_index: 1 },
+                Violation::Spread { tag: "a".to_string() },
+            ]
+        );
+        let missing = Action::Move { source_position: Position { group_index: 0, member_index: 5 }, target_group: 1 };
+        assert!(tablecache.explain(&missing, &condition).is_err());
+    }
+
+    #[test]
+    fn test_group_capacities() {
+        let condition = Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            capacities: vec![Range::AtMost(2), Range::AtMost(4)],
+            ..Default::default()
+        };
+        let empty = Table { groups: vec![Group { members: Vec::new() }, Group { members: Vec::new() }], ..Default::default() };
+        let mut tablecache = TableCache::create(&empty, &condition.penalty);
+        let add = |id, group_index| Action::Add { member: Member::new(id), group_index };
+        for id in 0..2 {
+            assert_eq!(tablecache.apply_validated(add(id, 0), &condition), Ok(None));
+        }
+        assert_eq!(tablecache.apply_validated(add(2, 0), &condition), Err(ActionError::ConstraintViolated));
+        for id in 2..6 {
+            assert_eq!(tablecache.apply_validated(add(id, 1), &condition), Ok(None));
+        }
+        assert_eq!(tablecache.apply_validated(add(6, 1), &condition), Err(ActionError::ConstraintViolated));
+        assert_eq!(tablecache.sizes(), vec![2, 4]);
+        let into_first = Action::Move { source_position: Position { group_index: 1, member_index: 0 }, target_group: 0 };
+        assert!(!tablecache.simulate(&into_first, &condition).is_feasible());
+        assert!(tablecache.is_feasible(&condition));
+        tablecache.act(into_first, &condition).unwrap();
+        assert_eq!(tablecache.count_violations(&condition), 1);
+    }
+
+    #[test]
+    fn test_score_breakdown() {
+        let condition = &condition_fixture();
+        let table = table_fixture();
+        let tablecache = tablecache_fixture();
+        for (group_index, group) in table.groups.iter().enumerate() {
+            let breakdown = tablecache.group(group_index).unwrap().score_breakdown(&condition.penalty);
+            assert_eq!(tablecache.score_breakdown(group_index, &condition.penalty), Some(breakdown.clone()));
+            assert_eq!(breakdown.len(), 3);
+            assert_eq!(breakdown.iter().map(|(_, score)| score).sum::<Score>(), group.calc_score(&condition.penalty));
+            assert!(breakdown.windows(2).all(|pairs| pairs[0].1 >= pairs[1].1));
+        }
+        assert_eq!(
+            tablecache.score_breakdown(1, &condition.penalty).unwrap(),
+            vec![([4, 5], 5 as Score), ([3, 4], 4 as Score), ([3, 5], 0 as Score)]
+        );
+        assert_eq!(tablecache.score_breakdown(2, &condition.penalty), None);
+    }
+
+    #[test]
+    fn test_tag_cooccurrence() {
+        let condition = Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            tag_cooccurrence: [("manager".to_string(), 2 as Score)].into_iter().collect(),
+            ..Default::default()
+        };
+        let managers = |n: u32| Table { groups: vec![Group {
+            members: (0..n).map(|id| Member::with_tags(id, ["manager"])).chain([Member::new(9)]).collect(),
+        }], ..Default::default() };
+        let two = TableCache::create(&managers(2), &condition.penalty);
+        let three = TableCache::create(&managers(3), &condition.penalty);
+        assert_eq!(two.effective_score(&condition), 2 as Score);
+        assert_eq!(three.effective_score(&condition), 6 as Score);
+        let add = Action::Add { member: Member::with_tags(3, ["manager"]), group_index: 0 };
+        assert_eq!(three.simulate(&add, &condition).score_delta(), Some(6 as Score));
+    }
+
+    #[test]
+    fn test_in_place_tagcounts_match_clone_based() {
+        use rand::{Rng, SeedableRng};
+        use rand::rngs::SmallRng;
+        let condition = &condition_fixture();
+        let mut tablecache = tablecache_fixture();
+        let mut expected: Vec<TagCounter> = tablecache.groups.iter().map(|group| group.tagcounts.clone()).collect();
+        let tags = |member: &Member| -> TagCounter { member.tags.iter().cloned().collect::<Vec<Tag>>().into() };
+        let mut rng = SmallRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let sizes = tablecache.sizes();
+            let source = rng.gen_range(0..2);
+            if sizes[source] == 0 {
+                continue;
+            }
+            let position = Position { group_index: source, member_index: rng.gen_range(0..sizes[source]) };
+            let target = 1 - source;
+            let member = tablecache.get_member(&position).unwrap().clone();
+            if sizes[target] > 0 && rng.gen_bool(0.5) {
+                let other_position = Position { group_index: target, member_index: rng.gen_range(0..sizes[target]) };
+                let other = tablecache.get_member(&other_position).unwrap().clone();
+                expected[source] = expected[source].clone() + tags(&other) - tags(&member);
+                expected[target] = expected[target].clone() + tags(&member) - tags(&other);
+                tablecache.act(Action::Swap(position, other_position), condition).unwrap();
+            } else {
+                expected[source] = expected[source].clone() - tags(&member);
+                expected[target] = expected[target].clone() + tags(&member);
+                tablecache.act(Action::Move { source_position: position, target_group: target }, condition).unwrap();
+            }
+        }
+        let actual: Vec<TagCounter> = tablecache.groups.iter().map(|group| group.tagcounts.clone()).collect();
+        assert_eq!(actual, expected);
+    }
+
+    fn remove_last_member(constraint: Constraint) -> (ActionResult, TableCache, Condition) {
+        let condition = Condition { penalty: RelationPenalty::new(1 as Score), constraint, ..Default::default() };
+        let table = Table { groups: vec![
+            Group { members: vec![Member::with_tags(0, ["a"])] },
+            Group { members: vec![Member::with_tags(1, ["a"]), Member::new(2)] },
+        ], ..Default::default() };
+        let mut tablecache = TableCache::create(&table, &condition.penalty);
+        let action = Action::Remove(Position { group_index: 0, member_index: 0 });
+        let result = tablecache.simulate(&action, &condition);
+        tablecache.act(action, &condition).unwrap();
+        assert_eq!(tablecache.sizes(), vec![0, 2]);
+        assert_eq!(tablecache.score_breakdown(0, &condition.penalty), Some(Vec::new()));
+        assert_eq!(result.is_feasible(), tablecache.is_group_feasible(0, &condition));
+        (result, tablecache, condition)
+    }
+
+    #[test]
+    fn test_remove_last_member_under_count() {
+        let (result, _, _) = remove_last_member(Constraint([("a".to_string(), Range::Count { min: 1, max: 2 })].into()));
+        assert_eq!(result, ActionResult::UnsatisfiedScoreDiff(0 as Score));
+        let (result, tablecache, condition) = remove_last_member(Constraint([("a".to_string(), Range::Count { min: 0, max: 2 })].into()));
+        assert_eq!(result, ActionResult::ScoreDiff(0 as Score));
+        assert!(tablecache.is_feasible(&condition));
+    }
+
+    #[test]
+    fn test_remove_last_member_under_ratio() {
+        let (result, _, _) = remove_last_member(Constraint([("a".to_string(), Range::Ratio { min: 0.5, max: 1.0 })].into()));
+        assert_eq!(result, ActionResult::UnsatisfiedScoreDiff(0 as Score));
+        let (result, tablecache, condition) = remove_last_member(Constraint([("a".to_string(), Range::Ratio { min: 0.0, max: 0.5 })].into()));
+        assert_eq!(result, ActionResult::ScoreDiff(0 as Score));
+        assert_eq!(tablecache.effective_score(&condition), 1 as Score);
+    }
+
+    #[test]
+    fn test_unassigned_pool() {
+        let condition = Condition { unassigned_penalty: 10 as Score, ..condition_fixture() };
+        let mut table = table_fixture();
+        table.groups[0].members[0].optional = true;
+        let mut tablecache = TableCache::create(&table, &condition.penalty);
+        let before = tablecache.effective_score(&condition);
+        let remove = Action::Remove(Position { group_index: 0, member_index: 0 });
+        let delta = tablecache.simulate(&remove, &condition).score_delta().unwrap();
+        assert_eq!(delta, (10 - 1) as Score);
+        let member = tablecache.act(remove, &condition).unwrap().unwrap();
+        assert_eq!(tablecache.unassigned(), core::slice::from_ref(&member));
+        assert_eq!(tablecache.effective_score(&condition), before + delta);
+
+        // The pool matches members by `id_key`, so a member returning with other tags still
+        // leaves it.
+        let mut member = member;
+        member.add_tag("z");
+        let add = Action::Add { member, group_index: 1 };
+        let delta = tablecache.simulate(&add, &condition).score_delta().unwrap();
+        assert_eq!(delta, -10 as Score);
+        tablecache.act(add, &condition).unwrap();
+        assert!(tablecache.unassigned().is_empty());
+
+        let required = Action::Remove(Position { group_index: 0, member_index: 0 });
+        tablecache.act(required, &condition).unwrap();
+        assert!(tablecache.unassigned().is_empty());
+    }
+
+    #[test]
+    fn test_churn_diff() {
+        let mut condition = Condition { churn_penalty: 10 as Score, ..condition_fixture() };
+        condition.set_previous(&table_fixture());
+        let mut tablecache = tablecache_fixture();
+        tablecache.act(Action::CreateGroup, &condition).unwrap();
+        let actions = [
+            Action::Move { source_position: Position { group_index: 0, member_index: 0 }, target_group: 2 },
+            Action::Swap(Position { group_index: 0, member_index: 0 }, Position { group_index: 1, member_index: 0 }),
+            Action::Move { source_position: Position { group_index: 2, member_index: 0 }, target_group: 0 },
+            Action::Add { member: Member::new(9), group_index: 2 },
+            Action::Remove(Position { group_index: 1, member_index: 0 }),
+        ];
+        for action in actions {
+            let before = tablecache.effective_score(&condition);
+            let delta = tablecache.simulate(&action, &condition).score_delta().unwrap();
+            tablecache.act(action, &condition).unwrap();
+            assert!((tablecache.effective_score(&condition) - before - delta).abs() < 1e-9);
+        }
+        assert_eq!(tablecache.churn_score(&condition), 10 as Score);
+    }
+
+    #[test]
+    fn test_empty_constraint_fast_path() {
+        let fast = Condition { constraint: Constraint::default(), ..condition_fixture() };
+        let slow = Condition {
+            constraint: Constraint([("a".to_string(), Range::AtLeast(0))].into()),
+            ..condition_fixture()
+        };
+        let mut fast_cache = tablecache_fixture();
+        let mut slow_cache = tablecache_fixture();
+        let created: Vec<TagCounter> = fast_cache.groups.iter().map(|group| group.tagcounts.clone()).collect();
+        let actions = [
+            Action::Swap(Position { group_index: 0, member_index: 0 }, Position { group_index: 1, member_index: 2 }),
+            Action::Move { source_position: Position { group_index: 1, member_index: 0 }, target_group: 0 },
+            Action::Add { member: Member::with_tags(7, ["a"]), group_index: 1 },
+            Action::Remove(Position { group_index: 0, member_index: 1 }),
+        ];
+        for action in actions {
+            assert_eq!(fast_cache.simulate(&action, &fast), slow_cache.simulate(&action, &slow));
+            fast_cache.act(action.clone(), &fast).unwrap();
+            slow_cache.act(action, &slow).unwrap();
+            assert_eq!(fast_cache.effective_score(&fast), slow_cache.effective_score(&slow));
+        }
+        // No tag counting happened on the fast path.
+        let kept: Vec<TagCounter> = fast_cache.groups.iter().map(|group| group.tagcounts.clone()).collect();
+        assert_eq!(kept, created);
+        assert!(fast_cache.is_feasible(&fast));
+    }
+
+    #[test]
+    fn test_violation_distance_penalty() {
+        let group = |n_tagged: u32| Table { groups: vec![Group {
+            members: (0..4).map(|id| if id < n_tagged { Member::with_tags(id, ["a"]) } else { Member::new(id) }).collect(),
+        }], ..Default::default() };
+        let score = |n_tagged: u32, violation_scaling: ViolationScaling| {
+            let condition = Condition {
+                penalty: RelationPenalty::new(0 as Score),
+                constraint: Constraint([("a".to_string(), Range::AtMost(0))].into()),
+                constraint_penalty: 2 as Score,
+                violation_scaling,
+                ..Default::default()
+            };
+            TableCache::create(&group(n_tagged), &condition.penalty).effective_score(&condition)
+        };
+        assert_eq!(score(1, ViolationScaling::Distance), 2 as Score);
+        assert_eq!(score(3, ViolationScaling::Distance), 6 as Score);
+        assert_eq!(score(1, ViolationScaling::Flat), score(3, ViolationScaling::Flat));
+
+        let condition = Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            constraint: Constraint([("a".to_string(), Range::AtMost(0))].into()),
+            constraint_penalty: 2 as Score,
+            violation_scaling: ViolationScaling::Distance,
+            ..Default::default()
+        };
+        let cache = TableCache::create(&group(1), &condition.penalty);
+        let add = Action::Add { member: Member::with_tags(9, ["a"]), group_index: 0 };
+        assert_eq!(cache.simulate(&add, &condition), ActionResult::UnsatisfiedScoreDiff(2 as Score));
+    }
+
+    #[test]
+    fn test_soft_constraint() {
+        let table = Table { groups: vec![
+            Group { members: vec![Member::with_tags(0, ["a"]), Member::with_tags(1, ["a"])] },
+            Group { members: vec![Member::new(2), Member::new(3)] },
+        ], ..Default::default() };
+        let condition = Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            soft_constraint: Constraint([("a".to_string(), Range::AtMost(1))].into()),
+            constraint_penalty: 3 as Score,
+            ..Default::default()
+        };
+        let mut cache = TableCache::create(&table, &condition.penalty);
+        assert!(cache.is_feasible(&condition));
+        assert_eq!(cache.effective_score(&condition), 3 as Score);
+
+        let spread = Action::Swap(Position::new(0, 1), Position::new(1, 0));
+        assert_eq!(cache.simulate(&spread, &condition), ActionResult::ScoreDiff(-3 as Score));
+        let crowd = Action::Move { source_position: Position::new(1, 0), target_group: 0 };
+        assert_eq!(cache.simulate(&crowd, &condition), ActionResult::ScoreDiff(0 as Score));
+        cache.act(spread, &condition).unwrap();
+        assert_eq!(cache.effective_score(&condition), 0 as Score);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_create_parallel_matches_create() {
+        let table = Table { groups: (0..16).map(|group_index| Group {
+            members: (0..8).map(|i| Member::with_tags(group_index * 8 + i, ["a"])).collect(),
+        }).collect(), ..Default::default() };
+        let mut penalty = RelationPenalty::new(1 as Score);
+        for id in 0..127 {
+            penalty.scores.insert([id, id + 1].into(), (id % 5) as Score);
+        }
+        let sequential = TableCache::create(&table, &penalty);
+        let parallel = TableCache::create_parallel(&table, &penalty);
+        assert_eq!(parallel.penalty_score, sequential.penalty_score);
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn test_global_tag_cap_rejects_add() {
+        let condition = Condition {
+            global_tag_cap: [("a".to_string(), 3)].into(),
+            ..condition_fixture()
+        };
+        let cache = tablecache_fixture();
+        let add = |tag: &str| Action::Add { member: Member::with_tags(9, [tag]), group_index: 0 };
+        assert_eq!(cache.simulate(&add("a"), &condition), ActionResult::Failed(vec![ActionError::ConstraintViolated]));
+        assert!(!cache.simulate(&add("b"), &condition).is_failed());
+    }
+
+    #[test]
+    fn test_count_violations_after_matches_act() {
+        let condition = Condition {
+            tag_spread: [("c".to_string(), Range::AtLeast(2))].into(),
+            max_pair_penalty: Some(4 as Score),
+            ..condition_fixture()
+        };
+        let cache = tablecache_fixture();
+        for member_index in 0..3 {
+            let source_position = Position { group_index: 0, member_index };
+            let mut actions = vec![Action::Move { source_position: source_position.clone(), target_group: 1 }];
+            actions.extend((0..3).map(|other| Action::Swap(source_position.clone(), Position { group_index: 1, member_index: other })));
+            for action in actions {
+                let mut trial = cache.clone();
+                trial.act(action.clone(), &condition).unwrap();
+                assert_eq!(cache.count_violations_after(&action, &condition), trial.count_violations(&condition), "{action:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_tag_spread() {
+        let table = Table { groups: vec![
+            Group { members: vec![Member::with_tags(0, ["x"]), Member::with_tags(1, ["x"])] },
+            Group { members: vec![Member::new(2), Member::new(3)] },
+        ], ..Default::default() };
+        let condition = Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            tag_spread: [("x".to_string(), Range::AtLeast(2))].into(),
+            constraint_penalty: 4 as Score,
+            ..Default::default()
+        };
+        let mut cache = TableCache::create(&table, &condition.penalty);
+        assert_eq!(cache.spread_violations(&condition), 1);
+        assert!(!cache.is_feasible(&condition));
+        assert_eq!(cache.effective_score(&condition), 4 as Score);
+
+        let spread = Action::Swap(Position::new(0, 1), Position::new(1, 0));
+        assert_eq!(cache.simulate(&spread, &condition), ActionResult::ScoreDiff(-4 as Score));
+        let moved = Action::Move { source_position: Position::new(1, 0), target_group: 0 };
+        assert_eq!(cache.simulate(&moved, &condition), ActionResult::UnsatisfiedScoreDiff(0 as Score));
+        cache.act(spread, &condition).unwrap();
+        assert!(cache.is_feasible(&condition));
+        assert_eq!(cache.effective_score(&condition), 0 as Score);
+    }
+
+    #[test]
+    fn test_noop() {
+        let condition = condition_fixture();
+        let mut tablecache = tablecache_fixture();
+        assert_eq!(tablecache.simulate(&Action::Noop, &condition), ActionResult::ScoreDiff(0 as Score));
+        assert_eq!(tablecache.act(Action::Noop, &condition), Ok(None));
+        assert_eq!(tablecache, tablecache_fixture());
+        assert_eq!(tablecache.explain(&Action::Noop, &condition).unwrap().affected_groups, Vec::<Index>::new());
+    }
+
+    #[test]
+    fn test_group_score_modes() {
+        let mut penalty = RelationPenalty::new(0 as Score);
+        penalty.scores.insert([0, 1].into(), 1 as Score);
+        penalty.scores.insert([0, 2].into(), 2 as Score);
+        penalty.scores.insert([1, 2].into(), 6 as Score);
+        let group = Group { members: (0..3).map(Member::new).collect() };
+        let score = |group_score_mode| group.aggregate_score(&Condition { penalty: penalty.clone(), group_score_mode, ..Default::default() });
+        assert_eq!(score(GroupScoreMode::SumPairs), 9 as Score);
+        assert_eq!(score(GroupScoreMode::MaxPair), 6 as Score);
+        assert_eq!(score(GroupScoreMode::MeanPair), 3 as Score);
+    }
+
+    #[test]
+    fn test_group_score_modes_simulate() {
+        for group_score_mode in [GroupScoreMode::SumPairs, GroupScoreMode::MaxPair, GroupScoreMode::MeanPair] {
+            let condition = Condition { group_score_mode, ..condition_fixture() };
+            let mut tablecache = tablecache_fixture();
+            let actions = [
+                Action::Swap(Position::new(0, 0), Position::new(1, 1)),
+                Action::Move { source_position: Position::new(1, 0), target_group: 0 },
+                Action::Remove(Position::new(0, 2)),
+                Action::Add { member: Member::new(6), group_index: 1 },
+            ];
+            for action in actions {
+                let before = tablecache.effective_score(&condition);
+                let delta = tablecache.simulate(&action, &condition).score_delta().unwrap();
+                tablecache.act(action, &condition).unwrap();
+                assert!((tablecache.effective_score(&condition) - before - delta).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_positions_with_tag() {
+        let tablecache = tablecache_fixture();
+        assert_eq!(
+            tablecache.positions_with_tag(&"a".to_string()),
+            vec![Position::new(0, 0), Position::new(1, 0), Position::new(1, 1)]
+        );
+        assert_eq!(tablecache.positions_with_tag(&"z".to_string()), Vec::new());
+    }
+
+    #[test]
+    fn test_checked_scores_report_overflow() {
+        let mut penalty = RelationPenalty::new(0 as Score);
+        penalty.scores.insert([0, 1].into(), Score::MAX / 1.5);
+        penalty.scores.insert([0, 2].into(), Score::MAX / 1.5);
+        penalty.scores.insert([1, 2].into(), Score::MAX / 1.5);
+        let table = Table { groups: vec![
+            Group { members: vec![Member::new(0), Member::new(1)] },
+            Group { members: vec![Member::new(2), Member::new(3)] },
+        ], ..Default::default() };
+        let mut cache = TableCache::create(&table, &penalty);
+        let add = Action::Add { member: Member::new(2), group_index: 0 };
+        let unchecked = Condition { penalty: penalty.clone(), ..Default::default() };
+        assert_eq!(cache.simulate(&add, &unchecked).score_delta(), Some(Score::INFINITY));
+
+        let checked = Condition { penalty, checked_scores: true, ..Default::default() };
+        assert_eq!(cache.simulate(&add, &checked), ActionResult::Failed(vec![ActionError::ScoreOverflow]));
+        let moved = Action::Move { source_position: Position::new(1, 0), target_group: 0 };
+        assert_eq!(cache.act(moved, &checked), Err(ActionError::ScoreOverflow));
+        assert_eq!(cache, TableCache::create(&table, &checked.penalty));
+        assert!(cache.act(Action::Swap(Position::new(0, 1), Position::new(1, 1)), &checked).is_ok());
+    }
+
+    #[test]
+    fn test_constraint_report() {
+        let condition = Condition {
+            constraint: Constraint([
+                ("a".to_string(), Range::Count { min: 2, max: 2 }),
+                ("c".to_string(), Range::AtMost(1)),
+            ].into()),
+            ..condition_fixture()
+        };
+        let tablecache = tablecache_fixture();
+        assert_eq!(tablecache.constraint_report(&condition), vec![
+            (0, Err(vec![TagViolation { tag: "a".to_string(), actual: 1, range: Range::Count { min: 2, max: 2 }, distance: 1 }])),
+            (1, Err(vec![TagViolation { tag: "c".to_string(), actual: 2, range: Range::AtMost(1), distance: 1 }])),
+        ]);
+        assert_eq!(tablecache.constraint_status(0, &condition_fixture()), Some(Ok(())));
+        assert_eq!(tablecache.constraint_status(2, &condition), None);
+        let group = tablecache.group(1).unwrap();
+        assert_eq!(Some(group.constraint_status(&condition)), tablecache.constraint_status(1, &condition));
+    }
+
+    #[test]
+    fn test_seat_weight() {
+        let mut penalty = RelationPenalty::new(0 as Score);
+        penalty.scores.insert([0, 1].into(), 4 as Score);
+        penalty.scores.insert([1, 2].into(), 2 as Score);
+        let group = Group { members: (0..3).map(Member::new).collect() };
+        let mut reversed = group.clone();
+        reversed.members.reverse();
+        let plain = Condition { penalty, ..Default::default() };
+        assert_eq!(group.aggregate_score(&plain), reversed.aggregate_score(&plain));
+        // Pairs involving the first seat count double.
+        let seated = Condition { seat_weight: Some(|i, j| if i.min(j) == 0 { 2.0 } else { 1.0 }), ..plain.clone() };
+        assert_eq!(group.aggregate_score(&seated), (2 * 4 + 2) as Score);
+        assert_eq!(reversed.aggregate_score(&seated), (4 + 2 * 2) as Score);
+
+        let mut tablecache = tablecache_fixture();
+        let condition = Condition { seat_weight: seated.seat_weight, ..condition_fixture() };
+        let actions = [
+            Action::Remove(Position::new(0, 0)),
+            Action::Swap(Position::new(0, 0), Position::new(1, 1)),
+            Action::Move { source_position: Position::new(1, 0), target_group: 0 },
+        ];
+        for action in actions {
+            let before = tablecache.effective_score(&condition);
+            let delta = tablecache.simulate(&action, &condition).score_delta().unwrap();
+            tablecache.act(action, &condition).unwrap();
+            assert!((tablecache.effective_score(&condition) - before - delta).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_swap_within_a_group() {
+        // Only a seat weight makes the order matter: pairs (3, 4) and (4, 5) score 4 * 2 + 5
+        // before and 5 * 2 + 4 after.
+        let seated = Condition { seat_weight: Some(|i, j| if i.min(j) == 0 { 2.0 } else { 1.0 }), ..condition_fixture() };
+        for (condition, expected) in [(condition_fixture(), 0 as Score), (seated, 1 as Score)] {
+            let mut cache = tablecache_fixture();
+            let swap = Action::Swap(Position::new(1, 0), Position::new(1, 2));
+            let before = cache.effective_score(&condition);
+            assert_eq!(cache.simulate(&swap, &condition), ActionResult::ScoreDiff(expected));
+            cache.act(swap, &condition).unwrap();
+            assert_eq!(cache.effective_score(&condition), before + expected);
+        }
+    }
+
+    #[test]
+    fn test_members_are_interned() {
+        let condition = Condition { unassigned_penalty: 10 as Score, ..condition_fixture() };
+        let mut table = table_fixture();
+        table.groups[0].members[0].optional = true;
+        let mut cache = TableCache::create(&table, &condition.penalty);
+        let removed = cache.act(Action::Remove(Position::new(0, 0)), &condition).unwrap().unwrap();
+        cache.act(Action::Add { member: removed.clone(), group_index: 1 }, &condition).unwrap();
+        assert!(matches!(cache.groups[1].seats[3], Seat::Interned(0)));
+
+        // A member differing from the one in the table is kept as given.
+        let mut retagged = removed;
+        retagged.add_tag("d");
+        cache.act(Action::Replace { position: Position::new(1, 3), member: retagged.clone() }, &condition).unwrap();
+        assert!(matches!(&cache.groups[1].seats[3], Seat::Owned(member) if *member == retagged));
+        assert_eq!(cache.to_table().groups[1].members[3], retagged);
+        assert_eq!(cache.recompute_total(&condition.penalty), cache.penalty_score);
+    }
+
+    #[test]
+    fn test_replace() {
+        let condition = Condition { unassigned_penalty: 10 as Score, ..condition_fixture() };
+        let mut table = table_fixture();
+        table.groups[1].members[2].optional = true;
+        let mut cache = TableCache::create(&table, &condition.penalty);
+        let position = Position::new(1, 2);
+        let newcomer = Member { id: 6, tags: ["b".to_string(), "c".to_string()].into(), ..Default::default() };
+        let replace = Action::Replace { position: position.clone(), member: newcomer.clone() };
+
+        let mut stepwise = cache.clone();
+        let removed = stepwise.simulate(&Action::Remove(position.clone()), &condition).score_delta().unwrap();
+        stepwise.act(Action::Remove(position), &condition).unwrap();
+        let add = Action::Add { member: newcomer, group_index: 1 };
+        let added = stepwise.simulate(&add, &condition).score_delta().unwrap();
+        stepwise.act(add, &condition).unwrap();
+
+        let before = cache.effective_score(&condition);
+        assert_eq!(cache.simulate(&replace, &condition), ActionResult::ScoreDiff(removed + added));
+        let replaced = cache.act(replace, &condition).unwrap().unwrap();
+        assert_eq!(replaced.id, 5);
+        assert_eq!(cache.unassigned(), [replaced]);
+        assert_eq!(cache.effective_score(&condition), before + removed + added);
+        assert_eq!(cache.groups[1].members().map(|member| member.id).collect::<Vec<_>>(), vec![3, 4, 6]);
+    }
+
+    #[test]
+    fn test_recompute_total() {
+        let condition = condition_fixture();
+        let mut cache = tablecache_fixture();
+        assert_eq!(cache.recompute_total(&condition.penalty), cache.penalty_score);
+        Arc::make_mut(&mut cache.groups[1]).penalty_score += 3 as Score;
+        cache.penalty_score += 3 as Score;
+        assert_eq!(cache.recompute_total(&condition.penalty), 12 as Score);
+        assert!(cache.resync(&condition.penalty, 1e-9));
+        assert_eq!(cache.penalty_score, 12 as Score);
+        assert_eq!(cache, tablecache_fixture());
+    }
+
+    /// Counts the allocations of the current thread, so that tests running in parallel do not
+    /// disturb each other.
+    #[cfg(feature = "std")]
+    mod counting {
+        use core::cell::Cell;
+        use std::alloc::{GlobalAlloc, Layout, System};
+
+        std::thread_local! {
+            static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        struct CountingAllocator;
+
+        unsafe impl GlobalAlloc for CountingAllocator {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+                System.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+        pub fn allocations(f: impl FnOnce()) -> usize {
+            let before = ALLOCATIONS.with(Cell::get);
+            f();
+            ALLOCATIONS.with(Cell::get) - before
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_swap_does_not_clone_members() {
+        let condition = Condition { constraint: Constraint::default(), ..condition_fixture() };
+        let mut cache = tablecache_fixture();
+        for i in 0..100 {
+            let swap = Action::Swap(Position::new(0, i % 3), Position::new(1, (i + 1) % 3));
+            let simulating = counting::allocations(|| { cache.simulate(&swap, &condition); });
+            // Pair lookups allocate on their own; acting must not add member or tag set clones.
+            let acting = counting::allocations(|| { cache.act(swap, &condition).unwrap(); });
+            assert_eq!(acting, simulating);
+        }
+        assert_eq!(cache.recompute_total(&condition.penalty), cache.penalty_score);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_interned_members_are_not_cloned() {
+        // `MaxPair` lists the members of a group for every simulation, which must not copy their tag sets.
+        let condition = Condition { constraint: Constraint::default(), group_score_mode: GroupScoreMode::MaxPair, ..condition_fixture() };
+        let workload = |table: &Table| {
+            let mut cache = TableCache::create(table, &condition.penalty);
+            counting::allocations(|| for i in 0..100 {
+                let action = match i % 4 {
+                    0 => Action::Move { source_position: Position::new(0, 0), target_group: 1 },
+                    2 => Action::Move { source_position: Position::new(1, 0), target_group: 0 },
+                    _ => Action::Swap(Position::new(0, i % 2), Position::new(1, (i + 1) % 2)),
+                };
+                cache.simulate(&action, &condition);
+                cache.act(action, &condition).unwrap();
+            })
+        };
+        let untagged = table_fixture();
+        let mut tagged = untagged.clone();
+        for member in tagged.groups.iter_mut().flat_map(|group| &mut group.members) {
+            member.tags.extend((0..8).map(|tag| format!("{}-{tag}", member.id)));
+        }
+        assert_eq!(workload(&tagged), workload(&untagged));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_pair_matrix_lookups_do_not_allocate() {
+        let condition = Condition { constraint: Constraint::default(), ..condition_fixture() };
+        let mut cache = TableCache::create_with_pair_matrix(&table_fixture(), &condition.penalty);
+        let allocations = counting::allocations(|| for i in 0..100 {
+            let swap = Action::Swap(Position::new(0, i % 3), Position::new(1, (i + 1) % 3));
+            cache.simulate(&swap, &condition);
+            cache.act(swap, &condition).unwrap();
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn test_labels_follow_groups() {
+        let condition = condition_fixture();
+        let table = Table { labels: Some(vec!["VIP".to_string(), "Window".to_string()]), ..table_fixture() };
+        let mut cache = TableCache::create(&table, &condition.penalty);
+        cache.act(Action::CreateGroup, &condition).unwrap();
+        cache.act(Action::CreateGroup, &condition).unwrap();
+        cache.act(Action::RemoveEmptyGroup(2), &condition).unwrap();
+        let labels = cache.to_table().labels.unwrap();
+        // The group created last keeps its label after the one before it is removed.
+        assert_eq!(labels, vec!["VIP".to_string(), "Window".to_string(), "group 3".to_string()]);
+
+        let move_to_window = Action::Move { source_position: Position::new(0, 0), target_group: 1 };
+        let transfer = &cache.explain(&move_to_window, &condition).unwrap().transfers[0];
+        assert_eq!((transfer.from_name.as_deref(), transfer.to_name.as_deref()), (Some("VIP"), Some("Window")));
+
+        let partial = Table { labels: Some(vec!["VIP".to_string()]), ..table_fixture() };
+        let mut cache = TableCache::create(&partial, &condition.penalty);
+        cache.act(Action::CreateGroup, &condition).unwrap();
+        let labels = cache.to_table().labels.unwrap();
+        assert_eq!(labels, vec!["VIP".to_string(), "group 1".to_string(), "group 2".to_string()]);
+    }
+
+    #[test]
+    fn test_pinned_member_is_rejected_before_scoring() {
+        // Scoring the swap would overflow, so only an early rejection can report the pin.
+        let mut condition = Condition { checked_scores: true, ..condition_fixture() };
+        condition.penalty.scores.insert([0, 4].into(), Score::INFINITY);
+        let cache = tablecache_fixture();
+        let swap = Action::Swap(Position::new(0, 1), Position::new(1, 1));
+        assert_eq!(cache.simulate(&swap, &condition), ActionResult::Failed(vec![ActionError::ScoreOverflow]));
+        condition.pinned.insert(4);
+        assert_eq!(cache.simulate(&swap, &condition), ActionResult::Failed(vec![ActionError::Pinned(4)]));
+
+        // Reordering a pinned member within its group is still allowed.
+        let reorder = Action::Swap(Position::new(1, 1), Position::new(1, 0));
+        assert!(!cache.simulate(&reorder, &condition).is_failed());
+        assert_eq!(
+            cache.simulate(&Action::Remove(Position::new(1, 1)), &condition),
+            ActionResult::Failed(vec![ActionError::Pinned(4)]),
+        );
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let condition = condition_fixture();
+        let mut table = table_fixture();
+        table.groups.push(Group { members: vec![Member::new(6), Member::new(7)] });
+        let mut cache = TableCache::create(&table, &condition.penalty);
+        let before = cache.clone();
+        let snapshot = cache.snapshot();
+        cache.act(Action::Swap(Position::new(0, 0), Position::new(1, 2)), &condition).unwrap();
+        // Untouched groups are still shared with the snapshot.
+        assert!(Arc::ptr_eq(&cache.groups[2], &snapshot.0.groups[2]));
+        assert!(!Arc::ptr_eq(&cache.groups[0], &snapshot.0.groups[0]));
+        cache.act(Action::Move { source_position: Position::new(2, 0), target_group: 0 }, &condition).unwrap();
+        cache.act(Action::Remove(Position::new(1, 1)), &condition).unwrap();
+        cache.act(Action::CreateGroup, &condition).unwrap();
+        assert_ne!(cache, before);
+
+        cache.restore(snapshot);
+        assert_eq!(cache, before);
+        assert_eq!(cache.to_table(), table);
+    }
+
+    #[test]
+    fn test_max_pair_penalty() {
+        let mut condition = Condition { constraint: Constraint::default(), max_pair_penalty: Some(4 as Score), ..condition_fixture() };
+        let mut cache = tablecache_fixture();
+        // Group 1 holds the pair (4, 5) scoring 5.
+        assert!(cache.is_group_feasible(0, &condition));
+        assert!(!cache.is_group_feasible(1, &condition));
+
+        // Moving 5 next to 6 would pair them at 6, moving 4 next to 3 only at 4.
+        cache.act(Action::Add { member: Member::new(6), group_index: 0 }, &condition).unwrap();
+        let over = Action::Move { source_position: Position::new(1, 2), target_group: 0 };
+        assert_eq!(cache.simulate(&over, &condition), ActionResult::UnsatisfiedScoreDiff(1 as Score));
+        assert_eq!(cache.apply_validated(over, &condition), Err(ActionError::ConstraintViolated));
+        let swap = Action::Swap(Position::new(0, 0), Position::new(1, 2));
+        assert_eq!(cache.apply_validated(swap, &condition), Err(ActionError::ConstraintViolated));
+        let within = Action::Remove(Position::new(1, 2));
+        assert_eq!(cache.apply_validated(within, &condition).unwrap().map(|member| member.id), Some(5));
+        assert!(cache.is_feasible(&condition));
+
+        condition.max_pair_penalty = None;
+        cache.act(Action::Add { member: Member::new(5), group_index: 0 }, &condition).unwrap();
+        assert!(cache.is_feasible(&condition));
+    }
+
+    #[test]
+    fn test_create_checked_limits_group_size() {
+        let condition = condition_fixture();
+        let mut table = table_fixture();
+        assert_eq!(TableCache::create_checked(&table, &condition.penalty, &Limits::default()), Ok(tablecache_fixture()));
+        let limits = Limits { max_group_size: Some(3) };
+        assert_eq!(TableCache::create_checked(&table, &condition.penalty, &limits), Ok(tablecache_fixture()));
+        table.groups[1].members.push(Member::new(6));
+        assert_eq!(
+            TableCache::create_checked(&table, &condition.penalty, &limits),
+            Err(AssignmentError::GroupTooLarge { group_index: 1, size: 4, max_size: 3 })
+        );
+    }
+
+    #[test]
+    fn test_pair_matrix_matches_penalty() {
+        let mut condition = condition_fixture();
+        condition.penalty.default = 0.5;
+        let mut table = table_fixture();
+        table.groups[1].members[2].weight = 2.0;
+        let matrix = PairMatrix::new(table.iter_members(), &condition.penalty);
+        assert_eq!(matrix.len(), 6);
+        let members: Vec<&Member> = table.iter_members().collect();
+        for (i, a) in members.iter().enumerate() {
+            for (j, b) in members.iter().enumerate().filter(|(j, _)| *j != i) {
+                assert_eq!(matrix.get(i, j), pair_score(&condition.penalty, a, b));
+            }
+        }
+
+        let mut dense = TableCache::create_with_pair_matrix(&table, &condition.penalty);
+        let mut sparse = TableCache::create(&table, &condition.penalty);
+        assert_eq!(dense.penalty_score, sparse.penalty_score);
+
+        // Every path scoring pairs reads the matrix, which keeps the penalty it was built with.
+        let zero = Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            constraint: Constraint::default(),
+            max_pair_penalty: Some(0.5),
+            ..condition.clone()
+        };
+        assert_eq!(dense.recompute_total(&zero.penalty), dense.penalty_score);
+        assert_eq!(sparse.recompute_total(&zero.penalty), 0 as Score);
+        assert!(!dense.is_feasible(&zero) && sparse.is_feasible(&zero));
+        let seated = Condition { seat_weight: Some(|_, _| 1.0), ..zero.clone() };
+        assert_eq!(dense.effective_score(&seated), dense.penalty_score);
+        let actions = [
+            Action::Swap(Position::new(0, 0), Position::new(1, 2)),
+            Action::Move { source_position: Position::new(1, 0), target_group: 0 },
+            Action::CreateGroup,
+            Action::Add { member: Member::new(6), group_index: 2 },
+            Action::Move { source_position: Position::new(0, 1), target_group: 2 },
+            Action::Replace { position: Position::new(0, 0), member: Member::new(7) },
+        ];
+        for action in actions {
+            assert_eq!(dense.simulate(&action, &condition), sparse.simulate(&action, &condition));
+            dense.act(action.clone(), &condition).unwrap();
+            sparse.act(action, &condition).unwrap();
+            assert_eq!(dense.penalty_score, sparse.penalty_score);
+        }
+        assert_eq!(dense.to_table(), sparse.to_table());
+        assert_eq!(dense.recompute_total(&condition.penalty), dense.penalty_score);
+    }
+
+    #[test]
+    fn test_move_respects_target_capacity() {
+        let condition = Condition {
+            constraint: Constraint::default(),
+            capacities: vec![Range::AtMost(3), Range::AtMost(4)],
+            ..condition_fixture()
+        };
+        let mut cache = tablecache_fixture();
+        // Group 0 is full, group 1 has one free seat.
+        let into_full = Action::Move { source_position: Position::new(1, 1), target_group: 0 };
+        let into_free = Action::Move { source_position: Position::new(0, 1), target_group: 1 };
+        assert_eq!(cache.simulate(&into_full, &condition), ActionResult::UnsatisfiedScoreDiff(-9 as Score));
+        assert_eq!(cache.simulate(&into_free, &condition), ActionResult::ScoreDiff(-3 as Score));
+        assert_eq!(cache.apply_validated(into_full, &condition), Err(ActionError::ConstraintViolated));
+        assert_eq!(cache.apply_validated(into_free, &condition), Ok(None));
+        assert_eq!(cache.sizes(), vec![2, 4]);
+        let into_missing = Action::Move { source_position: Position::new(0, 0), target_group: 2 };
+        assert_eq!(cache.simulate(&into_missing, &condition), ActionResult::Failed(vec![ActionError::InvalidPosition]));
     }
 }
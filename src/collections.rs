@@ -0,0 +1,7 @@
+//! Collections used in the public API: the standard hash maps with `std`, `hashbrown` without.
+
+#[cfg(feature = "std")]
+pub use std::collections::{HashMap, HashSet};
+#[cfg(not(feature = "std"))]
+pub use hashbrown::{HashMap, HashSet};
+pub use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
@@ -0,0 +1,29 @@
+use alloc::vec::Vec;
+use itertools::Itertools;
+use thiserror::Error;
+use crate::action::ActionError;
+use crate::anneal::ParamsError;
+use crate::model::group::{AssignmentError, RosterMismatch};
+use crate::validate::Infeasibility;
+
+/// Any error of the solver pipeline, from validating the input to applying actions.
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum ShuffleError {
+    #[error("invalid action: {0}")]
+    Action(#[from] ActionError),
+    #[error("invalid assignment: {0}")]
+    Assignment(#[from] AssignmentError),
+    #[error("invalid table: {0}")]
+    Roster(#[from] RosterMismatch),
+    #[error("constraints cannot be satisfied: {}", .0.iter().join("; "))]
+    Infeasible(Vec<Infeasibility>),
+    #[error("invalid parameters: {0}")]
+    Params(#[from] ParamsError),
+}
+
+impl From<Vec<Infeasibility>> for ShuffleError {
+    fn from(infeasibilities: Vec<Infeasibility>) -> Self {
+        ShuffleError::Infeasible(infeasibilities)
+    }
+}
+
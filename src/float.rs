@@ -0,0 +1,16 @@
+//! Float functions that `core` lacks, taken from `libm` without `std`.
+
+#[cfg(feature = "std")]
+mod imp {
+    pub fn exp(x: f64) -> f64 { x.exp() }
+    pub fn powf(x: f64, y: f64) -> f64 { x.powf(y) }
+    pub fn ceil(x: f64) -> f64 { x.ceil() }
+    pub fn floor(x: f64) -> f64 { x.floor() }
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+    pub use libm::{exp, pow as powf, ceil, floor};
+}
+
+pub(crate) use imp::*;
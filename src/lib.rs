@@ -0,0 +1,61 @@
+//! Shuffles members into groups by simulated annealing.
+//!
+//! Without the default `std` feature the crate is `no_std` and only needs `alloc`; enable the
+//! `alloc` feature instead. Randomness then has to come from the caller (see
+//! `anneal::Annealer::with_rng`).
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("either the `std` or the `alloc` feature must be enabled");
+
+extern crate alloc;
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+pub mod collections;
+mod float;
+pub mod model;
+pub mod action;
+pub mod cache;
+pub mod anneal;
+pub mod repair;
+pub mod assign;
+pub mod validate;
+pub mod error;
+pub mod report;
+pub mod prelude;
+#[cfg(feature = "seating")]
+pub mod seating;
+
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use rand::rngs::mock::StepRng;
+    use crate::model::entity::Member;
+    use crate::model::group::{Group, Table};
+    use crate::model::condition::{Condition, RelationPenalty, Score};
+    use crate::action::{Action, Position};
+    use crate::cache::TableCache;
+    use crate::anneal::{Annealer, Params};
+
+    /// Touches only `core` and `alloc`, so that `cargo test --no-default-features --features alloc`
+    /// checks the core still works without `std`.
+    #[test]
+    fn test_no_std_smoke() {
+        let mut penalty = RelationPenalty::new(0 as Score);
+        penalty.scores.insert([0, 1].into(), 2 as Score);
+        let condition = Condition { penalty, ..Default::default() };
+        let table = Table { groups: vec![
+            Group { members: vec![Member::new(0), Member::new(1)] },
+            Group { members: vec![Member::new(2), Member::new(3)] },
+        ], ..Default::default() };
+        let cache = TableCache::create(&table, &condition.penalty);
+        let swap = Action::Swap(Position::new(0, 1), Position::new(1, 0));
+        assert_eq!(cache.simulate(&swap, &condition).score_delta(), Some(-2 as Score));
+        let params = Params { max_iterations: 50, ..Default::default() };
+        let mut annealer = Annealer::with_rng(&table, &condition, &params, StepRng::new(1, 0x9e37_79b9_7f4a_7c15));
+        annealer.by_ref().for_each(drop);
+        assert_eq!(annealer.best_score(), 0 as Score);
+    }
+}
@@ -1,8 +1,3 @@
-mod model;
-mod anneal;
-mod action;
-mod cache;
-
 fn main() {
     println!("Hello, world!");
 }
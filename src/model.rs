@@ -1,37 +1,393 @@
 pub mod entity {
-    use std::collections::HashSet;
+    use alloc::string::String;
+    use crate::collections::HashSet;
 
     pub type Id = u32;
     pub type Tag = String;
 
     #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Member {
         pub id: Id,
+        #[cfg_attr(feature = "serde", serde(default))]
         pub tags: HashSet<Tag>,
+        /// Importance of the member's relationships; pair penalties are scaled by the product of weights.
+        #[cfg_attr(feature = "serde", serde(default = "default_weight"))]
+        pub weight: f64,
+        /// Whether the solver may leave the member out of every group (e.g. a waitlisted
+        /// attendee), at the cost of `Condition::unassigned_penalty`.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub optional: bool,
+    }
+
+    #[cfg(feature = "serde")]
+    fn default_weight() -> f64 {
+        Member::default().weight
+    }
+
+    impl Default for Member {
+        fn default() -> Self {
+            Member { id: 0, tags: HashSet::new(), weight: 1.0, optional: false }
+        }
+    }
+
+    impl Member {
+        pub fn new(id: Id) -> Member {
+            Member { id, ..Default::default() }
+        }
+
+        pub fn with_tags(id: Id, tags: impl IntoIterator<Item = impl Into<Tag>>) -> Member {
+            Member { id, tags: tags.into_iter().map(Into::into).collect(), ..Default::default() }
+        }
+
+        /// Returns whether the tag was newly added.
+        pub fn add_tag(&mut self, tag: impl Into<Tag>) -> bool {
+            self.tags.insert(tag.into())
+        }
+
+        /// Returns whether the tag was present.
+        pub fn remove_tag(&mut self, tag: &str) -> bool {
+            self.tags.remove(tag)
+        }
+
+        /// Identity of the member. Ids are unique within a table, so comparing or collecting ids
+        /// is the cheap way to compare members as people; `==` on `Member` also compares tags,
+        /// weight and whether the member is optional.
+        pub fn id_key(&self) -> Id {
+            self.id
+        }
+
+        pub fn has_tag(&self, tag: &str) -> bool {
+            self.tags.contains(tag)
+        }
     }
 }
 
 
 pub mod group {
-    use super::entity::{Id, Member, Tag};
+    use alloc::{format, string::String, vec::Vec};
+    use crate::collections::{BTreeSet, HashMap};
+    use rand::rngs::SmallRng;
+    use rand::seq::SliceRandom;
+    use thiserror::Error;
+    use super::entity::{Id, Member};
+    use crate::action::{Index, ActionError};
+    use super::condition::{ConstraintExpr, Range};
+
+    /// Like `Table::check_unique_ids`, for members that are not in a table yet.
+    pub fn check_unique_ids<'m>(members: impl IntoIterator<Item = &'m Member>) -> Result<(), AssignmentError> {
+        let mut seen = BTreeSet::new();
+        let duplicates: BTreeSet<Id> = members.into_iter()
+            .filter(|member| !seen.insert(member.id))
+            .map(|member| member.id)
+            .collect();
+        if duplicates.is_empty() {
+            Ok(())
+        } else {
+            Err(AssignmentError::DuplicateIds { ids: duplicates.into_iter().collect() })
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Group {
         pub members: Vec<Member>,
     }
 
+    impl Group {
+        /// Appends `member` unless a member with the same id is already in the group.
+        pub fn add_member(&mut self, member: Member) -> Result<(), ActionError> {
+            if self.members.iter().any(|other| other.id == member.id) {
+                return Err(ActionError::DuplicateId(member.id));
+            }
+            self.members.push(member);
+            Ok(())
+        }
+
+        /// Removes and returns the member with `id`, keeping the order of the others.
+        pub fn remove_member_by_id(&mut self, id: Id) -> Option<Member> {
+            let index = self.members.iter().position(|member| member.id == id)?;
+            Some(self.members.remove(index))
+        }
+
+        /// Whether the number of members matching `expr` lies in `range`, evaluated against the
+        /// size of the group like a tag constraint.
+        pub fn check_expr(&self, expr: &ConstraintExpr, range: &Range) -> bool {
+            let count = self.members.iter().filter(|member| expr.matches(member)).count();
+            range.contains(count, self.members.len())
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct Table {
         pub groups: Vec<Group>,
+        /// Names of the groups, indexed like `groups`, e.g. "VIP" or "Window". Operations that
+        /// add, remove or reorder groups keep them aligned; groups created by `TableCache` are
+        /// named after their index.
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub labels: Option<Vec<String>>,
+    }
+
+    #[derive(Debug, Clone, Error, PartialEq)]
+    pub enum AssignmentError {
+        #[error("{members} members but {assignments} assignments")]
+        LengthMismatch { members: usize, assignments: usize },
+        #[error("member {member_index} is assigned to group {group_index}, but there are only {group_count} groups")]
+        GroupOutOfRange { member_index: Index, group_index: Index, group_count: usize },
+        #[error("member ids {ids:?} occur more than once")]
+        DuplicateIds { ids: Vec<Id> },
+        #[error("group {group_index} has {size} members, but at most {max_size} are allowed")]
+        GroupTooLarge { group_index: Index, size: usize, max_size: usize },
+    }
+
+    /// Ids that differ between a table and the roster it should hold, each in ascending order.
+    #[derive(Debug, Clone, Error, PartialEq)]
+    #[error("table does not match the roster: missing ids {missing:?}, extra ids {extra:?}")]
+    pub struct RosterMismatch {
+        /// Ids of the roster that no group holds.
+        pub missing: Vec<Id>,
+        /// Ids held by some group that the roster lacks.
+        pub extra: Vec<Id>,
+    }
+
+    /// A member whose group differs between two tables; `None` means absent from that table.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct MemberMove {
+        pub id: Id,
+        pub from: Option<Index>,
+        pub to: Option<Index>,
+        /// `Table::group_name` of `from` in the first table and of `to` in the second.
+        pub from_name: Option<String>,
+        pub to_name: Option<String>,
+    }
+
+    /// `Table::group_name` for a table with `labels`, shared with the caches of tables.
+    pub(crate) fn group_name(labels: Option<&[String]>, group_index: Index) -> String {
+        match labels.and_then(|labels| labels.get(group_index)) {
+            Some(label) => label.clone(),
+            None => format!("group {group_index}"),
+        }
+    }
+
+    impl Table {
+        /// Builds a table with `group_count` groups, placing `members[i]` into group `assignment[i]`.
+        /// Members keep their relative order within each group.
+        pub fn from_assignment(members: Vec<Member>, assignment: &[Index], group_count: usize) -> Result<Table, AssignmentError> {
+            if members.len() != assignment.len() {
+                return Err(AssignmentError::LengthMismatch { members: members.len(), assignments: assignment.len() });
+            }
+            if let Some((member_index, group_index)) = assignment.iter().enumerate().find(|(_, group_index)| **group_index >= group_count) {
+                return Err(AssignmentError::GroupOutOfRange { member_index, group_index: *group_index, group_count });
+            }
+            let mut groups: Vec<Group> = (0..group_count).map(|_| Group { members: Vec::new() }).collect();
+            for (member, group_index) in members.into_iter().zip(assignment) {
+                groups[*group_index].members.push(member);
+            }
+            Ok(Table { groups, ..Default::default() })
+        }
+
+        /// Checks that no id occurs twice in the table, which would corrupt pair lookups and id
+        /// sets. The error lists every duplicated id once, in ascending order.
+        pub fn check_unique_ids(&self) -> Result<(), AssignmentError> {
+            check_unique_ids(self.iter_members())
+        }
+
+        /// Checks that the table holds exactly the members of `roster`, comparing ids only, so
+        /// edited tags or weights are not a mismatch.
+        pub fn validate_against_roster(&self, roster: &[Member]) -> Result<(), RosterMismatch> {
+            let held: BTreeSet<Id> = self.iter_members().map(|member| member.id).collect();
+            let expected: BTreeSet<Id> = roster.iter().map(|member| member.id).collect();
+            let missing: Vec<Id> = expected.difference(&held).copied().collect();
+            let extra: Vec<Id> = held.difference(&expected).copied().collect();
+            if missing.is_empty() && extra.is_empty() {
+                Ok(())
+            } else {
+                Err(RosterMismatch { missing, extra })
+            }
+        }
+
+        /// Randomly reorders the members of every group, e.g. for printing, without moving any
+        /// member to another group, so scores and constraints are unchanged.
+        pub fn shuffle_within_groups(&mut self, rng: &mut SmallRng) {
+            for group in &mut self.groups {
+                group.members.shuffle(rng);
+            }
+        }
+
+        /// Maps every member id to the index of its group.
+        pub fn to_assignment(&self) -> HashMap<Id, Index> {
+            self.groups.iter().enumerate()
+                .flat_map(|(group_index, group)| group.members.iter().map(move |member| (member.id, group_index)))
+                .collect()
+        }
+
+        pub fn group_sizes(&self) -> Vec<usize> {
+            self.groups.iter().map(|group| group.members.len()).collect()
+        }
+
+        /// Every member of the table, group by group.
+        pub fn iter_members(&self) -> impl Iterator<Item = &Member> {
+            self.groups.iter().flat_map(|group| &group.members)
+        }
+
+        pub fn member_count(&self) -> usize {
+            self.groups.iter().map(|group| group.members.len()).sum()
+        }
+
+        pub fn group_count(&self) -> usize {
+            self.groups.len()
+        }
+
+        /// Label of the group if the table is labeled, otherwise "group <index>".
+        pub fn group_name(&self, group_index: Index) -> String {
+            group_name(self.labels.as_deref(), group_index)
+        }
+
+        /// Members whose group index in `other` differs from `self`, ordered by id. Members are
+        /// matched by id, so reordering members within a group is not a change.
+        pub fn diff(&self, other: &Table) -> Vec<MemberMove> {
+            let before = self.to_assignment();
+            let after = other.to_assignment();
+            let ids: BTreeSet<Id> = before.keys().chain(after.keys()).copied().collect();
+            ids.into_iter()
+                .map(|id| (id, before.get(&id).copied(), after.get(&id).copied()))
+                .filter(|(_, from, to)| from != to)
+                .map(|(id, from, to)| MemberMove {
+                    id, from, to,
+                    from_name: from.map(|group_index| self.group_name(group_index)),
+                    to_name: to.map(|group_index| other.group_name(group_index)),
+                })
+                .collect()
+        }
+
+        /// Orders the members of every group by id, keeping every member in its group and the
+        /// groups in their order, so that equal memberships print identically.
+        pub fn sort_members_by_id(&mut self) {
+            for group in &mut self.groups {
+                group.members.sort_by_key(|member| member.id);
+            }
+        }
+
+        /// Orders groups by their smallest member id (empty groups last), so that solutions that
+        /// only differ by group order produce the same assignment.
+        pub fn canonicalize(&mut self) {
+            let mut order: Vec<Index> = (0..self.groups.len()).collect();
+            order.sort_by_key(|group_index| {
+                self.groups[*group_index].members.iter().map(|member| member.id).min().unwrap_or(Id::MAX)
+            });
+            let mut groups: Vec<Option<Group>> = self.groups.drain(..).map(Some).collect();
+            self.groups = order.iter().map(|group_index| groups[*group_index].take().unwrap()).collect();
+            if let Some(labels) = self.labels.as_mut() {
+                let mut old: Vec<Option<String>> = labels.drain(..).map(Some).collect();
+                *labels = order.iter().filter_map(|group_index| old.get_mut(*group_index)?.take()).collect();
+            }
+        }
+    }
+}
+
+pub mod roster {
+    use alloc::{string::String, vec::Vec};
+    use crate::collections::HashMap;
+    use crate::action::ActionError;
+    use super::entity::{Id, Tag, Member};
+
+    /// The members of an event with their names, handing out ids so that they never collide.
+    /// `members` is the list the solver entry points take.
+    #[derive(Debug, Clone, PartialEq, Default)]
+    pub struct Roster {
+        members: Vec<Member>,
+        /// Indexed like `members`.
+        names: Vec<String>,
+        indices: HashMap<Id, usize>,
+        next_id: Id,
+    }
+
+    impl Roster {
+        pub fn new() -> Roster {
+            Roster::default()
+        }
+
+        /// Adds a member with a fresh id, one above every id the roster has held, and returns it.
+        /// Once a member holds `Id::MAX`, the smallest free id is used instead.
+        pub fn add(&mut self, name: impl Into<String>, tags: impl IntoIterator<Item = impl Into<Tag>>) -> Id {
+            let id = match self.indices.contains_key(&self.next_id) {
+                false => self.next_id,
+                // A roster holding every id would not fit in memory.
+                true => (0..Id::MAX).find(|id| !self.indices.contains_key(id)).unwrap_or(Id::MAX),
+            };
+            self.push(name.into(), Member::with_tags(id, tags));
+            id
+        }
+
+        /// Adds `member` under `name`, keeping its id, unless a member already has that id.
+        pub fn insert(&mut self, name: impl Into<String>, member: Member) -> Result<(), ActionError> {
+            if self.indices.contains_key(&member.id) {
+                return Err(ActionError::DuplicateId(member.id));
+            }
+            self.push(name.into(), member);
+            Ok(())
+        }
+
+        fn push(&mut self, name: String, member: Member) {
+            self.next_id = self.next_id.max(member.id.saturating_add(1));
+            self.indices.insert(member.id, self.members.len());
+            self.members.push(member);
+            self.names.push(name);
+        }
+
+        pub fn get(&self, id: Id) -> Option<&Member> {
+            Some(&self.members[*self.indices.get(&id)?])
+        }
+
+        /// The first member added under `name`.
+        pub fn by_name(&self, name: &str) -> Option<&Member> {
+            let index = self.names.iter().position(|other| other == name)?;
+            Some(&self.members[index])
+        }
+
+        pub fn name(&self, id: Id) -> Option<&str> {
+            Some(&self.names[*self.indices.get(&id)?])
+        }
+
+        /// Every member, in the order they were added.
+        pub fn members(&self) -> &[Member] {
+            &self.members
+        }
+
+        pub fn len(&self) -> usize {
+            self.members.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.members.is_empty()
+        }
     }
 }
 
 pub mod condition {
-    use std::collections::{HashMap, BTreeSet};
-    use super::entity::{Id, Tag};
+    use alloc::{boxed::Box, vec::Vec};
+    use crate::collections::{HashMap, HashSet, BTreeSet};
+    use crate::float;
+    use itertools::Itertools;
+    use super::entity::{Id, Tag, Member};
+    use super::group::Table;
 
     pub type Score = f64;
 
+    #[derive(Debug, Clone, PartialEq, Default)]
     pub struct RelationPenalty {
         pub scores: HashMap<BTreeSet<Id>, Score>,
         pub default: f64,
+        /// Directed scores keyed by `(from, to)`. When set, the penalty of a pair is the sum of
+        /// both directions and `scores` is ignored.
+        pub directed: Option<HashMap<(Id, Id), Score>>,
+        /// Upper bound on the penalty of any single pair, so that one outlier relationship
+        /// cannot dominate the objective. Applied after summing both directions.
+        pub pair_cap: Option<Score>,
+        /// Penalty of an unlisted pair whose members share the tag, used before `default`
+        /// (see `get_pair_for`). When the members share several such tags, the smallest tag wins.
+        pub tag_defaults: HashMap<Tag, Score>,
     }
 
     impl RelationPenalty {
@@ -39,22 +395,1052 @@ pub mod condition {
             RelationPenalty {
                 scores: HashMap::new(),
                 default,
+                directed: None,
+                pair_cap: None,
+                tag_defaults: HashMap::new(),
             }
         }
+
+        /// Builds a symmetric penalty from `(a, b, score)` edges, summing the scores of duplicate
+        /// edges (see `from_edges_with` for other policies). Self-loops are skipped.
+        pub fn from_edges(edges: impl IntoIterator<Item = (Id, Id, Score)>, default: Score) -> RelationPenalty {
+            RelationPenalty::from_edges_with(edges, default, DuplicateEdges::default())
+        }
+
+        /// Like `from_edges`, with `duplicates` deciding how repeated edges (in either order) combine.
+        pub fn from_edges_with(edges: impl IntoIterator<Item = (Id, Id, Score)>, default: Score, duplicates: DuplicateEdges) -> RelationPenalty {
+            RelationPenalty::from_edges_with_report(edges, default, duplicates).0
+        }
+
+        /// Like `from_edges_with`, and also reports the skipped self-loops and combined duplicates.
+        pub fn from_edges_with_report(
+            edges: impl IntoIterator<Item = (Id, Id, Score)>, default: Score, duplicates: DuplicateEdges,
+        ) -> (RelationPenalty, EdgeReport) {
+            let mut penalty = RelationPenalty::new(default);
+            let mut report = EdgeReport::default();
+            for (a, b, score) in edges {
+                if a == b {
+                    report.self_loops.push(a);
+                    continue;
+                }
+                penalty.scores.entry(BTreeSet::from([a, b]))
+                    .and_modify(|previous| {
+                        report.duplicates += 1;
+                        *previous = match duplicates {
+                            DuplicateEdges::Sum => *previous + score,
+                            DuplicateEdges::Max => previous.max(score),
+                            DuplicateEdges::Last => score,
+                        };
+                    })
+                    .or_insert(score);
+            }
+            (penalty, report)
+        }
+
+        pub fn new_directed(default: Score) -> RelationPenalty {
+            RelationPenalty {
+                directed: Some(HashMap::new()),
+                ..RelationPenalty::new(default)
+            }
+        }
+
         pub fn get_pair(&self, ids: [Id; 2]) -> Score {
-            self.scores.get(&BTreeSet::from(ids)).copied().unwrap_or(self.default)
+            let score = self.get_uncapped(ids);
+            match self.pair_cap {
+                Some(cap) => score.min(cap),
+                None => score,
+            }
+        }
+
+        /// Like `get_pair`, but an unlisted pair of members sharing a tag of `tag_defaults` scores
+        /// that tag's default instead of `default`.
+        pub fn get_pair_for(&self, a: &Member, b: &Member) -> Score {
+            match self.tag_default(a, b) {
+                Some(score) => self.pair_cap.map_or(score, |cap| score.min(cap)),
+                None => self.get_pair([a.id, b.id]),
+            }
+        }
+
+        /// Uncapped tag default of an unlisted pair of members sharing a tag of `tag_defaults`.
+        fn tag_default(&self, a: &Member, b: &Member) -> Option<Score> {
+            if self.tag_defaults.is_empty() || self.contains_pair(a.id, b.id) {
+                return None;
+            }
+            self.tag_defaults.iter()
+                .filter(|(tag, _)| a.has_tag(tag) && b.has_tag(tag))
+                .min_by(|(tag1, _), (tag2, _)| tag1.cmp(tag2))
+                .map(|(_, score)| *score)
+        }
+
+        /// Combines weighted penalty sources into one: every pair scores the weighted sum of its
+        /// `get_pair` in each source, so a pair missing from a source contributes that source's
+        /// default. The merged default is likewise the weighted sum of the sources' pair defaults
+        /// (twice `default` for directed sources, since both directions count).
+        /// Caps are applied per source; the result is symmetric and has no cap of its own.
+        /// Tag defaults merge the same way, with sources lacking a tag contributing their pair
+        /// default; they only apply to pairs that no source lists.
+        pub fn merge(sources: &[(RelationPenalty, Score)]) -> RelationPenalty {
+            let pairs: BTreeSet<BTreeSet<Id>> = sources.iter().flat_map(|(source, _)| {
+                let directed_pairs = source.directed.iter()
+                    .flat_map(|directed| directed.keys())
+                    .filter(|(from, to)| from != to)
+                    .map(|(from, to)| BTreeSet::from([*from, *to]));
+                source.scores.keys().cloned().chain(directed_pairs)
+            }).collect();
+            let weighted_sum = |score: &dyn Fn(&RelationPenalty) -> Score| -> Score {
+                sources.iter().map(|(source, weight)| weight * score(source)).sum()
+            };
+            let scores = pairs.into_iter().map(|pair| {
+                let ids: Vec<Id> = pair.iter().copied().collect();
+                let score = weighted_sum(&|source| source.get_pair([ids[0], ids[1]]));
+                (pair, score)
+            }).collect();
+            let tags: BTreeSet<&Tag> = sources.iter().flat_map(|(source, _)| source.tag_defaults.keys()).collect();
+            let tag_defaults = tags.into_iter().map(|tag| {
+                let score = weighted_sum(&|source| source.tag_defaults.get(tag).map_or_else(|| source.default_pair(), |score| {
+                    source.pair_cap.map_or(*score, |cap| score.min(cap))
+                }));
+                (tag.clone(), score)
+            }).collect();
+            RelationPenalty {
+                scores,
+                default: weighted_sum(&|source| source.default_pair()),
+                tag_defaults,
+                ..Default::default()
+            }
+        }
+
+        /// Sets the score of the pair, overwriting any earlier score, and returns the previous one.
+        /// In directed mode this sets the direction from `a` to `b` only.
+        pub fn add_pair(&mut self, a: Id, b: Id, score: Score) -> Option<Score> {
+            match self.directed.as_mut() {
+                Some(directed) => directed.insert((a, b), score),
+                None => self.scores.insert(BTreeSet::from([a, b]), score),
+            }
+        }
+
+        /// Forgets the score of the pair, so that `get_pair` falls back to `default` (or a tag
+        /// default), and returns the removed uncapped score. In directed mode both directions are
+        /// removed and their sum is returned.
+        pub fn remove_pair(&mut self, a: Id, b: Id) -> Option<Score> {
+            if !self.contains_pair(a, b) {
+                return None;
+            }
+            let score = self.get_uncapped([a, b]);
+            match self.directed.as_mut() {
+                Some(directed) => {
+                    directed.remove(&(a, b));
+                    directed.remove(&(b, a));
+                }
+                None => {
+                    self.scores.remove(&BTreeSet::from([a, b]));
+                }
+            }
+            Some(score)
+        }
+
+        /// Whether the pair has an explicit score, in either direction in directed mode.
+        pub fn contains_pair(&self, a: Id, b: Id) -> bool {
+            match &self.directed {
+                Some(directed) => directed.contains_key(&(a, b)) || directed.contains_key(&(b, a)),
+                None => self.scores.contains_key(&BTreeSet::from([a, b])),
+            }
+        }
+
+        /// Every pair with an explicit score as `[smaller id, larger id]`, in ascending order,
+        /// with its uncapped score (both directions summed in directed mode).
+        pub fn iter_pairs(&self) -> impl Iterator<Item = ([Id; 2], Score)> + '_ {
+            let pairs: BTreeSet<[Id; 2]> = match &self.directed {
+                Some(directed) => directed.keys()
+                    .filter(|(from, to)| from != to)
+                    .map(|(from, to)| [*from.min(to), *from.max(to)])
+                    .collect(),
+                None => self.scores.keys()
+                    .map(|pair| {
+                        let ids: Vec<Id> = pair.iter().copied().collect();
+                        [ids[0], *ids.last().unwrap()]
+                    })
+                    .collect(),
+            };
+            pairs.into_iter().map(|ids| (ids, self.get_uncapped(ids)))
+        }
+
+        /// Penalty of a pair not listed in the penalty.
+        fn default_pair(&self) -> Score {
+            let score = if self.directed.is_some() { 2.0 * self.default } else { self.default };
+            self.pair_cap.map_or(score, |cap| score.min(cap))
+        }
+
+        fn get_uncapped(&self, ids: [Id; 2]) -> Score {
+            match &self.directed {
+                Some(_) => self.get_directed(ids[0], ids[1]) + self.get_directed(ids[1], ids[0]),
+                None => self.scores.get(&BTreeSet::from(ids)).copied().unwrap_or(self.default),
+            }
+        }
+
+        /// Score of `from` towards `to`. Unlisted directions, and every direction in symmetric
+        /// mode, fall back to `default`.
+        pub fn get_directed(&self, from: Id, to: Id) -> Score {
+            self.directed.as_ref()
+                .and_then(|directed| directed.get(&(from, to)).copied())
+                .unwrap_or(self.default)
+        }
+
+        /// Pairs whose two directions score differently, as `(a, b, score of a towards b, score of
+        /// b towards a)` with `a < b`, in ascending order. Unlisted directions count as `default`.
+        /// Always empty in symmetric mode, where both directions are the same score.
+        pub fn symmetric_check(&self) -> Vec<(Id, Id, Score, Score)> {
+            let Some(directed) = &self.directed else {
+                return Vec::new();
+            };
+            let pairs: BTreeSet<[Id; 2]> = directed.keys()
+                .filter(|(from, to)| from != to)
+                .map(|(from, to)| [*from.min(to), *from.max(to)])
+                .collect();
+            pairs.into_iter()
+                .map(|[a, b]| (a, b, self.get_directed(a, b), self.get_directed(b, a)))
+                .filter(|(_, _, forward, backward)| forward != backward)
+                .collect()
+        }
+
+        /// Gives both directions of every pair reported by `symmetric_check` the score chosen by
+        /// `policy`, so that afterwards the check is empty. The penalty stays directed.
+        pub fn symmetrize(&mut self, policy: AsymmetryPolicy) {
+            let asymmetric = self.symmetric_check();
+            let Some(directed) = self.directed.as_mut() else {
+                return;
+            };
+            for (a, b, forward, backward) in asymmetric {
+                let score = match policy {
+                    AsymmetryPolicy::Mean => (forward + backward) / 2.0,
+                    AsymmetryPolicy::Max => forward.max(backward),
+                    AsymmetryPolicy::Min => forward.min(backward),
+                };
+                directed.insert((a, b), score);
+                directed.insert((b, a), score);
+            }
+        }
+
+        /// Adds `per_repeat` to every pair that shares a group in `table`, so that a later
+        /// shuffle with this penalty avoids repeating the partners of a completed round.
+        /// In directed mode each direction receives half of `per_repeat`. An unlisted pair starts
+        /// from its tag default (see `get_pair_for`), split evenly between directions.
+        pub fn accumulate(&mut self, table: &Table, per_repeat: Score) {
+            for group in &table.groups {
+                for pair in group.members.iter().combinations(2) {
+                    let tag_default = self.tag_default(pair[0], pair[1]);
+                    let (a, b) = (pair[0].id, pair[1].id);
+                    let forward = tag_default.map_or_else(|| self.get_directed(a, b), |score| score / 2.0) + per_repeat / 2.0;
+                    let backward = tag_default.map_or_else(|| self.get_directed(b, a), |score| score / 2.0) + per_repeat / 2.0;
+                    let score = tag_default.unwrap_or_else(|| self.get_uncapped([a, b])) + per_repeat;
+                    match self.directed.as_mut() {
+                        Some(directed) => {
+                            directed.insert((a, b), forward);
+                            directed.insert((b, a), backward);
+                        }
+                        None => {
+                            self.scores.insert(BTreeSet::from([a, b]), score);
+                        }
+                    }
+                }
+            }
         }
     }
 
 
+    #[derive(Debug, Clone, PartialEq)]
     pub enum Range {
         Ratio {min: f64, max: f64},
         Count {min: usize, max: usize},
+        Exact(usize),
+        AtLeast(usize),
+        AtMost(usize),
+        /// Between `min_ratio` and `max_ratio` times the number of members with `tag` in the same
+        /// group, e.g. at most twice as many juniors as seniors. Only tag constraints know that
+        /// count (see `relative_bounds`); elsewhere the range only bounds counts by the group size.
+        RelativeTo { tag: Tag, min_ratio: f64, max_ratio: f64 },
     }
+
+    /// Slack used when rounding ratio thresholds, so that e.g. `0.1 * 30` counts as exactly 3.
+    const RATIO_EPSILON: f64 = 1e-9;
+
+    impl Range {
+        /// Inclusive `(min, max)` tag counts allowed in a group of `n_members`.
+        ///
+        /// Ratio thresholds are rounded inwards: the minimum is rounded up and the maximum down,
+        /// so `Ratio { min: 0.3, max: 0.5 }` on 3 members allows exactly 1. A ratio is undefined for
+        /// an empty group, which therefore only satisfies ratio ranges with `min == 0`.
+        pub fn count_bounds(&self, n_members: usize) -> (usize, usize) {
+            match self {
+                Range::Ratio {min, max} => {
+                    if n_members == 0 {
+                        return if *min > 0.0 { (1, 0) } else { (0, 0) };
+                    }
+                    let n = n_members as f64;
+                    let lower = float::ceil(min * n - RATIO_EPSILON).max(0.0) as usize;
+                    let upper = float::floor(max * n + RATIO_EPSILON).max(0.0) as usize;
+                    (lower, upper.min(n_members))
+                },
+                Range::Count {min, max} => (*min, *max),
+                Range::Exact(exact) => (*exact, *exact),
+                Range::AtLeast(min) => (*min, usize::MAX),
+                Range::AtMost(max) => (0, *max),
+                Range::RelativeTo { .. } => (0, n_members),
+            }
+        }
+
+        /// Like `count_bounds`, with `reference` the number of members holding the tag of a
+        /// `RelativeTo` range. Its thresholds are multiples of `reference`, rounded inwards like
+        /// ratios, so no division is involved: a group without any reference member only allows
+        /// a count of 0, unless `max_ratio` is infinite.
+        pub fn relative_bounds(&self, n_members: usize, reference: usize) -> (usize, usize) {
+            match self {
+                Range::RelativeTo { min_ratio, max_ratio, .. } => {
+                    let reference = reference as f64;
+                    let lower = float::ceil(min_ratio * reference - RATIO_EPSILON).max(0.0) as usize;
+                    let upper = if max_ratio.is_infinite() {
+                        n_members
+                    } else {
+                        float::floor(max_ratio * reference + RATIO_EPSILON).max(0.0) as usize
+                    };
+                    (lower, upper.min(n_members))
+                }
+                range => range.count_bounds(n_members),
+            }
+        }
+
+        pub fn contains(&self, count: usize, n_members: usize) -> bool {
+            let (min, max) = self.count_bounds(n_members);
+            min <= count && count <= max
+        }
+
+        /// How far `count` lies from the nearest bound of the range; 0 inside it.
+        pub fn distance(&self, count: usize, n_members: usize) -> usize {
+            let (min, max) = self.count_bounds(n_members);
+            min.saturating_sub(count).max(count.saturating_sub(max))
+        }
+    }
+
+    /// Required range of the count of each tag in every group.
+    ///
+    /// Empty groups are checked like any other: they violate every range that does not allow a
+    /// count of 0, and every ratio range with `min > 0` (an empty group has no ratio to meet it).
+    #[derive(Debug, Clone, PartialEq, Default)]
     pub struct Constraint (pub HashMap<Tag, Range>);
 
+    /// Predicate over the tags of one member, for rules on compound groups of people such as
+    /// "at least one senior who is also bilingual" (see `Group::check_expr`).
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum ConstraintExpr {
+        Tag(Tag),
+        And(Box<ConstraintExpr>, Box<ConstraintExpr>),
+        Or(Box<ConstraintExpr>, Box<ConstraintExpr>),
+    }
+
+    impl ConstraintExpr {
+        pub fn matches(&self, member: &Member) -> bool {
+            match self {
+                ConstraintExpr::Tag(tag) => member.has_tag(tag),
+                ConstraintExpr::And(left, right) => left.matches(member) && right.matches(member),
+                ConstraintExpr::Or(left, right) => left.matches(member) || right.matches(member),
+            }
+        }
+    }
+
+    impl Constraint {
+        /// Whether no tag has a required range, so every group satisfies the constraint.
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+    }
+
+    // Conditions holding the same `seat_weight` may compare unequal, as function pointers are
+    // not guaranteed to be unique.
+    #[allow(unpredictable_function_pointer_comparisons)]
+    #[derive(Debug, Clone, PartialEq, Default)]
     pub struct Condition {
         pub penalty: RelationPenalty,
         pub constraint: Constraint,
+        /// Reward per distinct tag present in a group; subtracted from the group's effective score.
+        pub diversity_bonus: Score,
+        /// Allowed number of members of each group, indexed by group. Ranges are evaluated with
+        /// the group size as both count and total, and groups past the end are unbounded.
+        pub capacities: Vec<Range>,
+        /// Penalty per pair of members sharing a tag: `k` members with the tag in one group add
+        /// `score * k * (k - 1) / 2` to the group's effective score.
+        pub tag_cooccurrence: HashMap<Tag, Score>,
+        /// Weight of the size-balance objective: every group adds `size_balance * size²` to the
+        /// effective score, which for a fixed number of members is smallest when sizes are equal.
+        pub size_balance: Score,
+        /// Cost of every optional member left unassigned; keep it high enough that dropping a
+        /// member is a last resort.
+        pub unassigned_penalty: Score,
+        /// Cost of every member whose group index differs from `previous`, to keep a
+        /// re-optimized table close to an earlier one.
+        pub churn_penalty: Score,
+        /// Group index of every member in the earlier table (see `set_previous`). Members
+        /// missing from it never count as moved.
+        pub previous: HashMap<Id, usize>,
+        /// Cost of a tag outside its range in `constraint`, counted per group as set by
+        /// `violation_scaling`. 0 keeps constraints out of the score; they then only decide
+        /// feasibility.
+        pub constraint_penalty: Score,
+        pub violation_scaling: ViolationScaling,
+        /// Ranges checked like `constraint` whose violations only cost `constraint_penalty` and
+        /// never decide feasibility. `anneal_staged` keeps the tags of later stages here.
+        pub soft_constraint: Constraint,
+        /// Most members holding each tag across the whole table. `validate` reports rosters whose
+        /// required members exceed a cap, and adding a member that would exceed one fails.
+        pub global_tag_cap: HashMap<Tag, usize>,
+        /// Allowed number of groups holding at least one member with each tag, evaluated over
+        /// the whole table with the number of groups as total. Violations count towards
+        /// feasibility and cost `constraint_penalty` like tag ranges.
+        pub tag_spread: HashMap<Tag, Range>,
+        pub group_score_mode: GroupScoreMode,
+        /// Makes `TableCache::simulate` and `act` report `ActionError::ScoreOverflow` instead of
+        /// producing a score that is no longer finite. `Score` is a float, so overflow would
+        /// otherwise surface as infinity or NaN. Off by default, as checking `act` costs a
+        /// full simulation.
+        pub checked_scores: bool,
+        /// Multiplier of the penalty between the members at seat indices `i` and `j` of a group
+        /// (their indices in `Group::members`), e.g. so that early arrivals matter more.
+        /// Setting it makes the order within a group affect the score, and like
+        /// `GroupScoreMode::MaxPair` it makes simulation recompute every affected group.
+        pub seat_weight: Option<fn(usize, usize) -> f64>,
+        /// Members that must stay in their current group. `TableCache::simulate` fails every
+        /// action taking one of them out with `ActionError::Pinned` before scoring it.
+        pub pinned: HashSet<Id>,
+        /// Largest pair penalty (scaled by weights, like the score) allowed between two members of
+        /// one group. A group holding a pair above it is infeasible, exactly like a violated tag
+        /// range. Checking it visits every pair of the affected groups, so it costs O(n²) per
+        /// simulated action and is skipped entirely while `None`.
+        pub max_pair_penalty: Option<Score>,
+        /// Priority of each tag in `constraint` for `anneal_staged`, 0 being the most important.
+        /// Tags missing from it have priority 0. Other annealing functions ignore it.
+        pub constraint_priorities: HashMap<Tag, u8>,
+    }
+
+    /// How `RelationPenalty::from_edges_with` combines edges listed more than once.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum DuplicateEdges {
+        /// Add up the scores, as repeated observations of the same relationship.
+        #[default]
+        Sum,
+        /// Keep the largest score.
+        Max,
+        /// Keep the score listed last.
+        Last,
+    }
+
+    /// Edges that `RelationPenalty::from_edges_with_report` could not take as listed.
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    pub struct EdgeReport {
+        /// Member of every skipped self-loop edge, in input order.
+        pub self_loops: Vec<Id>,
+        /// Number of edges combined with an earlier edge of the same pair.
+        pub duplicates: usize,
+    }
+
+    /// Score `RelationPenalty::symmetrize` gives both directions of a pair that disagree.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum AsymmetryPolicy {
+        /// The mean of both directions, which keeps the penalty of the pair.
+        #[default]
+        Mean,
+        /// The larger direction.
+        Max,
+        /// The smaller direction.
+        Min,
+    }
+
+    /// How the pair penalties of a group combine into its share of the effective score.
+    /// `TableCache::penalty_score` is the plain sum in every mode.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum GroupScoreMode {
+        /// Sum of all pairs, so larger groups accrue more penalty.
+        #[default]
+        SumPairs,
+        /// The single worst pair. Not incremental: simulating an action recomputes every
+        /// affected group from its members.
+        MaxPair,
+        /// Sum of all pairs divided by their number; groups below two members score 0.
+        MeanPair,
+    }
+
+    /// How `Condition::constraint_penalty` counts a violated tag.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum ViolationScaling {
+        /// Every violated tag costs the penalty once.
+        #[default]
+        Flat,
+        /// A violated tag costs the penalty once per member it is away from its range.
+        Distance,
+    }
+
+    /// Coefficients of the three main terms of the effective score:
+    /// `penalty_weight * pair penalty + balance_weight * Σ size² - diversity_weight * Σ distinct tags`.
+    /// `apply` turns them into a condition, so annealing minimizes the combination with the usual
+    /// incremental updates.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct CompositeObjective {
+        pub penalty_weight: Score,
+        pub balance_weight: Score,
+        pub diversity_weight: Score,
+    }
+
+    impl CompositeObjective {
+        /// `condition` with its penalty scaled by `penalty_weight` (as a symmetric penalty, see
+        /// `RelationPenalty::merge`), `size_balance` set to `balance_weight` and `diversity_bonus`
+        /// set to `diversity_weight`. Every other term is kept as it is.
+        pub fn apply(&self, condition: &Condition) -> Condition {
+            Condition {
+                penalty: RelationPenalty::merge(&[(condition.penalty.clone(), self.penalty_weight)]),
+                size_balance: self.balance_weight,
+                diversity_bonus: self.diversity_weight,
+                ..condition.clone()
+            }
+        }
+    }
+
+    impl Condition {
+        pub fn builder() -> ConditionBuilder {
+            ConditionBuilder::default()
+        }
+
+        /// Mutable access to the penalty, e.g. for tuning between runs. A `TableCache` built
+        /// under the old penalty keeps its old pair scores until `TableCache::rebuild`.
+        pub fn penalty_mut(&mut self) -> &mut RelationPenalty {
+            &mut self.penalty
+        }
+
+        /// Sets the required range of `tag`, returning the previous one. Existing caches keep
+        /// valid tag counts as long as the condition already used tags; otherwise, as when the
+        /// penalty changes, call `TableCache::rebuild` before simulating under the new condition.
+        pub fn set_tag_range(&mut self, tag: impl Into<Tag>, range: Range) -> Option<Range> {
+            self.constraint.0.insert(tag.into(), range)
+        }
+
+        /// Makes `churn_penalty` measure changes relative to `table`.
+        pub fn set_previous(&mut self, table: &Table) {
+            self.previous = table.to_assignment();
+        }
+
+        /// Churn cost of `id` sitting in group `group_index`.
+        pub fn churn_cost(&self, id: Id, group_index: usize) -> Score {
+            match self.previous.get(&id) {
+                Some(previous) if *previous != group_index => self.churn_penalty,
+                _ => 0 as Score,
+            }
+        }
+
+        pub fn allows_size(&self, group_index: usize, size: usize) -> bool {
+            self.capacities.get(group_index).is_none_or(|range| range.contains(size, size))
+        }
+
+        /// Largest size group `group_index` may reach, if its capacity bounds it from above.
+        pub fn max_group_size(&self, group_index: usize) -> Option<usize> {
+            match self.capacities.get(group_index)? {
+                Range::Count {max, ..} | Range::Exact(max) | Range::AtMost(max) => Some(*max),
+                Range::Ratio {..} | Range::AtLeast(_) | Range::RelativeTo {..} => None,
+            }
+        }
+    }
+
+    /// Fluent construction of a `Condition` with a symmetric penalty.
+    #[derive(Debug, Default)]
+    pub struct ConditionBuilder {
+        condition: Condition,
+    }
+
+    impl ConditionBuilder {
+        /// Penalty of every pair not set by `add_pair`.
+        pub fn penalty(mut self, default: Score) -> ConditionBuilder {
+            self.condition.penalty.default = default;
+            self
+        }
+
+        /// Sets the penalty of a pair; a later call for the same pair overwrites it.
+        pub fn add_pair(mut self, a: Id, b: Id, score: Score) -> ConditionBuilder {
+            self.condition.penalty.add_pair(a, b, score);
+            self
+        }
+
+        pub fn tag_count(mut self, tag: impl Into<Tag>, min: usize, max: usize) -> ConditionBuilder {
+            self.condition.constraint.0.insert(tag.into(), Range::Count {min, max});
+            self
+        }
+
+        pub fn tag_ratio(mut self, tag: impl Into<Tag>, min: f64, max: f64) -> ConditionBuilder {
+            self.condition.constraint.0.insert(tag.into(), Range::Ratio {min, max});
+            self
+        }
+
+        pub fn build(self) -> Condition {
+            self.condition
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec, string::ToString};
+    use crate::collections::HashSet;
+    use super::entity::{Id, Member};
+    use super::group::{AssignmentError, Group, MemberMove, RosterMismatch, Table};
+    use super::roster::Roster;
+    use alloc::boxed::Box;
+    use super::condition::{
+        AsymmetryPolicy, CompositeObjective, Condition, Constraint, ConstraintExpr, DuplicateEdges, EdgeReport, Range, RelationPenalty, Score,
+    };
+    use crate::cache::TableCache;
+    use crate::action::ActionError;
+
+    fn member(id: Id) -> Member {
+        Member::new(id)
+    }
+
+    fn table_fixture() -> Table {
+        Table {
+            groups: vec![
+                Group { members: vec![member(0), member(3)] },
+                Group { members: vec![member(1), member(4)] },
+                Group { members: vec![member(2)] },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_member_with_tags() {
+        let mut member = Member::with_tags(1, ["a", "b"]);
+        assert_eq!(member, Member { id: 1, tags: ["a".to_string(), "b".to_string()].into(), weight: 1.0, optional: false });
+        assert!(member.has_tag("a"));
+        assert!(!member.has_tag("c"));
+        assert!(member.add_tag("c"));
+        assert!(!member.add_tag("c".to_string()));
+        assert!(member.remove_tag("a"));
+        assert!(!member.remove_tag("a"));
+        assert_eq!(member.tags, ["b".to_string(), "c".to_string()].into());
+        assert_eq!(Member::new(2), Member { id: 2, tags: Default::default(), weight: 1.0, optional: false });
+    }
+
+    #[test]
+    fn test_member_identity() {
+        let tagged = Member::with_tags(1, ["a"]);
+        let retagged = Member::with_tags(1, ["b"]);
+        assert_ne!(tagged, retagged);
+        assert_eq!(tagged.id_key(), retagged.id_key());
+        let ids: HashSet<Id> = [&tagged, &retagged, &member(2)].iter().map(|member| member.id_key()).collect();
+        assert_eq!(ids, [1, 2].into_iter().collect());
+    }
+
+    #[test]
+    fn test_from_assignment_round_trip() {
+        let table = table_fixture();
+        let (members, assignment): (Vec<Member>, Vec<usize>) = table.groups.iter().enumerate()
+            .flat_map(|(group_index, group)| group.members.iter().map(move |member| (member.clone(), group_index)))
+            .unzip();
+        assert_eq!(Table::from_assignment(members, &assignment, 3), Ok(table));
+    }
+
+    #[test]
+    fn test_to_assignment() {
+        let assignment = table_fixture().to_assignment();
+        assert_eq!(assignment, [(0, 0), (3, 0), (1, 1), (4, 1), (2, 2)].into());
+    }
+
+    #[test]
+    fn test_group_sizes() {
+        assert_eq!(table_fixture().group_sizes(), vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let table = table_fixture();
+        let mut moved = table_fixture();
+        moved.groups[1].members.reverse();
+        assert_eq!(table.diff(&moved), Vec::new());
+        let transferred = moved.groups[0].members.remove(1);
+        moved.groups[2].members.push(transferred);
+        assert_eq!(table.diff(&moved), vec![MemberMove {
+            id: 3, from: Some(0), to: Some(2), from_name: Some("group 0".to_string()), to_name: Some("group 2".to_string()),
+        }]);
+        moved.groups[2].members.push(member(7));
+        assert_eq!(table.diff(&moved)[1], MemberMove { id: 7, from: None, to: Some(2), from_name: None, to_name: Some("group 2".to_string()) });
+
+        moved.labels = Some(vec!["VIP".to_string(), "Window".to_string(), "Bar".to_string()]);
+        assert_eq!(table.diff(&moved)[0].from_name.as_deref(), Some("group 0"));
+        assert_eq!(table.diff(&moved)[0].to_name.as_deref(), Some("Bar"));
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let mut table = table_fixture();
+        let mut permuted = table_fixture();
+        permuted.groups.rotate_left(1);
+        permuted.groups[1].members.reverse();
+        assert_ne!(table.to_assignment(), permuted.to_assignment());
+        table.canonicalize();
+        permuted.canonicalize();
+        assert_eq!(table.to_assignment(), permuted.to_assignment());
+        assert_eq!(table.to_assignment(), table_fixture().to_assignment());
+    }
+
+    #[test]
+    fn test_directed_penalty() {
+        let mut symmetric = RelationPenalty::new(0 as Score);
+        symmetric.scores.insert([0, 1].into(), 3 as Score);
+        let mut directed = RelationPenalty::new_directed(0 as Score);
+        directed.directed.as_mut().unwrap().insert((0, 1), 3 as Score);
+        assert_eq!(symmetric.get_pair([0, 1]), 3 as Score);
+        assert_eq!(directed.get_directed(0, 1), 3 as Score);
+        assert_eq!(directed.get_directed(1, 0), 0 as Score);
+        assert_eq!(directed.get_pair([1, 0]), 3 as Score);
+
+        directed.directed.as_mut().unwrap().insert((1, 0), 3 as Score);
+        assert_eq!(directed.get_pair([0, 1]), 6 as Score);
+        let table = Table { groups: vec![Group { members: vec![member(0), member(1), member(2)] }], ..Default::default() };
+        assert_eq!(TableCache::create(&table, &symmetric).penalty_score, 3 as Score);
+        assert_eq!(TableCache::create(&table, &directed).penalty_score, 6 as Score);
+    }
+
+    #[test]
+    fn test_merge_penalties() {
+        let mut history = RelationPenalty::new(1 as Score);
+        history.scores.insert([0, 1].into(), 4 as Score);
+        history.scores.insert([1, 2].into(), 2 as Score);
+        let mut dislikes = RelationPenalty::new_directed(0 as Score);
+        dislikes.directed.as_mut().unwrap().insert((1, 0), 5 as Score);
+        let merged = RelationPenalty::merge(&[(history, 2 as Score), (dislikes, 3 as Score)]);
+        assert_eq!(merged.get_pair([0, 1]), (2 * 4 + 3 * 5) as Score);
+        assert_eq!(merged.get_pair([2, 1]), (2 * 2) as Score);
+        assert_eq!(merged.get_pair([3, 4]), 2 as Score);
+        assert_eq!(merged.scores.len(), 2);
+    }
+
+    #[test]
+    fn test_tag_defaults() {
+        let mut penalty = RelationPenalty::new(1 as Score);
+        penalty.tag_defaults.insert("cohort-a".to_string(), 5 as Score);
+        penalty.scores.insert([0, 1].into(), 2 as Score);
+        let a0 = Member::with_tags(0, ["cohort-a"]);
+        let a1 = Member::with_tags(1, ["cohort-a"]);
+        let a2 = Member::with_tags(2, ["cohort-a"]);
+        let b3 = Member::with_tags(3, ["cohort-b"]);
+        assert_eq!(penalty.get_pair_for(&a0, &a1), 2 as Score);
+        assert_eq!(penalty.get_pair_for(&a0, &a2), 5 as Score);
+        assert_eq!(penalty.get_pair_for(&a2, &b3), 1 as Score);
+        penalty.pair_cap = Some(3 as Score);
+        assert_eq!(penalty.get_pair_for(&a0, &a2), 3 as Score);
+
+        let table = Table { groups: vec![Group { members: vec![a0, a2, b3] }], ..Default::default() };
+        penalty.pair_cap = None;
+        assert_eq!(TableCache::create(&table, &penalty).penalty_score, (5 + 1 + 1) as Score);
+        let merged = RelationPenalty::merge(&[(penalty, 2 as Score)]);
+        assert_eq!(merged.tag_defaults["cohort-a"], 10 as Score);
+    }
+
+    #[test]
+    fn test_accumulate_starts_from_tag_defaults() {
+        let a0 = Member::with_tags(0, ["cohort-a"]);
+        let a1 = Member::with_tags(1, ["cohort-a"]);
+        let b2 = Member::with_tags(2, ["cohort-b"]);
+        let table = Table { groups: vec![Group { members: vec![a0.clone(), a1.clone(), b2.clone()] }], ..Default::default() };
+        let mut penalty = RelationPenalty::new(0 as Score);
+        penalty.tag_defaults.insert("cohort-a".to_string(), 5 as Score);
+        assert_eq!(penalty.get_pair_for(&a0, &a1), 5 as Score);
+        penalty.accumulate(&table, 1 as Score);
+        assert_eq!(penalty.get_pair_for(&a0, &a1), 6 as Score);
+        assert_eq!(penalty.get_pair_for(&a0, &b2), 1 as Score);
+
+        let mut directed = RelationPenalty::new_directed(0 as Score);
+        directed.tag_defaults.insert("cohort-a".to_string(), 5 as Score);
+        directed.accumulate(&table, 1 as Score);
+        assert_eq!(directed.get_pair_for(&a0, &a1), 6 as Score);
+        assert_eq!(directed.get_directed(1, 0), 3 as Score);
+    }
+
+    #[test]
+    fn test_iter_pairs() {
+        let mut penalty = RelationPenalty::new(1 as Score);
+        penalty.scores.insert([5, 2].into(), 4 as Score);
+        penalty.scores.insert([0, 1].into(), 2 as Score);
+        assert!(penalty.contains_pair(2, 5));
+        assert!(!penalty.contains_pair(1, 2));
+        assert_eq!(penalty.iter_pairs().collect::<Vec<_>>(), vec![([0, 1], 2 as Score), ([2, 5], 4 as Score)]);
+
+        let mut directed = RelationPenalty::new_directed(0 as Score);
+        directed.directed.as_mut().unwrap().insert((3, 1), 5 as Score);
+        assert!(directed.contains_pair(1, 3));
+        assert_eq!(directed.iter_pairs().collect::<Vec<_>>(), vec![([1, 3], 5 as Score)]);
+    }
+
+    #[test]
+    fn test_shuffle_within_groups() {
+        use rand::{SeedableRng, rngs::SmallRng};
+        let table = Table { groups: vec![
+            Group { members: (0..8).map(member).collect() },
+            Group { members: (8..16).map(member).collect() },
+        ], ..Default::default() };
+        let mut shuffled = table.clone();
+        shuffled.shuffle_within_groups(&mut SmallRng::seed_from_u64(0));
+        assert_ne!(shuffled, table);
+        assert_eq!(shuffled.diff(&table), Vec::new());
+        let mut again = table.clone();
+        again.shuffle_within_groups(&mut SmallRng::seed_from_u64(0));
+        assert_eq!(again, shuffled);
+    }
+
+    #[test]
+    fn test_group_add_and_remove_member() {
+        let mut group = Group { members: vec![member(0), member(1), member(2)] };
+        assert_eq!(group.add_member(member(1)), Err(ActionError::DuplicateId(1)));
+        assert_eq!(group.add_member(member(3)), Ok(()));
+        assert_eq!(group.remove_member_by_id(1), Some(member(1)));
+        assert_eq!(group.remove_member_by_id(1), None);
+        assert_eq!(group.members.iter().map(|member| member.id).collect::<Vec<_>>(), vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn test_check_unique_ids() {
+        let mut table = table_fixture();
+        assert_eq!(table.check_unique_ids(), Ok(()));
+        table.groups[2].members.push(member(3));
+        table.groups[0].members.push(member(1));
+        table.groups[1].members.push(member(3));
+        let error = table.check_unique_ids().unwrap_err();
+        assert_eq!(error, AssignmentError::DuplicateIds { ids: vec![1, 3] });
+        assert_eq!(error.to_string(), "member ids [1, 3] occur more than once");
+    }
+
+    #[test]
+    fn test_from_assignment_errors() {
+        let members: Vec<Member> = (0..3).map(member).collect();
+        assert_eq!(
+            Table::from_assignment(members.clone(), &[0, 1], 2),
+            Err(AssignmentError::LengthMismatch { members: 3, assignments: 2 })
+        );
+        assert_eq!(
+            Table::from_assignment(members, &[0, 2, 1], 2),
+            Err(AssignmentError::GroupOutOfRange { member_index: 1, group_index: 2, group_count: 2 })
+        );
+    }
+
+    #[test]
+    fn test_from_edges() {
+        let edges = [(0, 1, 2 as Score), (2, 2, 5 as Score), (1, 0, 3 as Score), (1, 2, 1 as Score)];
+        let penalty = RelationPenalty::from_edges(edges, 0 as Score);
+        assert_eq!(penalty.iter_pairs().collect::<Vec<_>>(), vec![([0, 1], 5 as Score), ([1, 2], 1 as Score)]);
+        assert_eq!(penalty.get_pair([2, 2]), 0 as Score);
+        let penalty = RelationPenalty::from_edges_with(edges, 0 as Score, DuplicateEdges::Max);
+        assert_eq!(penalty.get_pair([0, 1]), 3 as Score);
+        let penalty = RelationPenalty::from_edges_with(edges, 0 as Score, DuplicateEdges::Last);
+        assert_eq!(penalty.get_pair([1, 0]), 3 as Score);
+        let (reported, report) = RelationPenalty::from_edges_with_report(edges, 0 as Score, DuplicateEdges::Last);
+        assert_eq!(reported, penalty);
+        assert_eq!(report, EdgeReport { self_loops: vec![2], duplicates: 1 });
+    }
+
+    #[test]
+    fn test_member_count() {
+        let table = table_fixture();
+        assert_eq!(table.member_count(), table.group_sizes().iter().sum::<usize>());
+        assert_eq!(table.member_count(), 5);
+        assert_eq!(table.group_count(), 3);
+        assert_eq!(table.iter_members().map(|member| member.id).collect::<Vec<_>>(), vec![0, 3, 1, 4, 2]);
+    }
+
+    #[test]
+    fn test_add_and_remove_pair() {
+        let mut penalty = RelationPenalty::new(1 as Score);
+        assert_eq!(penalty.add_pair(0, 1, 5 as Score), None);
+        assert_eq!(penalty.add_pair(1, 0, 7 as Score), Some(5 as Score));
+        assert_eq!(penalty.get_pair([0, 1]), 7 as Score);
+        assert_eq!(penalty.remove_pair(0, 1), Some(7 as Score));
+        assert_eq!(penalty.get_pair([0, 1]), 1 as Score);
+        assert_eq!(penalty.remove_pair(0, 1), None);
+
+        let mut directed = RelationPenalty::new_directed(0 as Score);
+        directed.add_pair(0, 1, 2 as Score);
+        directed.add_pair(1, 0, 3 as Score);
+        assert_eq!(directed.get_pair([0, 1]), 5 as Score);
+        assert_eq!(directed.remove_pair(1, 0), Some(5 as Score));
+        assert!(!directed.contains_pair(0, 1));
+    }
+
+    #[test]
+    fn test_check_expr() {
+        let tagged = |id, tags: &[&str]| Member::with_tags(id, tags.iter().copied());
+        let tag = |tag: &str| Box::new(ConstraintExpr::Tag(tag.to_string()));
+        let group = Group { members: vec![
+            tagged(0, &["senior"]),
+            tagged(1, &["bilingual"]),
+            tagged(2, &["senior", "bilingual"]),
+            tagged(3, &["vegan"]),
+        ] };
+
+        let senior_and_bilingual = ConstraintExpr::And(tag("senior"), tag("bilingual"));
+        assert!(group.check_expr(&senior_and_bilingual, &Range::AtLeast(1)));
+        assert!(!group.check_expr(&senior_and_bilingual, &Range::AtLeast(2)));
+
+        let vegan_or_vegetarian = ConstraintExpr::Or(tag("vegan"), tag("vegetarian"));
+        assert!(group.check_expr(&vegan_or_vegetarian, &Range::Count { min: 1, max: 2 }));
+        let mut group = group;
+        group.members[0].add_tag("vegetarian");
+        group.members[1].add_tag("vegetarian");
+        assert!(!group.check_expr(&vegan_or_vegetarian, &Range::Count { min: 1, max: 2 }));
+    }
+
+    #[test]
+    fn test_condition_builder() {
+        let condition = Condition::builder()
+            .penalty(0 as Score)
+            .add_pair(0, 1, 1 as Score)
+            .add_pair(2, 1, 2 as Score)
+            .add_pair(2, 3, 3 as Score)
+            .tag_count("a", 1, 2)
+            .tag_count("b", 1, 2)
+            .build();
+        let mut penalty = RelationPenalty::new(0 as Score);
+        penalty.scores.extend([([0, 1].into(), 1 as Score), ([1, 2].into(), 2 as Score), ([2, 3].into(), 3 as Score)]);
+        let expected = Condition {
+            penalty,
+            constraint: Constraint([
+                ("a".to_string(), Range::Count { min: 1, max: 2 }),
+                ("b".to_string(), Range::Count { min: 1, max: 2 }),
+            ].into()),
+            ..Default::default()
+        };
+        assert_eq!(condition, expected);
+        let ratio = Condition::builder().tag_ratio("a", 0.0, 0.5).build();
+        assert_eq!(ratio.constraint.0["a"], Range::Ratio { min: 0.0, max: 0.5 });
+    }
+
+    #[test]
+    fn test_set_tag_range() {
+        let table = Table { groups: vec![
+            Group { members: vec![Member::with_tags(0, ["a"]), Member::with_tags(1, ["b"])] },
+            Group { members: vec![Member::with_tags(2, ["a"]), Member::with_tags(3, ["a"])] },
+        ], ..Default::default() };
+        let mut condition = Condition { penalty: RelationPenalty::new(0 as Score), ..Default::default() };
+        let mut cache = TableCache::create(&table, &condition.penalty);
+        assert_eq!(condition.set_tag_range("a", Range::AtLeast(1)), None);
+        cache.rebuild(&table, &condition.penalty);
+        assert!(cache.is_feasible(&condition));
+
+        assert_eq!(condition.set_tag_range("a", Range::Exact(2)), Some(Range::AtLeast(1)));
+        condition.penalty_mut().add_pair(2, 3, 5 as Score);
+        cache.rebuild(&table, &condition.penalty);
+        assert!(!cache.is_group_feasible(0, &condition));
+        assert!(cache.is_group_feasible(1, &condition));
+        assert_eq!(cache.penalty_score, 5 as Score);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_labeled_table_serde_round_trip() {
+        let table = Table { labels: Some(vec!["VIP".to_string(), "Window".to_string(), "Door".to_string()]), ..table_fixture() };
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(serde_json::from_str::<Table>(&json).unwrap(), table);
+        let unlabeled: Table = serde_json::from_str(r#"{"groups": [{"members": [{"id": 1}]}]}"#).unwrap();
+        assert_eq!(unlabeled, Table { groups: vec![Group { members: vec![member(1)] }], labels: None });
+        assert!(serde_json::from_str::<Member>(r#"{"tags": ["a"]}"#).is_err());
+    }
+
+    #[test]
+    fn test_canonicalize_keeps_labels() {
+        let mut table = Table {
+            groups: vec![Group { members: vec![member(4)] }, Group { members: Vec::new() }, Group { members: vec![member(1)] }],
+            labels: Some(vec!["a".to_string(), "b".to_string(), "c".to_string()]),
+        };
+        table.canonicalize();
+        assert_eq!(table.labels, Some(vec!["c".to_string(), "a".to_string(), "b".to_string()]));
+        assert_eq!(table.groups[0].members, vec![member(1)]);
+    }
+
+    #[test]
+    fn test_composite_objective_weights() {
+        let mut condition = Condition { penalty: RelationPenalty::new(0 as Score), ..Default::default() };
+        condition.penalty.add_pair(0, 1, 10 as Score);
+        // Separates the pair at the cost of uneven sizes (3 + 1), or keeps it and balances (2 + 2).
+        let separated = Table::from_assignment((0..4).map(member).collect(), &[0, 1, 0, 0], 2).unwrap();
+        let balanced = Table::from_assignment((0..4).map(member).collect(), &[0, 0, 1, 1], 2).unwrap();
+        let prefers_separated = |objective: CompositeObjective| {
+            let condition = objective.apply(&condition);
+            let score = |table: &Table| TableCache::create(table, &condition.penalty).effective_score(&condition);
+            score(&separated) < score(&balanced)
+        };
+        assert!(prefers_separated(CompositeObjective { penalty_weight: 1.0, balance_weight: 1.0, diversity_weight: 0.0 }));
+        assert!(!prefers_separated(CompositeObjective { penalty_weight: 0.1, balance_weight: 10.0, diversity_weight: 0.0 }));
+    }
+
+    #[test]
+    fn test_symmetric_check_and_symmetrize() {
+        let mut directed = RelationPenalty::new_directed(1 as Score);
+        directed.add_pair(0, 1, 2 as Score);
+        directed.add_pair(1, 0, 2 as Score);
+        directed.add_pair(3, 2, 5 as Score);
+        assert_eq!(directed.symmetric_check(), vec![(2, 3, 1 as Score, 5 as Score)]);
+        assert_eq!(RelationPenalty::from_edges([(3, 2, 5 as Score)], 1 as Score).symmetric_check(), Vec::new());
+
+        let mut maximized = directed.clone();
+        maximized.symmetrize(AsymmetryPolicy::Max);
+        assert_eq!(maximized.symmetric_check(), Vec::new());
+        assert_eq!(maximized.get_directed(2, 3), 5 as Score);
+        assert_eq!(maximized.get_pair([0, 1]), 4 as Score);
+        directed.symmetrize(AsymmetryPolicy::Mean);
+        assert_eq!(directed.symmetric_check(), Vec::new());
+        assert_eq!(directed.get_pair([2, 3]), 6 as Score);
+    }
+
+    #[test]
+    fn test_validate_against_roster() {
+        let table = table_fixture();
+        let roster: Vec<Member> = (0..5).map(|id| Member::with_tags(id, ["retagged"])).collect();
+        assert_eq!(table.validate_against_roster(&roster), Ok(()));
+
+        let mut missing = table.clone();
+        missing.groups[1].remove_member_by_id(4);
+        assert_eq!(missing.validate_against_roster(&roster), Err(RosterMismatch { missing: vec![4], extra: Vec::new() }));
+
+        let mut extra = table;
+        extra.groups[2].members.push(member(9));
+        let error = extra.validate_against_roster(&roster[1..]).unwrap_err();
+        assert_eq!(error, RosterMismatch { missing: Vec::new(), extra: vec![0, 9] });
+        assert_eq!(error.to_string(), "table does not match the roster: missing ids [], extra ids [0, 9]");
+    }
+
+    #[test]
+    fn test_roster_allocates_unique_ids() {
+        let mut roster = Roster::new();
+        assert_eq!(roster.add("Ada", ["engineer"]), 0);
+        assert_eq!(roster.add("Grace", ["engineer", "lead"]), 1);
+        assert_eq!(roster.insert("Alan", member(5)), Ok(()));
+        assert_eq!(roster.insert("Alan again", member(1)), Err(ActionError::DuplicateId(1)));
+        assert_eq!(roster.add("Edsger", Vec::<&str>::new()), 6);
+        assert_eq!(roster.len(), 4);
+        let ids: HashSet<Id> = roster.members().iter().map(|member| member.id).collect();
+        assert_eq!(ids.len(), roster.len());
+        assert_eq!(roster.get(1).map(|member| member.has_tag("lead")), Some(true));
+        assert_eq!(roster.get(2), None);
+        assert_eq!(roster.name(5), Some("Alan"));
+
+        assert_eq!(roster.insert("Barbara", member(Id::MAX)), Ok(()));
+        assert_eq!(roster.add("Donald", ["engineer"]), 2);
+        assert_eq!(roster.add("Frances", ["lead"]), 3);
+        assert_eq!(roster.name(3), Some("Frances"));
+    }
+
+    #[test]
+    fn test_roster_by_name() {
+        let mut roster = Roster::new();
+        roster.add("Ada", ["engineer"]);
+        let grace = roster.add("Grace", ["lead"]);
+        roster.add("Grace", ["intern"]);
+        assert_eq!(roster.by_name("Grace").map(|member| member.id), Some(grace));
+        assert_eq!(roster.by_name("Alan"), None);
+        let table = Table::from_assignment(roster.members().to_vec(), &[0, 1, 0], 2).unwrap();
+        assert_eq!(table.validate_against_roster(roster.members()), Ok(()));
     }
 }
@@ -0,0 +1,27 @@
+//! Re-exports of the types and entry points most programs need.
+//!
+//! ```
+//! use group_shuffle::prelude::*;
+//!
+//! let mut penalty = RelationPenalty::new(0 as Score);
+//! penalty.scores.insert([0, 1].into(), 5 as Score);
+//! let condition = Condition { penalty, ..Default::default() };
+//! let table = Table { groups: vec![
+//!     Group { members: vec![Member::new(0), Member::new(1)] },
+//!     Group { members: vec![Member::new(2), Member::new(3)] },
+//! ], ..Default::default() };
+//! let params = Params { max_iterations: 100, ..Default::default() };
+//! let best = anneal(&table, &condition, &params).unwrap();
+//! let assignment = best.to_assignment();
+//! assert_ne!(assignment[&0], assignment[&1]);
+//! ```
+
+pub use crate::model::entity::{Id, Tag, Member};
+pub use crate::model::group::{Group, Table};
+pub use crate::model::roster::Roster;
+pub use crate::model::condition::{Score, RelationPenalty, Constraint, Range, Condition};
+pub use crate::action::{Action, Position};
+pub use crate::anneal::{Params, Annealer, anneal, anneal_cached, solve, anneal_with_restarts, anneal_staged, shuffle_rounds};
+pub use crate::assign::{greedy_assign, random_assignment, feasible_assignment};
+pub use crate::validate::validate;
+pub use crate::error::ShuffleError;
@@ -0,0 +1,157 @@
+use alloc::vec::Vec;
+
+use crate::model::group::Table;
+use crate::model::condition::{Condition, Score};
+use crate::action::{Action, Position, Index};
+use crate::cache::TableCache;
+use crate::error::ShuffleError;
+
+
+/// Moves and swaps that take a member out of a group violating its constraints.
+fn candidates(cache: &TableCache, condition: &Condition) -> Vec<Action> {
+    let sizes = cache.sizes();
+    let violating: Vec<Index> = (0..sizes.len()).filter(|index| !cache.is_group_feasible(*index, condition)).collect();
+    let mut actions = Vec::new();
+    for group_index in violating {
+        for member_index in 0..sizes[group_index] {
+            let source_position = Position { group_index, member_index };
+            for target_group in (0..sizes.len()).filter(|target| *target != group_index) {
+                actions.push(Action::Move { source_position: source_position.clone(), target_group });
+                for other_index in 0..sizes[target_group] {
+                    let other_position = Position { group_index: target_group, member_index: other_index };
+                    actions.push(Action::Swap(source_position.clone(), other_position));
+                }
+            }
+        }
+    }
+    actions
+}
+
+/// Greedily applies the move or swap that most reduces the number of violated tag constraints,
+/// preferring the lowest score delta reported by `simulate` among equally good candidates and
+/// breaking remaining ties with `Action::tie_break_key`, until the table is feasible or no
+/// candidate reduces the violations any further.
+/// The result is meant as a warm start for `anneal`. Fails if an id occurs twice in `table`.
+pub fn repair(table: &Table, condition: &Condition) -> Result<Table, ShuffleError> {
+    table.check_unique_ids()?;
+    let mut cache = TableCache::create(table, &condition.penalty);
+    let mut violations = cache.count_violations(condition);
+    while violations > 0 {
+        let mut best: Option<(usize, Score, Action)> = None;
+        for action in candidates(&cache, condition) {
+            let Some(score) = cache.simulate(&action, condition).score_delta() else { continue };
+            let remaining = cache.count_violations_after(&action, condition);
+            let improves = match &best {
+                Some((best_remaining, best_score, best_action)) => {
+                    (remaining, score) < (*best_remaining, *best_score)
+                        || ((remaining, score) == (*best_remaining, *best_score)
+                            && action.tie_break_key() < best_action.tie_break_key())
+                }
+                None => true,
+            };
+            if improves {
+                best = Some((remaining, score, action));
+            }
+        }
+        match best {
+            Some((remaining, _, action)) if remaining < violations => {
+                cache.act(action, condition)?;
+                violations = remaining;
+            }
+            _ => break,
+        }
+    }
+    Ok(cache.to_table())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::vec;
+    use crate::collections::BTreeSet;
+    use super::*;
+    use crate::model::entity::{Id, Member};
+    use crate::model::group::Group;
+    use crate::model::condition::{RelationPenalty, Constraint, Range};
+
+    fn member(id: Id, tag: &str) -> Member {
+        Member { id, tags: [tag.to_string()].into(), ..Default::default() }
+    }
+
+    fn infeasible_table_fixture() -> Table {
+        Table {
+            groups: vec![
+                Group { members: vec![member(0, "a"), member(1, "a"), member(2, "b")] },
+                Group { members: vec![member(3, "b"), member(4, "c"), member(5, "c")] },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn condition_fixture() -> Condition {
+        Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            constraint: Constraint(
+                [
+                    ("a".to_string(), Range::Count { min: 1, max: 2 }),
+                    ("b".to_string(), Range::Count { min: 1, max: 2 }),
+                    ("c".to_string(), Range::Count { min: 1, max: 2 }),
+                ].into()
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_repair_reaches_feasibility() {
+        let condition = condition_fixture();
+        let table = infeasible_table_fixture();
+        assert_eq!(TableCache::create(&table, &condition.penalty).count_violations(&condition), 2);
+        let repaired = repair(&table, &condition).unwrap();
+        let cache = TableCache::create(&repaired, &condition.penalty);
+        assert!(cache.is_feasible(&condition));
+        let ids: BTreeSet<Id> = repaired.iter_members().map(|member| member.id).collect();
+        assert_eq!(ids, (0..6).collect());
+    }
+
+    #[test]
+    fn test_repair_stops_at_local_minimum() {
+        let condition = Condition {
+            penalty: RelationPenalty::new(0 as Score),
+            constraint: Constraint([("c".to_string(), Range::Count { min: 1, max: 3 })].into()),
+            ..Default::default()
+        };
+        let table = Table {
+            groups: vec![
+                Group { members: vec![member(0, "a"), member(1, "a")] },
+                Group { members: vec![member(2, "b"), member(3, "b")] },
+            ],
+            ..Default::default()
+        };
+        let repaired = repair(&table, &condition).unwrap();
+        assert_eq!(TableCache::create(&repaired, &condition.penalty).count_violations(&condition), 2);
+        assert_eq!(repaired, table);
+    }
+
+    #[test]
+    fn test_repair_breaks_ties_deterministically() {
+        // both 1 and 2 can fix group 0 by swapping with member 3; the smaller position wins
+        let condition = Condition {
+            constraint: Constraint([("a".to_string(), Range::Count { min: 1, max: 1 })].into()),
+            ..Default::default()
+        };
+        let table = Table {
+            groups: vec![
+                Group { members: vec![member(0, "b"), member(1, "a"), member(2, "a")] },
+                Group { members: vec![member(3, "b"), member(4, "b"), member(5, "b")] },
+            ],
+            ..Default::default()
+        };
+        let repaired = repair(&table, &condition).unwrap();
+        assert_eq!(
+            repaired.groups[1].members.iter().map(|member| member.id).collect::<Vec<Id>>(),
+            vec![1, 4, 5]
+        );
+    }
+}
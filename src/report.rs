@@ -0,0 +1,68 @@
+use crate::collections::{BTreeMap, BTreeSet, HashMap};
+
+use itertools::Itertools;
+use crate::model::entity::Id;
+use crate::model::group::Table;
+
+/// How often each pair shared a group over the rounds of a multi-session event.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RepeatReport {
+    /// Number of rounds in which each pair shared a group; pairs that never met are absent.
+    pub counts: HashMap<BTreeSet<Id>, usize>,
+    /// Largest entry of `counts`, 0 without rounds.
+    pub max_repeats: usize,
+    /// Number of pairs that shared a group in more than one round.
+    pub repeated_pairs: usize,
+    /// Number of pairs by the number of rounds they shared a group.
+    pub distribution: BTreeMap<usize, usize>,
+}
+
+/// Counts how often each pair of members shared a group in `rounds`, e.g. the output of
+/// `shuffle_rounds`, to check that partners rarely repeat.
+pub fn repeat_report(rounds: &[Table]) -> RepeatReport {
+    let mut counts: HashMap<BTreeSet<Id>, usize> = HashMap::new();
+    for table in rounds {
+        for group in &table.groups {
+            for pair in group.members.iter().combinations(2) {
+                *counts.entry(BTreeSet::from([pair[0].id, pair[1].id])).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut distribution = BTreeMap::new();
+    for count in counts.values() {
+        *distribution.entry(*count).or_insert(0) += 1;
+    }
+    RepeatReport {
+        max_repeats: counts.values().copied().max().unwrap_or(0),
+        repeated_pairs: counts.values().filter(|count| **count > 1).count(),
+        distribution,
+        counts,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use super::*;
+    use crate::model::entity::Member;
+
+    fn round(assignment: &[usize]) -> Table {
+        Table::from_assignment((0..assignment.len() as Id).map(Member::new).collect(), assignment, 2).unwrap()
+    }
+
+    #[test]
+    fn test_repeat_report() {
+        // 0 and 1 meet in every round, 2 and 3 in the first two.
+        let rounds = vec![round(&[0, 0, 1, 1]), round(&[1, 1, 0, 0]), round(&[0, 0, 1, 0])];
+        let report = repeat_report(&rounds);
+        assert_eq!(report.counts[&BTreeSet::from([0, 1])], 3);
+        assert_eq!(report.counts[&BTreeSet::from([2, 3])], 2);
+        assert_eq!(report.counts[&BTreeSet::from([0, 3])], 1);
+        assert!(!report.counts.contains_key(&BTreeSet::from([0, 2])));
+        assert_eq!(report.max_repeats, 3);
+        assert_eq!(report.repeated_pairs, 2);
+        assert_eq!(report.distribution, BTreeMap::from([(1, 2), (2, 1), (3, 1)]));
+        assert_eq!(repeat_report(&[]), RepeatReport::default());
+    }
+}
@@ -0,0 +1,164 @@
+//! Seat order within a group as a secondary objective. The order of `Group::members` is the
+//! seating around a round table: every member sits next to the previous and the next one, and the
+//! last member next to the first.
+
+use alloc::vec::Vec;
+
+use rand::Rng;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::model::entity::Member;
+use crate::model::group::{Group, Table};
+use crate::model::condition::{RelationPenalty, Score};
+use crate::action::Index;
+use crate::cache::pair_score;
+
+/// Pairs of neighbouring seats, each pair once.
+fn neighbours(members: &[Member]) -> impl Iterator<Item = (&Member, &Member)> {
+    let n_pairs = match members.len() {
+        0 | 1 => 0,
+        2 => 1,
+        n => n,
+    };
+    (0..n_pairs).map(move |i| (&members[i], &members[(i + 1) % members.len()]))
+}
+
+/// Extra penalty of a group's seating: the pair penalty of every two neighbours, multiplied by
+/// `adjacency_weight`. Pairs that are not neighbours contribute nothing beyond the group score.
+pub fn adjacency_score(group: &Group, penalty: &RelationPenalty, adjacency_weight: Score) -> Score {
+    neighbours(&group.members).map(|(a, b)| pair_score(penalty, a, b)).sum::<Score>() * adjacency_weight
+}
+
+/// Group score plus the adjacency score of its seating.
+pub fn seating_score(group: &Group, penalty: &RelationPenalty, adjacency_weight: Score) -> Score {
+    group.calc_score(penalty) + adjacency_score(group, penalty, adjacency_weight)
+}
+
+/// A change of seat order within one group; the group's members stay the same.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SeatAction {
+    /// Rotates the seats `start..end` left by one.
+    Rotate { group_index: Index, start: Index, end: Index },
+    /// Reverses the seats `start..end`.
+    Reverse { group_index: Index, start: Index, end: Index },
+}
+
+impl SeatAction {
+    pub fn group_index(&self) -> Index {
+        match self {
+            SeatAction::Rotate { group_index, .. } | SeatAction::Reverse { group_index, .. } => *group_index,
+        }
+    }
+
+    /// Returns whether the action referred to an existing segment.
+    pub fn apply(&self, table: &mut Table) -> bool {
+        let (SeatAction::Rotate { group_index, start, end } | SeatAction::Reverse { group_index, start, end }) = self;
+        let Some(group) = table.groups.get_mut(*group_index) else { return false };
+        if start > end || *end > group.members.len() {
+            return false;
+        }
+        let segment = &mut group.members[*start..*end];
+        match self {
+            SeatAction::Rotate { .. } if !segment.is_empty() => segment.rotate_left(1),
+            SeatAction::Rotate { .. } => {}
+            SeatAction::Reverse { .. } => segment.reverse(),
+        }
+        true
+    }
+}
+
+/// Proposes a uniformly random rotation or reversal of a segment of at least two seats.
+pub struct SeatingGenerator {
+    rng: SmallRng,
+}
+
+impl SeatingGenerator {
+    pub fn new(rng: SmallRng) -> SeatingGenerator {
+        SeatingGenerator { rng }
+    }
+
+    pub fn next(&mut self, table: &Table) -> Option<SeatAction> {
+        let groups: Vec<Index> = (0..table.groups.len()).filter(|index| table.groups[*index].members.len() >= 3).collect();
+        if groups.is_empty() {
+            return None;
+        }
+        let group_index = groups[self.rng.gen_range(0..groups.len())];
+        let n_members = table.groups[group_index].members.len();
+        let start = self.rng.gen_range(0..n_members - 1);
+        let end = self.rng.gen_range(start + 2..=n_members);
+        Some(if self.rng.gen_bool(0.5) {
+            SeatAction::Rotate { group_index, start, end }
+        } else {
+            SeatAction::Reverse { group_index, start, end }
+        })
+    }
+}
+
+/// Improves the seating of every group of `table` without changing group membership, by
+/// applying `iterations` proposals of a `SeatingGenerator` seeded with `seed` and keeping those
+/// that do not increase the adjacency score.
+pub fn optimize_seating(table: &Table, penalty: &RelationPenalty, adjacency_weight: Score, iterations: usize, seed: u64) -> Table {
+    let mut table = table.clone();
+    let mut generator = SeatingGenerator::new(SmallRng::seed_from_u64(seed));
+    for _ in 0..iterations {
+        let Some(action) = generator.next(&table) else { break };
+        let group_index = action.group_index();
+        let before = adjacency_score(&table.groups[group_index], penalty, adjacency_weight);
+        let previous = table.groups[group_index].clone();
+        action.apply(&mut table);
+        if adjacency_score(&table.groups[group_index], penalty, adjacency_weight) > before {
+            table.groups[group_index] = previous;
+        }
+    }
+    table
+}
+
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use super::*;
+    use crate::model::entity::Id;
+
+    fn group_fixture() -> Group {
+        Group { members: (0..5).map(Member::new).collect() }
+    }
+
+    fn penalty_fixture() -> RelationPenalty {
+        let mut penalty = RelationPenalty::new(0 as Score);
+        penalty.scores.insert([0, 1].into(), 4 as Score);
+        penalty.scores.insert([1, 3].into(), 1 as Score);
+        penalty.scores.insert([2, 4].into(), 2 as Score);
+        penalty
+    }
+
+    #[test]
+    fn test_reverse_changes_only_adjacency() {
+        let penalty = penalty_fixture();
+        let mut table = Table { groups: vec![group_fixture()], ..Default::default() };
+        let before = table.groups[0].clone();
+        assert!(SeatAction::Reverse { group_index: 0, start: 1, end: 4 }.apply(&mut table));
+        let after = &table.groups[0];
+        assert_eq!(after.members.iter().map(|member| member.id).collect::<Vec<Id>>(), vec![0, 3, 2, 1, 4]);
+        assert_eq!(after.calc_score(&penalty), before.calc_score(&penalty));
+        // Neighbours before: 01 12 23 34 40, after: 03 32 21 14 40.
+        assert_eq!(adjacency_score(&before, &penalty, 2 as Score), (4 * 2) as Score);
+        assert_eq!(adjacency_score(after, &penalty, 2 as Score), 0 as Score);
+        assert_eq!(
+            seating_score(&before, &penalty, 2 as Score) - seating_score(after, &penalty, 2 as Score),
+            (4 * 2) as Score
+        );
+    }
+
+    #[test]
+    fn test_optimize_seating() {
+        let penalty = penalty_fixture();
+        let table = Table { groups: vec![group_fixture()], ..Default::default() };
+        let seated = optimize_seating(&table, &penalty, 1 as Score, 200, 0);
+        assert_eq!(adjacency_score(&seated.groups[0], &penalty, 1 as Score), 0 as Score);
+        let mut ids: Vec<Id> = seated.groups[0].members.iter().map(|member| member.id).collect();
+        ids.sort();
+        assert_eq!(ids, (0..5).collect::<Vec<Id>>());
+    }
+}
@@ -0,0 +1,139 @@
+use alloc::{vec, vec::Vec};
+
+use itertools::Itertools;
+use thiserror::Error;
+use crate::model::entity::{Member, Tag};
+use crate::model::condition::Condition;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum Infeasibility {
+    #[error("{members} members cannot be placed into 0 groups")]
+    NoGroups { members: usize },
+    #[error("no count of tag {tag} satisfies its range in a group of {group_size}")]
+    EmptyRange { tag: Tag, group_size: usize },
+    #[error("tag {tag} is held by {supply} members, but the groups need at least {required}")]
+    InsufficientSupply { tag: Tag, supply: usize, required: usize },
+    #[error("tag {tag} is held by {supply} members, but the groups allow at most {allowed}")]
+    ExcessSupply { tag: Tag, supply: usize, allowed: usize },
+    #[error("tag {tag} is held by {supply} required members, but at most {cap} may be placed")]
+    GlobalCapExceeded { tag: Tag, supply: usize, cap: usize },
+}
+
+/// Group sizes when `n_members` are spread as evenly as possible over `group_count` groups.
+fn balanced_sizes(n_members: usize, group_count: usize) -> Vec<usize> {
+    (0..group_count)
+        .map(|index| n_members / group_count + usize::from(index < n_members % group_count))
+        .collect()
+}
+
+/// Checks up front that `condition` can be met by some assignment of `members` into
+/// `group_count` groups of balanced sizes (the sizes produced by `greedy_assign` and kept by
+/// swaps). Only necessary conditions are checked: `Ok` does not guarantee a feasible table exists,
+/// but every reported infeasibility means none does. Infeasibilities of the tag ranges come
+/// first, then those of `Condition::global_tag_cap`, each ordered by tag. Caps only count
+/// required members: `greedy_assign` leaves out optional members over a cap, so it meets every cap
+/// accepted here.
+pub fn validate(members: &[Member], condition: &Condition, group_count: usize) -> Result<(), Vec<Infeasibility>> {
+    if group_count == 0 {
+        return if members.is_empty() { Ok(()) } else { Err(vec![Infeasibility::NoGroups { members: members.len() }]) };
+    }
+    let sizes = balanced_sizes(members.len(), group_count);
+    let mut infeasibilities = Vec::new();
+    for (tag, range) in condition.constraint.0.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+        let bounds: Vec<(usize, usize)> = sizes.iter().map(|size| range.count_bounds(*size)).collect();
+        if let Some((size, _)) = sizes.iter().zip(&bounds).find(|(_, (min, max))| min > max) {
+            infeasibilities.push(Infeasibility::EmptyRange { tag: tag.clone(), group_size: *size });
+            continue;
+        }
+        let supply = members.iter().filter(|member| member.has_tag(tag)).count();
+        let required = bounds.iter().map(|(min, _)| *min).sum();
+        let allowed = bounds.iter().fold(0usize, |total, (_, max)| total.saturating_add(*max));
+        if supply < required {
+            infeasibilities.push(Infeasibility::InsufficientSupply { tag: tag.clone(), supply, required });
+        } else if supply > allowed {
+            infeasibilities.push(Infeasibility::ExcessSupply { tag: tag.clone(), supply, allowed });
+        }
+    }
+    for (tag, cap) in condition.global_tag_cap.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+        let supply = members.iter().filter(|member| !member.optional && member.has_tag(tag)).count();
+        if supply > *cap {
+            infeasibilities.push(Infeasibility::GlobalCapExceeded { tag: tag.clone(), supply, cap: *cap });
+        }
+    }
+    if infeasibilities.is_empty() {
+        Ok(())
+    } else {
+        Err(infeasibilities)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use super::*;
+    use crate::model::condition::{Constraint, Range};
+
+    fn members_fixture() -> Vec<Member> {
+        ["a", "a", "b", "b", "b", "c"].iter().enumerate()
+            .map(|(id, tag)| Member::with_tags(id as u32, [*tag]))
+            .collect()
+    }
+
+    fn condition_fixture(ranges: Vec<(&str, Range)>) -> Condition {
+        Condition {
+            constraint: Constraint(ranges.into_iter().map(|(tag, range)| (tag.to_string(), range)).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_feasible() {
+        let condition = condition_fixture(vec![("a", Range::Count { min: 1, max: 1 }), ("b", Range::AtMost(2))]);
+        assert_eq!(validate(&members_fixture(), &condition, 2), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_insufficient_supply() {
+        let condition = condition_fixture(vec![("c", Range::AtLeast(1)), ("a", Range::Count { min: 0, max: 2 })]);
+        let result = validate(&members_fixture(), &condition, 3);
+        assert_eq!(result, Err(vec![Infeasibility::InsufficientSupply { tag: "c".to_string(), supply: 1, required: 3 }]));
+        assert_eq!(
+            result.unwrap_err()[0].to_string(),
+            "tag c is held by 1 members, but the groups need at least 3"
+        );
+    }
+
+    #[test]
+    fn test_validate_excess_supply_and_empty_range() {
+        let condition = condition_fixture(vec![
+            ("b", Range::AtMost(1)),
+            ("a", Range::Ratio { min: 0.4, max: 0.5 }),
+        ]);
+        assert_eq!(
+            validate(&members_fixture(), &condition, 2),
+            Err(vec![
+                Infeasibility::EmptyRange { tag: "a".to_string(), group_size: 3 },
+                Infeasibility::ExcessSupply { tag: "b".to_string(), supply: 3, allowed: 2 },
+            ])
+        );
+        assert_eq!(
+            validate(&members_fixture(), &Condition::default(), 0),
+            Err(vec![Infeasibility::NoGroups { members: 6 }])
+        );
+    }
+
+    #[test]
+    fn test_validate_global_tag_cap() {
+        let mut condition = Condition { global_tag_cap: [("b".to_string(), 2), ("a".to_string(), 2)].into(), ..Default::default() };
+        assert_eq!(
+            validate(&members_fixture(), &condition, 2),
+            Err(vec![Infeasibility::GlobalCapExceeded { tag: "b".to_string(), supply: 3, cap: 2 }])
+        );
+        let mut members = members_fixture();
+        members[2].optional = true;
+        assert_eq!(validate(&members, &condition, 2), Ok(()));
+        condition.global_tag_cap.insert("c".to_string(), 0);
+        assert_eq!(validate(&members, &condition, 2).unwrap_err().len(), 1);
+    }
+}